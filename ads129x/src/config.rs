@@ -0,0 +1,510 @@
+//! Typed, validated device configuration.
+//!
+//! [`AdsConfig`] describes the device settings in terms of features instead of register bits.
+//! It is checked for conflicting settings and rendered into [`ConfigRegisters`] by
+//! [`AdsConfig::render`].
+
+use crate::{ll, ConfigRegisters, RespirationConfig, RespirationError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InvalidConfig {
+    /// A channel measures the test signal, but the test signal generator is disabled.
+    TestSignalDisabled,
+    /// Inputs are connected to the RLD derivation, or a channel measures RLD, but the RLD
+    /// amplifier is powered down.
+    RldPoweredDown,
+    /// Lead-off detection is enabled on some inputs, but the comparators are powered down.
+    LeadOffComparatorsPoweredDown,
+    /// The clock output is enabled with the divider set up for the 2.048MHz external clock.
+    ClockOutputWithExternalClock,
+    /// The respiration settings are invalid.
+    Respiration(RespirationError),
+}
+
+/// Selects the positive and/or negative input of a channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Inputs {
+    pub positive: bool,
+    pub negative: bool,
+}
+
+impl Inputs {
+    pub const NONE: Self = Self::new(false, false);
+    pub const BOTH: Self = Self::new(true, true);
+
+    pub const fn new(positive: bool, negative: bool) -> Self {
+        Self { positive, negative }
+    }
+
+    const fn any(self) -> bool {
+        self.positive || self.negative
+    }
+}
+
+fn input(connected: bool) -> ll::Input {
+    if connected {
+        ll::Input::Connected
+    } else {
+        ll::Input::NotConnected
+    }
+}
+
+fn buffer(enabled: bool) -> ll::Buffer {
+    if enabled {
+        ll::Buffer::Enabled
+    } else {
+        ll::Buffer::PoweredDown
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelConfig<M> {
+    pub enabled: bool,
+    pub gain: ll::Gain,
+    pub mux: M,
+    /// Inputs with lead-off detection.
+    pub lead_off: Inputs,
+    pub lead_off_direction: ll::CurrentDirection,
+    /// Inputs used to derive the RLD signal.
+    pub rld: Inputs,
+}
+
+pub type Channel1Config = ChannelConfig<ll::Ch1mux>;
+pub type Channel2Config = ChannelConfig<ll::Ch2mux>;
+
+impl<M: Copy> ChannelConfig<M> {
+    pub const fn new(gain: ll::Gain, mux: M) -> Self {
+        Self {
+            enabled: true,
+            gain,
+            mux,
+            lead_off: Inputs::NONE,
+            lead_off_direction: ll::CurrentDirection::Normal,
+            rld: Inputs::NONE,
+        }
+    }
+
+    /// A powered down channel with shorted inputs.
+    pub const fn powered_down(mux: M) -> Self {
+        Self {
+            enabled: false,
+            ..Self::new(ll::Gain::X1, mux)
+        }
+    }
+
+    pub const fn with_gain(self, gain: ll::Gain) -> Self {
+        Self { gain, ..self }
+    }
+
+    pub const fn with_mux(self, mux: M) -> Self {
+        Self { mux, ..self }
+    }
+
+    pub const fn with_lead_off(self, lead_off: Inputs) -> Self {
+        Self { lead_off, ..self }
+    }
+
+    pub const fn with_rld(self, rld: Inputs) -> Self {
+        Self { rld, ..self }
+    }
+
+    fn channel(&self) -> ll::Channel {
+        if self.enabled {
+            ll::Channel::Enabled
+        } else {
+            ll::Channel::PowerDown
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeadOffConfig {
+    /// Enables the lead-off comparators.
+    pub comparators: bool,
+    pub threshold: ll::ComparatorThreshold,
+    pub current: ll::LeadOffCurrent,
+    pub frequency: ll::LeadOffFrequency,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RldConfig {
+    /// Enables the RLD amplifier.
+    pub enabled: bool,
+    pub chop: ll::ChopFrequency,
+    pub reference: ll::RldReference,
+    /// Connects the RLD output to the lead-off comparator.
+    pub lead_off_sense: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdsConfig {
+    pub data_rate: ll::DataRate,
+    pub reference: ll::ReferenceVoltage,
+    /// Modulator clock divider, must match the frequency of the clock source.
+    pub clock_divider: ll::ClockDivider,
+    /// Outputs the internal oscillator's clock on the CLK pin.
+    pub clock_output: bool,
+    pub test_signal: ll::TestSignal,
+    pub lead_off: LeadOffConfig,
+    pub rld: RldConfig,
+    pub channel1: Channel1Config,
+    pub channel2: Channel2Config,
+    pub respiration: Option<RespirationConfig>,
+}
+
+impl Default for AdsConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl AdsConfig {
+    /// Single channel ECG measurement on channel 1 at 1ksps, using the internal clock and
+    /// reference, with DC lead-off detection and RLD derived from channel 1.
+    pub const DEFAULT: Self = Self {
+        data_rate: ll::DataRate::_1ksps,
+        reference: ll::ReferenceVoltage::_2_42v,
+        clock_divider: ll::ClockDivider::External512kHz,
+        clock_output: false,
+        test_signal: ll::TestSignal::Disabled,
+        lead_off: LeadOffConfig {
+            comparators: true,
+            threshold: ll::ComparatorThreshold::_95,
+            current: ll::LeadOffCurrent::_22nA,
+            frequency: ll::LeadOffFrequency::Dc,
+        },
+        rld: RldConfig {
+            enabled: true,
+            chop: ll::ChopFrequency::Fmod2,
+            reference: ll::RldReference::MidSupply,
+            lead_off_sense: false,
+        },
+        channel1: ChannelConfig::new(ll::Gain::X1, ll::Ch1mux::Normal)
+            .with_lead_off(Inputs::BOTH)
+            .with_rld(Inputs::BOTH),
+        channel2: ChannelConfig::powered_down(ll::Ch2mux::Shorted),
+        respiration: None,
+    };
+
+    pub const fn with_data_rate(self, data_rate: ll::DataRate) -> Self {
+        Self { data_rate, ..self }
+    }
+
+    pub const fn with_reference(self, reference: ll::ReferenceVoltage) -> Self {
+        Self { reference, ..self }
+    }
+
+    pub const fn with_clock(self, clock_divider: ll::ClockDivider, clock_output: bool) -> Self {
+        Self {
+            clock_divider,
+            clock_output,
+            ..self
+        }
+    }
+
+    pub const fn with_test_signal(self, test_signal: ll::TestSignal) -> Self {
+        Self {
+            test_signal,
+            ..self
+        }
+    }
+
+    pub const fn with_lead_off(self, lead_off: LeadOffConfig) -> Self {
+        Self { lead_off, ..self }
+    }
+
+    pub const fn with_rld(self, rld: RldConfig) -> Self {
+        Self { rld, ..self }
+    }
+
+    pub const fn with_channel1(self, channel1: Channel1Config) -> Self {
+        Self { channel1, ..self }
+    }
+
+    pub const fn with_channel2(self, channel2: Channel2Config) -> Self {
+        Self { channel2, ..self }
+    }
+
+    pub const fn with_respiration(self, respiration: Option<RespirationConfig>) -> Self {
+        Self {
+            respiration,
+            ..self
+        }
+    }
+
+    /// Checks the configuration for conflicting settings.
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        let measures_test_signal = (self.channel1.enabled
+            && self.channel1.mux == ll::Ch1mux::TestSignal)
+            || (self.channel2.enabled && self.channel2.mux == ll::Ch2mux::TestSignal);
+        if measures_test_signal && self.test_signal == ll::TestSignal::Disabled {
+            return Err(InvalidConfig::TestSignalDisabled);
+        }
+
+        let uses_rld = self.channel1.rld.any()
+            || self.channel2.rld.any()
+            || self.rld.lead_off_sense
+            || (self.channel1.enabled && self.channel1.mux == ll::Ch1mux::Rld)
+            || (self.channel2.enabled && self.channel2.mux == ll::Ch2mux::Rld);
+        if uses_rld && !self.rld.enabled {
+            return Err(InvalidConfig::RldPoweredDown);
+        }
+
+        let uses_lead_off =
+            self.channel1.lead_off.any() || self.channel2.lead_off.any() || self.rld.lead_off_sense;
+        if uses_lead_off && !self.lead_off.comparators {
+            return Err(InvalidConfig::LeadOffComparatorsPoweredDown);
+        }
+
+        if self.clock_output && self.clock_divider == ll::ClockDivider::External2mhz {
+            return Err(InvalidConfig::ClockOutputWithExternalClock);
+        }
+
+        if let Some(respiration) = self.respiration {
+            respiration.validate().map_err(InvalidConfig::Respiration)?;
+        }
+
+        Ok(())
+    }
+
+    /// Validates the configuration and renders it into register values.
+    ///
+    /// GPIO settings are board specific and are left at their default value.
+    pub fn render(&self) -> Result<ConfigRegisters, InvalidConfig> {
+        self.validate()?;
+
+        let mut regs = ConfigRegisters::default();
+
+        regs.config1.set_sampling(ll::Sampling::Continuous);
+        regs.config1.set_data_rate(self.data_rate);
+
+        regs.config2
+            .set_pdb_loff_comp(buffer(self.lead_off.comparators));
+        regs.config2.set_ref_voltage(self.reference);
+        regs.config2.set_clock_pin(if self.clock_output {
+            ll::ClockPin::Enabled
+        } else {
+            ll::ClockPin::Disabled
+        });
+        regs.config2.set_test_signal(self.test_signal);
+
+        regs.loff.set_comp_th(self.lead_off.threshold);
+        regs.loff.set_leadoff_current(self.lead_off.current);
+        regs.loff.set_leadoff_frequency(self.lead_off.frequency);
+
+        regs.ch1set.set_enabled(self.channel1.channel());
+        regs.ch1set.set_gain(self.channel1.gain);
+        regs.ch1set.set_mux(self.channel1.mux);
+
+        regs.ch2set.set_enabled(self.channel2.channel());
+        regs.ch2set.set_gain(self.channel2.gain);
+        regs.ch2set.set_mux(self.channel2.mux);
+
+        regs.rldsens.set_chop(self.rld.chop);
+        regs.rldsens.set_pdb_rld(buffer(self.rld.enabled));
+        regs.rldsens.set_loff_sense(input(self.rld.lead_off_sense));
+        regs.rldsens.set_rld2n(input(self.channel2.rld.negative));
+        regs.rldsens.set_rld2p(input(self.channel2.rld.positive));
+        regs.rldsens.set_rld1n(input(self.channel1.rld.negative));
+        regs.rldsens.set_rld1p(input(self.channel1.rld.positive));
+
+        regs.loffsens.set_flip2(self.channel2.lead_off_direction);
+        regs.loffsens.set_flip1(self.channel1.lead_off_direction);
+        regs.loffsens
+            .set_loff2n(input(self.channel2.lead_off.negative));
+        regs.loffsens
+            .set_loff2p(input(self.channel2.lead_off.positive));
+        regs.loffsens
+            .set_loff1n(input(self.channel1.lead_off.negative));
+        regs.loffsens
+            .set_loff1p(input(self.channel1.lead_off.positive));
+
+        regs.loffstat.set_clk_div(self.clock_divider);
+
+        regs.resp2.set_rld_reference(self.rld.reference);
+        if let Some(respiration) = self.respiration {
+            respiration
+                .apply(&mut regs)
+                .map_err(InvalidConfig::Respiration)?;
+        }
+
+        Ok(regs)
+    }
+
+    /// Reads back the configuration from register values. Returns `None` if a register
+    /// contains a value that can't be represented.
+    pub fn from_registers(regs: &ConfigRegisters) -> Option<Self> {
+        let connected = |input: ll::Input| input == ll::Input::Connected;
+
+        Some(Self {
+            data_rate: regs.config1.data_rate().ok()?,
+            reference: regs.config2.ref_voltage().ok()?,
+            clock_divider: regs.loffstat.clk_div(),
+            clock_output: regs.config2.clock_pin() == ll::ClockPin::Enabled,
+            test_signal: regs.config2.test_signal().ok()?,
+            lead_off: LeadOffConfig {
+                comparators: regs.config2.pdb_loff_comp() == ll::Buffer::Enabled,
+                threshold: regs.loff.comp_th(),
+                current: regs.loff.leadoff_current(),
+                frequency: regs.loff.leadoff_frequency(),
+            },
+            rld: RldConfig {
+                enabled: regs.rldsens.pdb_rld() == ll::Buffer::Enabled,
+                chop: regs.rldsens.chop().ok()?,
+                reference: regs.resp2.rld_reference(),
+                lead_off_sense: connected(regs.rldsens.loff_sense()),
+            },
+            channel1: ChannelConfig {
+                enabled: regs.ch1set.enabled() == ll::Channel::Enabled,
+                gain: regs.ch1set.gain().ok()?,
+                mux: regs.ch1set.mux().ok()?,
+                lead_off: Inputs::new(
+                    connected(regs.loffsens.loff1p()),
+                    connected(regs.loffsens.loff1n()),
+                ),
+                lead_off_direction: regs.loffsens.flip1(),
+                rld: Inputs::new(
+                    connected(regs.rldsens.rld1p()),
+                    connected(regs.rldsens.rld1n()),
+                ),
+            },
+            channel2: ChannelConfig {
+                enabled: regs.ch2set.enabled() == ll::Channel::Enabled,
+                gain: regs.ch2set.gain().ok()?,
+                mux: regs.ch2set.mux().ok()?,
+                lead_off: Inputs::new(
+                    connected(regs.loffsens.loff2p()),
+                    connected(regs.loffsens.loff2n()),
+                ),
+                lead_off_direction: regs.loffsens.flip2().ok()?,
+                rld: Inputs::new(
+                    connected(regs.rldsens.rld2p()),
+                    connected(regs.rldsens.rld2n()),
+                ),
+            },
+            respiration: RespirationConfig::from_registers(regs),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(config: AdsConfig) {
+        let regs = config.render().unwrap();
+        assert_eq!(AdsConfig::from_registers(&regs), Some(config));
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        round_trip(AdsConfig::default());
+    }
+
+    #[test]
+    fn dual_channel_config_round_trips() {
+        round_trip(
+            AdsConfig::default()
+                .with_data_rate(ll::DataRate::_500sps)
+                .with_reference(ll::ReferenceVoltage::_4_033v)
+                .with_channel2(
+                    ChannelConfig::new(ll::Gain::X6, ll::Ch2mux::Normal)
+                        .with_lead_off(Inputs::BOTH)
+                        .with_rld(Inputs::new(true, false)),
+                ),
+        );
+    }
+
+    #[test]
+    fn test_signal_config_round_trips() {
+        round_trip(
+            AdsConfig::default()
+                .with_test_signal(ll::TestSignal::Ac)
+                .with_channel1(ChannelConfig::new(ll::Gain::X12, ll::Ch1mux::TestSignal))
+                .with_channel2(ChannelConfig::new(ll::Gain::X2, ll::Ch2mux::TestSignal)),
+        );
+    }
+
+    #[test]
+    fn respiration_config_round_trips() {
+        round_trip(
+            AdsConfig::default()
+                .with_channel2(ChannelConfig::new(ll::Gain::X6, ll::Ch2mux::Normal))
+                .with_respiration(Some(RespirationConfig::DEFAULT)),
+        );
+    }
+
+    #[test]
+    fn render_sets_register_fields() {
+        let regs = AdsConfig::default()
+            .with_clock(ll::ClockDivider::External2mhz, false)
+            .render()
+            .unwrap();
+
+        assert_eq!(regs.config1.data_rate().ok(), Some(ll::DataRate::_1ksps));
+        assert_eq!(regs.ch1set.gain().ok(), Some(ll::Gain::X1));
+        assert_eq!(regs.ch2set.enabled(), ll::Channel::PowerDown);
+        assert_eq!(regs.loffsens.loff1p(), ll::Input::Connected);
+        assert_eq!(regs.loffsens.loff2p(), ll::Input::NotConnected);
+        assert_eq!(regs.loffstat.clk_div(), ll::ClockDivider::External2mhz);
+    }
+
+    #[test]
+    fn test_signal_must_be_enabled() {
+        let config = AdsConfig::default()
+            .with_channel1(ChannelConfig::new(ll::Gain::X1, ll::Ch1mux::TestSignal));
+
+        assert_eq!(config.render(), Err(InvalidConfig::TestSignalDisabled));
+    }
+
+    #[test]
+    fn rld_must_be_enabled() {
+        let mut config = AdsConfig::default();
+        config.rld.enabled = false;
+
+        assert_eq!(config.render(), Err(InvalidConfig::RldPoweredDown));
+
+        config.channel1.rld = Inputs::NONE;
+        assert!(config.render().is_ok());
+    }
+
+    #[test]
+    fn lead_off_comparators_must_be_enabled() {
+        let mut config = AdsConfig::default();
+        config.lead_off.comparators = false;
+
+        assert_eq!(
+            config.render(),
+            Err(InvalidConfig::LeadOffComparatorsPoweredDown)
+        );
+    }
+
+    #[test]
+    fn clock_output_requires_internal_clock() {
+        let config = AdsConfig::default().with_clock(ll::ClockDivider::External2mhz, true);
+
+        assert_eq!(
+            config.render(),
+            Err(InvalidConfig::ClockOutputWithExternalClock)
+        );
+    }
+
+    #[test]
+    fn invalid_respiration_config_is_rejected() {
+        let config = AdsConfig::default().with_respiration(Some(RespirationConfig::new(
+            ll::RespirationFrequency::_64kHz,
+            ll::Phase::_168deg,
+        )));
+
+        assert_eq!(
+            config.render(),
+            Err(InvalidConfig::Respiration(RespirationError::InvalidPhase))
+        );
+    }
+}
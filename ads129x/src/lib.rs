@@ -1,9 +1,19 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 use embedded_hal::digital::OutputPin;
 use embedded_hal_async::spi::{ErrorType, Operation, SpiDevice as AsyncSpiDevice};
 
+mod config;
 pub mod ll;
+mod respiration;
+mod self_test;
+
+pub use config::{
+    AdsConfig, Channel1Config, Channel2Config, ChannelConfig, Inputs, InvalidConfig, LeadOffConfig,
+    RldConfig,
+};
+pub use respiration::{RespirationConfig, RespirationError};
+pub use self_test::{ChannelReport, Measurement, SelfTestReport};
 
 // t_mod = 1/128kHz
 const MIN_T_POR: u32 = 32; // >= 4096 * t_mod >= 1/32s
@@ -12,15 +22,30 @@ const MIN_RST_WAIT: u32 = 1; // >= 18 * t_mod >= 140us
 
 pub struct Ads129x<S> {
     driver: ll::Ads129X<ll::Ads129xSpiInterface<S>>,
+    external_reference: f32,
+    scales: [ChannelScale; 2],
 }
 
 impl<S> Ads129x<S> {
     pub const fn new(spi_device: S) -> Self {
         Self {
             driver: ll::Ads129X::new(ll::Ads129xSpiInterface { spi: spi_device }),
+            external_reference: ChannelScale::DEFAULT.reference,
+            scales: [ChannelScale::DEFAULT; 2],
         }
     }
 
+    /// Sets the voltage of the external reference, used when `config2` selects
+    /// [`ll::ReferenceVoltage::External`]. Takes effect on the next applied config.
+    pub fn set_external_reference(&mut self, volts: f32) {
+        self.external_reference = volts;
+    }
+
+    /// Returns the conversion parameters of the last successfully applied config.
+    pub fn channel_scales(&self) -> [ChannelScale; 2] {
+        self.scales
+    }
+
     pub fn inner_mut(&mut self) -> &mut S {
         &mut self.driver.interface().spi
     }
@@ -84,7 +109,10 @@ where
 
     pub async fn read_sample_async(&mut self) -> Result<AdsData, S::Error> {
         let data = self.driver.rdata().dispatch_async().await?;
-        Ok(AdsData { raw: data })
+        Ok(AdsData {
+            raw: data,
+            scales: self.scales,
+        })
     }
 
     pub async fn read_continuous_sample_async(&mut self) -> Result<AdsData, S::Error> {
@@ -96,6 +124,7 @@ where
             .await?;
         Ok(AdsData {
             raw: ll::RdataFieldSetOut::from(bytes),
+            scales: self.scales,
         })
     }
 
@@ -132,6 +161,15 @@ where
             return Err(AdsConfigError::ReadbackMismatch);
         }
 
+        self.scales = [
+            config
+                .ch1_scale(self.external_reference)
+                .unwrap_or(self.scales[0]),
+            config
+                .ch2_scale(self.external_reference)
+                .unwrap_or(self.scales[1]),
+        ];
+
         Ok(())
     }
 
@@ -216,10 +254,12 @@ where
 {
     ReadbackMismatch,
 
+    Invalid(InvalidConfig),
+
     Spi(S::Error),
 }
 
-// TODO: should not expose raw register types?
+/// Raw register values. Prefer building them from an [`AdsConfig`].
 
 #[derive(Copy, Clone, Default, Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -247,25 +287,114 @@ impl ConfigRegisters {
         self.gpio.set_d2(ll::PinState::Low);
         self.gpio.set_d1(ll::PinState::Low);
     }
+
+    fn reference_voltage(&self, external_reference: f32) -> Option<f32> {
+        match self.config2.ref_voltage().ok()? {
+            ll::ReferenceVoltage::External => Some(external_reference),
+            ll::ReferenceVoltage::_2_42v => Some(2.42),
+            ll::ReferenceVoltage::_4_033v => Some(4.033),
+        }
+    }
+
+    /// Returns the conversion parameters of channel 1, or `None` if the register values are
+    /// invalid.
+    pub fn ch1_scale(&self, external_reference: f32) -> Option<ChannelScale> {
+        Some(ChannelScale::new(
+            self.ch1set.gain().ok()?,
+            self.reference_voltage(external_reference)?,
+        ))
+    }
+
+    /// Returns the conversion parameters of channel 2, or `None` if the register values are
+    /// invalid.
+    pub fn ch2_scale(&self, external_reference: f32) -> Option<ChannelScale> {
+        Some(ChannelScale::new(
+            self.ch2set.gain().ok()?,
+            self.reference_voltage(external_reference)?,
+        ))
+    }
+}
+
+impl ll::Gain {
+    /// Returns the amplification factor of the PGA.
+    pub const fn factor(self) -> u8 {
+        match self {
+            ll::Gain::X1 => 1,
+            ll::Gain::X2 => 2,
+            ll::Gain::X3 => 3,
+            ll::Gain::X4 => 4,
+            ll::Gain::X6 => 6,
+            ll::Gain::X8 => 8,
+            ll::Gain::X12 => 12,
+        }
+    }
+}
+
+/// Parameters needed to convert raw ADC codes into voltages at the channel inputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelScale {
+    gain: ll::Gain,
+    reference: f32,
+}
+
+impl ChannelScale {
+    /// Unity gain with the 2.42V internal reference.
+    pub const DEFAULT: Self = Self::new(ll::Gain::X1, 2.42);
+
+    pub const fn new(gain: ll::Gain, reference: f32) -> Self {
+        Self { gain, reference }
+    }
+
+    #[inline]
+    pub fn gain(&self) -> ll::Gain {
+        self.gain
+    }
+
+    #[inline]
+    pub fn reference(&self) -> f32 {
+        self.reference
+    }
+
+    /// Input-referred voltage of one LSB.
+    #[inline]
+    pub fn volts_per_lsb(&self) -> f32 {
+        // Full scale is ±VREF / gain, represented in 24 bits.
+        self.reference / (self.gain.factor() as f32 * (1 << 23) as f32)
+    }
+}
+
+impl Default for ChannelScale {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
 }
 
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct AdsData {
     raw: ll::RdataFieldSetOut,
+    scales: [ChannelScale; 2],
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Sample {
     sample: i32,
+    scale: ChannelScale,
 }
 
 impl AdsData {
     #[inline]
     pub fn new(buffer: [u8; 9]) -> Self {
+        Self::new_scaled(buffer, [ChannelScale::DEFAULT; 2])
+    }
+
+    #[inline]
+    pub fn new_scaled(buffer: [u8; 9], scales: [ChannelScale; 2]) -> Self {
         Self {
             raw: ll::RdataFieldSetOut::from(buffer),
+            scales,
         }
     }
 
@@ -303,6 +432,7 @@ impl AdsData {
     pub fn ch1_sample(&self) -> Sample {
         Sample {
             sample: self.raw.ch1(),
+            scale: self.scales[0],
         }
     }
 
@@ -310,20 +440,119 @@ impl AdsData {
     pub fn ch2_sample(&self) -> Sample {
         Sample {
             sample: self.raw.ch2(),
+            scale: self.scales[1],
         }
     }
 }
 
 impl Sample {
-    pub const VOLTS_PER_LSB: f32 = 2.42 / (1 << 23) as f32;
+    #[inline]
+    pub const fn new(sample: i32, scale: ChannelScale) -> Self {
+        Self { sample, scale }
+    }
 
+    /// Returns the voltage at the channel inputs, in volts.
     #[inline]
     pub fn voltage(self) -> f32 {
-        (self.sample as f32) * Self::VOLTS_PER_LSB
+        (self.sample as f32) * self.scale.volts_per_lsb()
+    }
+
+    /// Returns the voltage at the channel inputs, in microvolts.
+    #[inline]
+    pub fn microvolts(self) -> f32 {
+        self.voltage() * 1_000_000.0
     }
 
     #[inline]
     pub fn raw(self) -> i32 {
         self.sample
     }
+
+    #[inline]
+    pub fn scale(self) -> ChannelScale {
+        self.scale
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL_GAINS: [ll::Gain; 7] = [
+        ll::Gain::X1,
+        ll::Gain::X2,
+        ll::Gain::X3,
+        ll::Gain::X4,
+        ll::Gain::X6,
+        ll::Gain::X8,
+        ll::Gain::X12,
+    ];
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= b.abs() * 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn full_scale_is_reference_over_gain() {
+        for gain in ALL_GAINS {
+            let scale = ChannelScale::new(gain, 2.42);
+            let full_scale = Sample::new((1 << 23) - 1, scale).voltage();
+
+            assert_close(full_scale, 2.42 / gain.factor() as f32);
+        }
+    }
+
+    #[test]
+    fn negative_full_scale_is_negative_reference_over_gain() {
+        for gain in ALL_GAINS {
+            let scale = ChannelScale::new(gain, 4.033);
+            let full_scale = Sample::new(-(1 << 23), scale).voltage();
+
+            assert_close(full_scale, -4.033 / gain.factor() as f32);
+        }
+    }
+
+    #[test]
+    fn microvolts_match_volts() {
+        for gain in ALL_GAINS {
+            let sample = Sample::new(12345, ChannelScale::new(gain, 2.42));
+
+            assert_close(sample.microvolts(), sample.voltage() * 1_000_000.0);
+        }
+    }
+
+    #[test]
+    fn scale_is_read_from_config() {
+        for gain in ALL_GAINS {
+            let mut config = ConfigRegisters::default();
+            config
+                .config2
+                .set_ref_voltage(ll::ReferenceVoltage::_4_033v);
+            config.ch1set.set_gain(gain);
+            config.ch2set.set_gain(ll::Gain::X1);
+
+            assert_eq!(config.ch1_scale(2.0), Some(ChannelScale::new(gain, 4.033)));
+            assert_eq!(
+                config.ch2_scale(2.0),
+                Some(ChannelScale::new(ll::Gain::X1, 4.033))
+            );
+
+            config
+                .config2
+                .set_ref_voltage(ll::ReferenceVoltage::External);
+            assert_eq!(config.ch1_scale(2.0), Some(ChannelScale::new(gain, 2.0)));
+        }
+    }
+
+    #[test]
+    fn data_carries_channel_scales() {
+        let scales = [
+            ChannelScale::new(ll::Gain::X6, 2.42),
+            ChannelScale::new(ll::Gain::X12, 2.42),
+        ];
+        let data = AdsData::new_scaled([0; 9], scales);
+
+        assert_eq!(data.ch1_sample().scale(), scales[0]);
+        assert_eq!(data.ch2_sample().scale(), scales[1]);
+    }
 }
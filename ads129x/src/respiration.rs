@@ -0,0 +1,135 @@
+//! Respiration (impedance pneumography) settings of the ADS1292R.
+//!
+//! When enabled, the device modulates a small excitation signal onto the channel 1 inputs and
+//! channel 1 outputs the demodulated signal, which follows the thoracic impedance.
+
+use crate::{ll, ConfigRegisters};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RespirationError {
+    /// The demodulation phase can only be set in 22.5° steps up to 157.5° at 64kHz.
+    InvalidPhase,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RespirationConfig {
+    /// Modulation frequency.
+    pub frequency: ll::RespirationFrequency,
+    /// Demodulation phase. Variant names describe the phase at 32kHz, the step size doubles
+    /// at 64kHz.
+    pub phase: ll::Phase,
+}
+
+impl Default for RespirationConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl RespirationConfig {
+    /// 32kHz modulation with 112.5° demodulation phase, as recommended by the datasheet for
+    /// internal respiration.
+    pub const DEFAULT: Self = Self::new(ll::RespirationFrequency::_32kHz, ll::Phase::_112deg);
+
+    pub const fn new(frequency: ll::RespirationFrequency, phase: ll::Phase) -> Self {
+        Self { frequency, phase }
+    }
+
+    pub fn validate(&self) -> Result<(), RespirationError> {
+        let phase_supported = match self.frequency {
+            ll::RespirationFrequency::_32kHz => true,
+            ll::RespirationFrequency::_64kHz => matches!(
+                self.phase,
+                ll::Phase::_0deg
+                    | ll::Phase::_11deg
+                    | ll::Phase::_22deg
+                    | ll::Phase::_33deg
+                    | ll::Phase::_45deg
+                    | ll::Phase::_56deg
+                    | ll::Phase::_67deg
+                    | ll::Phase::_78deg
+            ),
+        };
+
+        if phase_supported {
+            Ok(())
+        } else {
+            Err(RespirationError::InvalidPhase)
+        }
+    }
+
+    /// Enables respiration measurement on channel 1 with the internal modulation clock.
+    pub fn apply(&self, config: &mut ConfigRegisters) -> Result<(), RespirationError> {
+        self.validate()?;
+
+        config.resp1.set_demod_en(ll::Respiration::Enabled);
+        config.resp1.set_mod_en(ll::Respiration::Enabled);
+        config.resp1.set_phase(self.phase);
+        config.resp1.set_clock(ll::RespirationClock::Internal);
+
+        config.resp2.set_calibration(ll::Calibration::Disabled);
+        config.resp2.set_frequency(self.frequency);
+
+        Ok(())
+    }
+
+    /// Disables respiration measurement.
+    pub fn disable(config: &mut ConfigRegisters) {
+        config.resp1.set_demod_en(ll::Respiration::Disabled);
+        config.resp1.set_mod_en(ll::Respiration::Disabled);
+    }
+
+    /// Returns the respiration settings of `config`, or `None` if respiration is disabled.
+    pub fn from_registers(config: &ConfigRegisters) -> Option<Self> {
+        let enabled = config.resp1.demod_en() == ll::Respiration::Enabled
+            && config.resp1.mod_en() == ll::Respiration::Enabled;
+
+        enabled.then(|| Self::new(config.resp2.frequency(), config.resp1.phase()))
+    }
+}
+
+impl ll::DeviceId {
+    /// Returns whether the device has the respiration modulator and demodulator.
+    pub fn supports_respiration(self) -> bool {
+        matches!(self, ll::DeviceId::Ads1292r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn respiration_is_disabled_by_default() {
+        assert_eq!(
+            RespirationConfig::from_registers(&ConfigRegisters::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn applied_config_can_be_read_back() {
+        let mut registers = ConfigRegisters::default();
+        let config = RespirationConfig::new(ll::RespirationFrequency::_64kHz, ll::Phase::_45deg);
+
+        config.apply(&mut registers).unwrap();
+        assert_eq!(RespirationConfig::from_registers(&registers), Some(config));
+
+        RespirationConfig::disable(&mut registers);
+        assert_eq!(RespirationConfig::from_registers(&registers), None);
+    }
+
+    #[test]
+    fn large_phase_is_rejected_at_64khz() {
+        let mut registers = ConfigRegisters::default();
+        let config = RespirationConfig::new(ll::RespirationFrequency::_64kHz, ll::Phase::_112deg);
+
+        assert_eq!(
+            config.apply(&mut registers),
+            Err(RespirationError::InvalidPhase)
+        );
+        assert_eq!(RespirationConfig::from_registers(&registers), None);
+    }
+}
@@ -0,0 +1,449 @@
+//! Built-in self test.
+//!
+//! The self test uses the internal multiplexer to measure known signals: shorted inputs (offset
+//! and noise floor), the internal test signal (gain path and timing), the temperature sensor and
+//! the supply voltages. The results are checked against limits derived from the datasheet.
+
+use core::convert::Infallible;
+
+use embedded_hal_async::{digital::Wait, spi::SpiDevice as AsyncSpiDevice};
+
+use crate::{ll, Ads129x, AdsConfig, AdsConfigError, ChannelConfig};
+
+const DATA_RATE: ll::DataRate = ll::DataRate::_500sps;
+const SAMPLE_RATE: f32 = 500.0;
+
+/// Samples discarded after starting conversions, to let the digital filter settle.
+const SETTLE_SAMPLES: usize = 50;
+const NOISE_SAMPLES: usize = 500;
+/// The test signal is a ~1Hz square wave, we need a few periods.
+const TEST_SIGNAL_SAMPLES: usize = 2000;
+const MONITOR_SAMPLES: usize = 50;
+
+/// Samples used to find the signal levels before detecting edges.
+const EDGE_WARMUP_SAMPLES: usize = 600;
+
+/// A measured value and its accepted range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Measurement {
+    pub const fn new(value: f32, min: f32, max: f32) -> Self {
+        Self { value, min, max }
+    }
+
+    pub fn passed(&self) -> bool {
+        (self.min..=self.max).contains(&self.value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelReport {
+    /// Peak-to-peak noise with shorted inputs, in µV.
+    pub noise: Measurement,
+    /// Mean value with shorted inputs, in µV.
+    pub offset: Measurement,
+    /// Peak-to-peak amplitude of the test signal, in µV.
+    pub test_amplitude: Measurement,
+    /// Frequency of the test signal, in Hz. NaN if no edges were detected.
+    pub test_frequency: Measurement,
+}
+
+impl ChannelReport {
+    pub fn passed(&self) -> bool {
+        self.noise.passed()
+            && self.offset.passed()
+            && self.test_amplitude.passed()
+            && self.test_frequency.passed()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestReport {
+    pub device_id: ll::DeviceId,
+    /// Channel 2 is `None` on single channel devices.
+    pub channels: [Option<ChannelReport>; 2],
+    /// Die temperature, in °C.
+    pub temperature: Measurement,
+    /// Analog supply voltage, in V.
+    pub analog_supply: Measurement,
+    /// Digital supply voltage, in V. Measured by channel 2, so it is `None` on single channel
+    /// devices.
+    pub digital_supply: Option<Measurement>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.channels.iter().flatten().all(ChannelReport::passed)
+            && self.temperature.passed()
+            && self.analog_supply.passed()
+            && self
+                .digital_supply
+                .as_ref()
+                .map(Measurement::passed)
+                .unwrap_or(true)
+    }
+}
+
+/// Statistics of a signal, collected without buffering samples.
+#[derive(Clone, Copy, Debug)]
+struct SignalStats {
+    count: usize,
+    sum: f32,
+    min: f32,
+    max: f32,
+
+    high: bool,
+    edges: usize,
+    first_edge: usize,
+    last_edge: usize,
+}
+
+impl SignalStats {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f32::MAX,
+            max: f32::MIN,
+            high: false,
+            edges: 0,
+            first_edge: 0,
+            last_edge: 0,
+        }
+    }
+
+    fn update(&mut self, sample: f32) {
+        self.count += 1;
+        self.sum += sample;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+
+        let mid = (self.min + self.max) / 2.0;
+        if self.count <= EDGE_WARMUP_SAMPLES {
+            self.high = sample > mid;
+            return;
+        }
+
+        let hysteresis = (self.max - self.min) / 4.0;
+        if self.high {
+            if sample < mid - hysteresis {
+                self.high = false;
+            }
+        } else if sample > mid + hysteresis {
+            self.high = true;
+
+            if self.edges == 0 {
+                self.first_edge = self.count;
+            }
+            self.last_edge = self.count;
+            self.edges += 1;
+        }
+    }
+
+    fn mean(&self) -> f32 {
+        self.sum / self.count.max(1) as f32
+    }
+
+    fn peak_to_peak(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.max - self.min
+        }
+    }
+
+    /// Returns the frequency of rising edges, in Hz.
+    fn frequency(&self, sample_rate: f32) -> Option<f32> {
+        if self.edges < 2 {
+            return None;
+        }
+
+        let periods = (self.edges - 1) as f32;
+        let samples = (self.last_edge - self.first_edge) as f32;
+
+        Some(periods * sample_rate / samples)
+    }
+}
+
+/// Converts the temperature sensor output to °C.
+fn temperature_from_voltage(volts: f32) -> f32 {
+    // 145.3mV at 25°C, 490µV/°C
+    (volts * 1_000_000.0 - 145_300.0) / 490.0 + 25.0
+}
+
+impl ll::DeviceId {
+    /// Returns the number of input channels of the device.
+    pub fn channel_count(self) -> usize {
+        match self {
+            ll::DeviceId::Ads1191 | ll::DeviceId::Ads1291 => 1,
+            ll::DeviceId::Ads1192 | ll::DeviceId::Ads1292 | ll::DeviceId::Ads1292r => 2,
+        }
+    }
+}
+
+fn self_test_config(
+    dual_channel: bool,
+    ch1: ll::Ch1mux,
+    ch2: ll::Ch2mux,
+    gain: ll::Gain,
+    test_signal: ll::TestSignal,
+) -> AdsConfig {
+    let channel2 = if dual_channel {
+        ChannelConfig::new(gain, ch2)
+    } else {
+        ChannelConfig::powered_down(ll::Ch2mux::Shorted)
+    };
+
+    AdsConfig::default()
+        .with_data_rate(DATA_RATE)
+        .with_test_signal(test_signal)
+        .with_channel1(ChannelConfig::new(gain, ch1))
+        .with_channel2(channel2)
+}
+
+impl<S> Ads129x<S>
+where
+    S: AsyncSpiDevice,
+{
+    /// Runs the self test.
+    ///
+    /// The device must be powered up and out of continuous read mode. The self test leaves the
+    /// conversions stopped and the device in the last test configuration, so the measurement
+    /// configuration has to be applied again afterwards.
+    pub async fn self_test_async<D>(
+        &mut self,
+        drdy: &mut D,
+    ) -> Result<SelfTestReport, AdsConfigError<S>>
+    where
+        D: Wait<Error = Infallible>,
+    {
+        let device_id = self
+            .read_device_id_async()
+            .await
+            .map_err(AdsConfigError::Spi)?
+            .device_id()
+            .map_err(|_| AdsConfigError::ReadbackMismatch)?;
+        let dual_channel = device_id.channel_count() == 2;
+
+        let short = self
+            .self_test_measure_async(
+                drdy,
+                self_test_config(
+                    dual_channel,
+                    ll::Ch1mux::Shorted,
+                    ll::Ch2mux::Shorted,
+                    ll::Gain::X6,
+                    ll::TestSignal::Disabled,
+                ),
+                NOISE_SAMPLES,
+            )
+            .await?;
+
+        let test_signal = self
+            .self_test_measure_async(
+                drdy,
+                self_test_config(
+                    dual_channel,
+                    ll::Ch1mux::TestSignal,
+                    ll::Ch2mux::TestSignal,
+                    ll::Gain::X1,
+                    ll::TestSignal::Ac,
+                ),
+                TEST_SIGNAL_SAMPLES,
+            )
+            .await?;
+        // Test signal amplitude is ±VREF/2420
+        let expected_amplitude = 2.0 * self.scales[0].reference() / 2420.0 * 1_000_000.0;
+
+        let temperature = self
+            .self_test_measure_async(
+                drdy,
+                self_test_config(
+                    dual_channel,
+                    ll::Ch1mux::Temperature,
+                    ll::Ch2mux::QuarterDvdd,
+                    ll::Gain::X1,
+                    ll::TestSignal::Disabled,
+                ),
+                MONITOR_SAMPLES,
+            )
+            .await?;
+
+        let supply = self
+            .self_test_measure_async(
+                drdy,
+                self_test_config(
+                    dual_channel,
+                    ll::Ch1mux::HalfAvdd,
+                    ll::Ch2mux::Shorted,
+                    ll::Gain::X1,
+                    ll::TestSignal::Disabled,
+                ),
+                MONITOR_SAMPLES,
+            )
+            .await?;
+
+        let channel_report = |ch: usize| ChannelReport {
+            noise: Measurement::new(short[ch].peak_to_peak() * 1_000_000.0, 0.0, 30.0),
+            offset: Measurement::new(short[ch].mean() * 1_000_000.0, -500.0, 500.0),
+            test_amplitude: Measurement::new(
+                test_signal[ch].peak_to_peak() * 1_000_000.0,
+                expected_amplitude * 0.8,
+                expected_amplitude * 1.2,
+            ),
+            test_frequency: Measurement::new(
+                test_signal[ch].frequency(SAMPLE_RATE).unwrap_or(f32::NAN),
+                0.8,
+                1.2,
+            ),
+        };
+
+        Ok(SelfTestReport {
+            device_id,
+            channels: [
+                Some(channel_report(0)),
+                dual_channel.then(|| channel_report(1)),
+            ],
+            temperature: Measurement::new(
+                temperature_from_voltage(temperature[0].mean()),
+                -40.0,
+                85.0,
+            ),
+            analog_supply: Measurement::new(supply[0].mean() * 2.0, 2.7, 5.25),
+            digital_supply: dual_channel
+                .then(|| Measurement::new(temperature[1].mean() * 4.0, 1.7, 3.6)),
+        })
+    }
+
+    async fn self_test_measure_async<D>(
+        &mut self,
+        drdy: &mut D,
+        config: AdsConfig,
+        samples: usize,
+    ) -> Result<[SignalStats; 2], AdsConfigError<S>>
+    where
+        D: Wait<Error = Infallible>,
+    {
+        let mut registers = config.render().map_err(AdsConfigError::Invalid)?;
+        // Keep the board specific GPIO settings.
+        registers.gpio = self.read_gpio_async().await.map_err(AdsConfigError::Spi)?;
+        self.apply_config_async(registers).await?;
+
+        self.start_command_async()
+            .await
+            .map_err(AdsConfigError::Spi)?;
+
+        let mut stats = [SignalStats::new(); 2];
+        for i in 0..SETTLE_SAMPLES + samples {
+            let Ok(()) = drdy.wait_for_falling_edge().await;
+            let data = self
+                .read_sample_async()
+                .await
+                .map_err(AdsConfigError::Spi)?;
+
+            if i >= SETTLE_SAMPLES {
+                stats[0].update(data.ch1_sample().voltage());
+                stats[1].update(data.ch2_sample().voltage());
+            }
+        }
+
+        self.stop_command_async()
+            .await
+            .map_err(AdsConfigError::Spi)?;
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn square_wave(stats: &mut SignalStats, frequency: f32, amplitude: f32, samples: usize) {
+        for i in 0..samples {
+            let t = i as f32 / SAMPLE_RATE;
+            let phase = (t * frequency).fract();
+            let value = if phase < 0.5 { amplitude } else { -amplitude };
+            // Small offset and a bit of deterministic noise
+            let noise = ((i * 7919) % 13) as f32 / 13.0 * amplitude * 0.05;
+
+            stats.update(0.001 + value + noise);
+        }
+    }
+
+    #[test]
+    fn measures_square_wave() {
+        let mut stats = SignalStats::new();
+        square_wave(&mut stats, 1.0, 0.001, TEST_SIGNAL_SAMPLES);
+
+        let frequency = stats.frequency(SAMPLE_RATE).unwrap();
+        assert!((frequency - 1.0).abs() < 0.01, "{frequency}");
+
+        let amplitude = stats.peak_to_peak();
+        assert!((amplitude - 0.002).abs() < 0.0001, "{amplitude}");
+    }
+
+    #[test]
+    fn no_frequency_for_constant_signal() {
+        let mut stats = SignalStats::new();
+        for _ in 0..TEST_SIGNAL_SAMPLES {
+            stats.update(0.5);
+        }
+
+        assert_eq!(stats.frequency(SAMPLE_RATE), None);
+        assert_eq!(stats.peak_to_peak(), 0.0);
+        assert_eq!(stats.mean(), 0.5);
+    }
+
+    #[test]
+    fn temperature_conversion() {
+        assert_eq!(temperature_from_voltage(0.1453), 25.0);
+        assert!((temperature_from_voltage(0.1453 + 0.0049) - 35.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn measurement_limits() {
+        assert!(Measurement::new(1.0, 0.0, 1.0).passed());
+        assert!(!Measurement::new(1.1, 0.0, 1.0).passed());
+        assert!(!Measurement::new(f32::NAN, 0.0, 1.0).passed());
+    }
+
+    #[test]
+    fn self_test_configs_are_valid() {
+        for dual_channel in [false, true] {
+            for (ch1, ch2, test_signal) in [
+                (
+                    ll::Ch1mux::Shorted,
+                    ll::Ch2mux::Shorted,
+                    ll::TestSignal::Disabled,
+                ),
+                (
+                    ll::Ch1mux::TestSignal,
+                    ll::Ch2mux::TestSignal,
+                    ll::TestSignal::Ac,
+                ),
+                (
+                    ll::Ch1mux::Temperature,
+                    ll::Ch2mux::QuarterDvdd,
+                    ll::TestSignal::Disabled,
+                ),
+                (
+                    ll::Ch1mux::HalfAvdd,
+                    ll::Ch2mux::Shorted,
+                    ll::TestSignal::Disabled,
+                ),
+            ] {
+                let config = self_test_config(dual_channel, ch1, ch2, ll::Gain::X1, test_signal);
+                assert!(config.render().is_ok());
+            }
+        }
+    }
+}
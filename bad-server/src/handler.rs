@@ -107,6 +107,87 @@ impl<C: Connection> RequestHandler<C> for StaticHandler<'_> {
     }
 }
 
+/// Why a [`BodySink`] refused a request body.
+#[derive(Clone, Copy, Debug)]
+pub struct SinkError {
+    pub status: ResponseStatus,
+    pub message: &'static str,
+}
+
+impl SinkError {
+    pub const fn new(status: ResponseStatus, message: &'static str) -> Self {
+        Self { status, message }
+    }
+}
+
+/// Receives a request body piece by piece, as [`StreamingHandler`] reads it.
+pub trait BodySink {
+    /// Called before the body is read. `len` is the length of the body, if the client sent it.
+    async fn begin(&self, len: Option<usize>) -> Result<(), SinkError>;
+
+    async fn write(&self, data: &[u8]) -> Result<(), SinkError>;
+
+    /// Called after the complete body has been written.
+    async fn finish(&self) -> Result<(), SinkError>;
+
+    /// Called instead of `finish` if the body could not be received or written.
+    async fn abort(&self);
+}
+
+/// Passes the request body to a [`BodySink`] in `N` byte pieces, without buffering all of it.
+pub struct StreamingHandler<S, const N: usize> {
+    sink: S,
+}
+
+impl<S: BodySink, const N: usize> StreamingHandler<S, N> {
+    pub const fn new(sink: S) -> Self {
+        Self { sink }
+    }
+}
+
+impl<C: Connection, S: BodySink, const N: usize> RequestHandler<C> for StreamingHandler<S, N> {
+    async fn handle(&self, mut request: Request<'_, '_, C>) -> Result<(), HandleError<C>> {
+        if let Err(e) = self
+            .sink
+            .begin(request.remaining_len().map(|len| len as usize))
+            .await
+        {
+            return request.send_error_response(e.status, e.message).await;
+        }
+
+        let mut buffer = [0; N];
+        while !request.is_complete() {
+            let read = match request.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    self.sink.abort().await;
+                    return Err(e.into());
+                }
+            };
+
+            if let Err(e) = self.sink.write(&buffer[..read]).await {
+                self.sink.abort().await;
+                return request.send_error_response(e.status, e.message).await;
+            }
+        }
+
+        // Without a length or chunked encoding, the end of the body can't be told from a dropped
+        // connection.
+        if !request.is_complete() {
+            self.sink.abort().await;
+            return request
+                .send_error_response(ResponseStatus::BadRequest, "Incomplete request body")
+                .await;
+        }
+
+        match self.sink.finish().await {
+            Ok(()) => request.send_response("").await,
+            Err(e) => request.send_error_response(e.status, e.message).await,
+        }
+    }
+}
+
 pub struct RequestWithMatcher<'a, C: Connection, H: RequestHandler<C>> {
     method: Method,
     path: &'a str,
@@ -44,6 +44,11 @@ impl<'req, 's, C: Connection> Request<'req, 's, C> {
         self.body.is_complete()
     }
 
+    /// Returns the number of body bytes not read yet, if the client sent a `Content-Length`.
+    pub fn remaining_len(&self) -> Option<u32> {
+        self.body.remaining_len()
+    }
+
     pub async fn read(&mut self, buf: &mut [u8]) -> ReadResult<usize, C> {
         self.body.read(buf).await
     }
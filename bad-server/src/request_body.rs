@@ -400,6 +400,13 @@ where
         }
     }
 
+    pub fn remaining_len(&self) -> Option<u32> {
+        match self {
+            Self::ContentLength(reader) => Some(reader.length),
+            Self::Chunked(_) | Self::Unknown(_) => None,
+        }
+    }
+
     pub async fn read(&mut self, buf: &mut [u8]) -> ReadResult<usize, C> {
         match self {
             Self::Chunked(reader) => reader.read(buf).await,
@@ -10,9 +10,11 @@ use crate::{connector::Connection, HandleError};
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ResponseStatus {
     Ok = 200,
+    Found = 302,
     NotModified = 304,
     BadRequest = 400,
     NotFound = 404,
+    Conflict = 409,
     RequestEntityTooLarge = 413,
     InternalServerError = 500,
     NotImplemented = 501,
@@ -22,9 +24,11 @@ impl ResponseStatus {
     pub fn name(self) -> &'static str {
         match self {
             Self::Ok => "OK",
+            Self::Found => "Found",
             Self::NotModified => "Not Modified",
             Self::BadRequest => "Bad Request",
             Self::NotFound => "Not Found",
+            Self::Conflict => "Conflict",
             Self::RequestEntityTooLarge => "Request Entity Too Large",
             Self::InternalServerError => "Internal Server Error",
             Self::NotImplemented => "Not Implemented",
@@ -112,8 +112,11 @@ fn main() {
         .expect("Not a valid utf8 string")
         .trim();
 
+    let build_number = build_number();
+
     println!("cargo:rustc-env=COMMIT_HASH={git_hash_str}");
     println!("cargo:rustc-env=FW_VERSION={pkg_version}-{git_hash_str}");
+    println!("cargo:rustc-env=APP_VERSION={pkg_version}+{build_number}.{git_hash_str}");
 
     println!("cargo:rustc-env=MCU_MODEL={}", mcu.as_str());
     println!("cargo:rustc-env=HW_VERSION={}", build_config.as_str());
@@ -122,4 +125,51 @@ fn main() {
         mcu.as_str(),
         build_config.as_str()
     );
+
+    let public_key = firmware_public_key();
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("firmware_key.rs"), format!("{public_key:?}"))
+        .expect("Failed to write firmware key");
+}
+
+/// Reads the build number, which updates use to tell later builds from earlier ones. The release
+/// pipeline sets `BUILD_NUMBER`, other builds use the committed `build-number` file.
+fn build_number() -> u32 {
+    let (source, number) = match std::env::var("BUILD_NUMBER") {
+        Ok(number) => ("BUILD_NUMBER", number),
+        Err(_) => (
+            "build-number",
+            std::fs::read_to_string("build-number")
+                .expect("Set BUILD_NUMBER or commit a build-number file"),
+        ),
+    };
+
+    number
+        .trim()
+        .parse()
+        .unwrap_or_else(|_| panic!("{source} must contain a decimal build number"))
+}
+
+/// Reads the key that firmware updates must be signed with. Generate one with
+/// `cargo xtask generate-key`.
+fn firmware_public_key() -> Option<[u8; 32]> {
+    let path = std::env::var("FW_PUBLIC_KEY").unwrap_or_else(|_| "firmware-key.pub".to_string());
+
+    let Ok(hex) = std::fs::read_to_string(&path) else {
+        println!("cargo:warning=No firmware signing key at {path}, updates will be refused");
+        return None;
+    };
+
+    let hex = hex.trim().as_bytes();
+    let mut key = [0; 32];
+    if hex.len() != 2 * key.len() {
+        panic!("{path} must contain a hex encoded 32 byte public key");
+    }
+    for (byte, digits) in key.iter_mut().zip(hex.chunks_exact(2)) {
+        let digits = std::str::from_utf8(digits).unwrap();
+        *byte = u8::from_str_radix(digits, 16)
+            .unwrap_or_else(|_| panic!("{path} must contain a hex encoded 32 byte public key"));
+    }
+
+    Some(key)
 }
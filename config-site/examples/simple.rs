@@ -2,7 +2,7 @@
 
 use bad_server::{
     connector::{std_compat::StdTcpSocket, Connection},
-    handler::RequestHandler,
+    handler::{BodySink, RequestHandler, SinkError, StreamingHandler},
     request::Request,
     response::ResponseStatus,
     HandleError,
@@ -35,10 +35,19 @@ pub async fn run() {
     let context = SharedWebContext::new(WebContext {
         known_networks,
         backend_url: heapless::String::from("http://localhost:8080"),
+        server_ca: heapless::Vec::new(),
     });
 
     config_site::create(&context, "Example")
         .with_handler(RequestHandler::get("/vn", VisibleNetworks))
+        .with_handler(RequestHandler::post(
+            "/fw",
+            StreamingHandler::<_, 2048>::new(DiscardFirmware),
+        ))
+        .with_handler(RequestHandler::post(
+            "/fw/downgrade",
+            StreamingHandler::<_, 2048>::new(DiscardFirmware),
+        ))
         .with_request_buffer_size::<2048>()
         .with_header_count::<48>()
         .listen(&mut socket, 8080)
@@ -60,3 +69,24 @@ impl<C: Connection> RequestHandler<C> for VisibleNetworks {
         response.end_chunked_response().await
     }
 }
+
+struct DiscardFirmware;
+impl BodySink for DiscardFirmware {
+    async fn begin(&self, len: Option<usize>) -> Result<(), SinkError> {
+        println!("Receiving firmware: {len:?} bytes");
+        Ok(())
+    }
+
+    async fn write(&self, _data: &[u8]) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn finish(&self) -> Result<(), SinkError> {
+        println!("Firmware received");
+        Ok(())
+    }
+
+    async fn abort(&self) {
+        println!("Firmware upload aborted");
+    }
+}
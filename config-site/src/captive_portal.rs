@@ -0,0 +1,420 @@
+//! DHCP and DNS messages that make devices joining the configuration access point open the config
+//! site.
+//!
+//! The access point has no upstream network. Its only client gets a fixed address, and every DNS
+//! query is answered with the address of the access point. When the client probes for a captive
+//! portal, the probe reaches the config site, which redirects it to the config page. Clients that
+//! support RFC 8910 are also told the address of the portal directly.
+//!
+//! Messages that can't be answered are ignored.
+
+use core::net::Ipv4Addr;
+
+pub const AP_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 2, 1);
+pub const AP_PREFIX_LEN: u8 = 24;
+pub const PORTAL_URL: &str = "http://192.168.2.1/";
+
+/// The access point accepts a single client.
+const CLIENT_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 2, 2);
+const SUBNET_MASK: [u8; 4] = [255, 255, 255, 0];
+
+pub const DNS_PORT: u16 = 53;
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+/// Seconds.
+const DNS_TTL: u32 = 60;
+const LEASE_TIME: u32 = 3600;
+
+const DNS_HEADER_LEN: usize = 12;
+pub const DNS_MAX_LEN: usize = 512;
+
+const DHCP_OPTIONS_START: usize = 240;
+/// Some clients ignore shorter messages.
+const DHCP_MIN_LEN: usize = 300;
+pub const DHCP_MAX_LEN: usize = 576;
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+const DHCP_DISCOVER: u8 = 1;
+const DHCP_OFFER: u8 = 2;
+const DHCP_REQUEST: u8 = 3;
+const DHCP_ACK: u8 = 5;
+const DHCP_NAK: u8 = 6;
+
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS_SERVER: u8 = 6;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_CAPTIVE_PORTAL: u8 = 114;
+const OPTION_PAD: u8 = 0;
+const OPTION_END: u8 = 255;
+
+/// Answers A queries with the address of the access point, and other queries with no records.
+pub fn dns_response(query: &[u8], response: &mut [u8]) -> Option<usize> {
+    if query.len() > DNS_MAX_LEN {
+        return None;
+    }
+
+    let header = query.get(..DNS_HEADER_LEN)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    let question_count = u16::from_be_bytes([header[4], header[5]]);
+
+    // Only standard queries (QR = 0, opcode = 0) with a single question.
+    if flags & 0xF800 != 0 || question_count != 1 {
+        return None;
+    }
+
+    let mut name_end = DNS_HEADER_LEN;
+    loop {
+        let label_len = *query.get(name_end)? as usize;
+        name_end += 1;
+        if label_len == 0 {
+            break;
+        }
+        // Questions are not compressed.
+        if label_len & 0xC0 != 0 {
+            return None;
+        }
+        name_end += label_len;
+    }
+
+    let question = query.get(DNS_HEADER_LEN..name_end + 4)?;
+    let record_type = u16::from_be_bytes([query[name_end], query[name_end + 1]]);
+    let class = u16::from_be_bytes([query[name_end + 2], query[name_end + 3]]);
+
+    // A or ANY, in the Internet class.
+    let answer = class == 1 && (record_type == 1 || record_type == 255);
+
+    let answer_start = DNS_HEADER_LEN + question.len();
+    let len = answer_start + if answer { 16 } else { 0 };
+    let response = response.get_mut(..len)?;
+
+    response[0..2].copy_from_slice(&header[0..2]);
+    // QR, AA and the RD bit of the query.
+    response[2..4].copy_from_slice(&(0x8400 | (flags & 0x0100)).to_be_bytes());
+    response[4..6].copy_from_slice(&1u16.to_be_bytes());
+    response[6..8].copy_from_slice(&(answer as u16).to_be_bytes());
+    response[8..12].fill(0);
+    response[DNS_HEADER_LEN..answer_start].copy_from_slice(question);
+
+    if answer {
+        let record = &mut response[answer_start..];
+        // The name is a pointer to the one in the question.
+        record[0..2].copy_from_slice(&[0xC0, DNS_HEADER_LEN as u8]);
+        record[2..4].copy_from_slice(&1u16.to_be_bytes());
+        record[4..6].copy_from_slice(&1u16.to_be_bytes());
+        record[6..10].copy_from_slice(&DNS_TTL.to_be_bytes());
+        record[10..12].copy_from_slice(&4u16.to_be_bytes());
+        record[12..16].copy_from_slice(&AP_ADDRESS.octets());
+    }
+
+    Some(len)
+}
+
+/// Answers DISCOVER with an offer of the client address, and REQUEST with an ACK or NAK.
+pub fn dhcp_reply(request: &[u8], reply: &mut [u8]) -> Option<usize> {
+    // BOOTREQUEST, Ethernet
+    if !(DHCP_OPTIONS_START..=DHCP_MAX_LEN).contains(&request.len())
+        || request[0] != 1
+        || request[1] != 1
+        || request[236..240] != DHCP_MAGIC_COOKIE
+    {
+        return None;
+    }
+
+    let options = &request[DHCP_OPTIONS_START..];
+    let message_type = match dhcp_option(options, OPTION_MESSAGE_TYPE)? {
+        [DHCP_DISCOVER] => DHCP_OFFER,
+        [DHCP_REQUEST] => {
+            // The client may have chosen another server, or ask for an address from another
+            // network.
+            let server = dhcp_option(options, OPTION_SERVER_ID);
+            if server.is_some_and(|server| server != AP_ADDRESS.octets()) {
+                return None;
+            }
+
+            let requested = dhcp_option(options, OPTION_REQUESTED_ADDRESS)
+                .or_else(|| Some(&request[12..16]).filter(|ciaddr| *ciaddr != [0; 4]));
+            match requested {
+                Some(address) if address != CLIENT_ADDRESS.octets() => DHCP_NAK,
+                _ => DHCP_ACK,
+            }
+        }
+        _ => return None,
+    };
+
+    let reply = reply.get_mut(..DHCP_MAX_LEN)?;
+    reply.fill(0);
+
+    reply[0] = 2; // BOOTREPLY
+    reply[1..3].copy_from_slice(&request[1..3]); // hardware type and address length
+    reply[4..8].copy_from_slice(&request[4..8]); // transaction ID
+    reply[10..12].copy_from_slice(&request[10..12]); // flags
+    if message_type != DHCP_NAK {
+        reply[16..20].copy_from_slice(&CLIENT_ADDRESS.octets());
+    }
+    reply[28..44].copy_from_slice(&request[28..44]); // client hardware address
+    reply[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+
+    let mut len = DHCP_OPTIONS_START;
+    let mut option = |code: u8, value: &[u8]| {
+        reply[len] = code;
+        reply[len + 1] = value.len() as u8;
+        reply[len + 2..len + 2 + value.len()].copy_from_slice(value);
+        len += 2 + value.len();
+    };
+
+    option(OPTION_MESSAGE_TYPE, &[message_type]);
+    option(OPTION_SERVER_ID, &AP_ADDRESS.octets());
+    if message_type != DHCP_NAK {
+        option(OPTION_LEASE_TIME, &LEASE_TIME.to_be_bytes());
+        option(OPTION_SUBNET_MASK, &SUBNET_MASK);
+        option(OPTION_ROUTER, &AP_ADDRESS.octets());
+        option(OPTION_DNS_SERVER, &AP_ADDRESS.octets());
+        option(OPTION_CAPTIVE_PORTAL, PORTAL_URL.as_bytes());
+    }
+
+    reply[len] = OPTION_END;
+    len += 1;
+
+    Some(len.max(DHCP_MIN_LEN))
+}
+
+/// Returns the value of a DHCP option.
+fn dhcp_option(mut options: &[u8], code: u8) -> Option<&[u8]> {
+    loop {
+        match *options.first()? {
+            OPTION_END => return None,
+            OPTION_PAD => options = &options[1..],
+            current => {
+                let len = *options.get(1)? as usize;
+                let value = options.get(2..2 + len)?;
+                if current == code {
+                    return Some(value);
+                }
+                options = &options[2 + len..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn dns_query(name: &[&str], record_type: u16) -> Vec<u8> {
+        let mut query = Vec::from([0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&record_type.to_be_bytes());
+        query.extend_from_slice(&1u16.to_be_bytes());
+        query
+    }
+
+    fn dhcp_request(options: &[u8]) -> Vec<u8> {
+        let mut request = std::vec![0; DHCP_OPTIONS_START];
+        request[0] = 1;
+        request[1] = 1;
+        request[2] = 6;
+        request[4..8].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        request[28..34].copy_from_slice(&[2, 0, 0, 0, 0, 1]);
+        request[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        request.extend_from_slice(options);
+        request.push(OPTION_END);
+        request
+    }
+
+    #[test]
+    fn a_queries_are_answered_with_the_access_point() {
+        let query = dns_query(&["connectivitycheck", "gstatic", "com"], 1);
+        let mut response = [0; DNS_MAX_LEN];
+
+        let len = dns_response(&query, &mut response).unwrap();
+        assert_eq!(len, query.len() + 16);
+
+        assert_eq!(response[0..2], [0x12, 0x34]);
+        assert_eq!(response[2..4], [0x85, 0x00]);
+        assert_eq!(response[4..8], [0, 1, 0, 1]);
+        assert_eq!(response[12..query.len()], query[12..]);
+        assert_eq!(response[len - 4..len], AP_ADDRESS.octets());
+
+        let query = dns_query(&["example", "com"], 28);
+        let len = dns_response(&query, &mut response).unwrap();
+        assert_eq!(len, query.len());
+        assert_eq!(response[6..8], [0, 0]);
+    }
+
+    #[test]
+    fn invalid_dns_queries_are_ignored() {
+        let query = dns_query(&["example", "com"], 1);
+        let mut response = [0; DNS_MAX_LEN];
+
+        for len in 0..query.len() {
+            assert_eq!(dns_response(&query[..len], &mut response), None, "{len}");
+        }
+
+        let mut not_a_query = query.clone();
+        not_a_query[2] |= 0x80;
+        assert_eq!(dns_response(&not_a_query, &mut response), None);
+
+        let mut two_questions = query.clone();
+        two_questions[5] = 2;
+        assert_eq!(dns_response(&two_questions, &mut response), None);
+
+        let mut compressed = query.clone();
+        compressed[12] = 0xC0;
+        assert_eq!(dns_response(&compressed, &mut response), None);
+
+        let mut overrunning_label = query.clone();
+        overrunning_label[12] = 63;
+        assert_eq!(dns_response(&overrunning_label, &mut response), None);
+
+        let label = "a".repeat(63);
+        let oversized = dns_query(&[label.as_str(); 8], 1);
+        assert!(oversized.len() > DNS_MAX_LEN);
+        assert_eq!(dns_response(&oversized, &mut response), None);
+
+        // The query fits, but the answer would not.
+        let mut name = [label.as_str(); 8];
+        name[7] = &label[..33];
+        let long_name = dns_query(&name, 1);
+        assert!(long_name.len() <= DNS_MAX_LEN);
+        assert_eq!(dns_response(&long_name, &mut response), None);
+    }
+
+    #[test]
+    fn discover_is_answered_with_an_offer() {
+        let request = dhcp_request(&[OPTION_MESSAGE_TYPE, 1, DHCP_DISCOVER]);
+        let mut reply = [0; DHCP_MAX_LEN];
+
+        let len = dhcp_reply(&request, &mut reply).unwrap();
+        assert_eq!(len, DHCP_MIN_LEN);
+
+        let reply = &reply[..len];
+        assert_eq!(reply[0], 2);
+        assert_eq!(reply[4..8], request[4..8]);
+        assert_eq!(reply[16..20], CLIENT_ADDRESS.octets());
+        assert_eq!(reply[28..34], request[28..34]);
+
+        let options = &reply[DHCP_OPTIONS_START..];
+        assert_eq!(
+            dhcp_option(options, OPTION_MESSAGE_TYPE),
+            Some(&[DHCP_OFFER][..])
+        );
+        assert_eq!(
+            dhcp_option(options, OPTION_SERVER_ID),
+            Some(&AP_ADDRESS.octets()[..])
+        );
+        assert_eq!(
+            dhcp_option(options, OPTION_DNS_SERVER),
+            Some(&AP_ADDRESS.octets()[..])
+        );
+        assert_eq!(
+            dhcp_option(options, OPTION_CAPTIVE_PORTAL),
+            Some(PORTAL_URL.as_bytes())
+        );
+    }
+
+    #[test]
+    fn requests_for_other_addresses_are_refused() {
+        let mut reply = [0; DHCP_MAX_LEN];
+        let mut request = |address: [u8; 4]| {
+            let request = dhcp_request(&[
+                OPTION_MESSAGE_TYPE,
+                1,
+                DHCP_REQUEST,
+                OPTION_REQUESTED_ADDRESS,
+                4,
+                address[0],
+                address[1],
+                address[2],
+                address[3],
+            ]);
+            let len = dhcp_reply(&request, &mut reply).unwrap();
+            dhcp_option(&reply[DHCP_OPTIONS_START..len], OPTION_MESSAGE_TYPE).map(|t| t[0])
+        };
+
+        assert_eq!(request(CLIENT_ADDRESS.octets()), Some(DHCP_ACK));
+        assert_eq!(request([10, 0, 0, 5]), Some(DHCP_NAK));
+    }
+
+    #[test]
+    fn invalid_dhcp_messages_are_ignored() {
+        let request = dhcp_request(&[OPTION_MESSAGE_TYPE, 1, DHCP_DISCOVER]);
+        let mut reply = [0; DHCP_MAX_LEN];
+
+        for len in 0..request.len() - 1 {
+            assert_eq!(dhcp_reply(&request[..len], &mut reply), None, "{len}");
+        }
+
+        let mut wrong_cookie = request.clone();
+        wrong_cookie[236] = 0;
+        assert_eq!(dhcp_reply(&wrong_cookie, &mut reply), None);
+
+        let mut not_a_request = request.clone();
+        not_a_request[0] = 2;
+        assert_eq!(dhcp_reply(&not_a_request, &mut reply), None);
+
+        let overrunning_option = dhcp_request(&[OPTION_SERVER_ID, 200, 0, 0, 0, 0]);
+        assert_eq!(dhcp_reply(&overrunning_option, &mut reply), None);
+
+        let other_server = dhcp_request(&[
+            OPTION_MESSAGE_TYPE,
+            1,
+            DHCP_REQUEST,
+            OPTION_SERVER_ID,
+            4,
+            10,
+            0,
+            0,
+            1,
+        ]);
+        assert_eq!(dhcp_reply(&other_server, &mut reply), None);
+
+        let mut oversized = std::vec![OPTION_PAD; DHCP_MAX_LEN];
+        oversized.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, DHCP_DISCOVER]);
+        let oversized = dhcp_request(&oversized);
+        assert_eq!(dhcp_reply(&oversized, &mut reply), None);
+
+        // Replies don't fit in a short buffer.
+        assert_eq!(dhcp_reply(&request, &mut [0; DHCP_MIN_LEN]), None);
+    }
+
+    #[test]
+    fn corrupted_messages_do_not_panic() {
+        let query = dns_query(&["example", "com"], 1);
+        let request = dhcp_request(&[
+            OPTION_MESSAGE_TYPE,
+            1,
+            DHCP_REQUEST,
+            OPTION_REQUESTED_ADDRESS,
+            4,
+            192,
+            168,
+            2,
+            2,
+        ]);
+
+        let mut response = [0; DHCP_MAX_LEN];
+        for message in [&query, &request] {
+            for index in 0..message.len() {
+                for value in [0, 1, 0x3F, 0x40, 0xC0, 0xFF] {
+                    let mut corrupted = message.clone();
+                    corrupted[index] = value;
+                    _ = dns_response(&corrupted, &mut response);
+                    _ = dhcp_reply(&corrupted, &mut response);
+                }
+            }
+        }
+    }
+}
@@ -8,9 +8,15 @@ use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
 #[cfg(feature = "std")]
 use smol::lock::Mutex;
 
+pub const MAX_CA_CERTIFICATE_LEN: usize = 2048;
+
+/// A DER encoded X.509 certificate.
+pub type CaCertificate = heapless::Vec<u8, MAX_CA_CERTIFICATE_LEN>;
+
 pub struct WebContext {
     pub known_networks: heapless::Vec<WifiNetwork, 8>,
     pub backend_url: heapless::String<64>,
+    pub server_ca: CaCertificate,
 }
 
 #[cfg(feature = "embedded")]
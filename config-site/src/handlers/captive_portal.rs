@@ -0,0 +1,60 @@
+use core::marker::PhantomData;
+
+use bad_server::{
+    connector::Connection, handler::Handler, method::Method, request::Request,
+    response::ResponseStatus, HandleError, Header,
+};
+
+/// Requests operating systems send to find out whether a network has a captive portal.
+const PROBE_PATHS: &[&str] = &[
+    // Android, ChromeOS
+    "/generate_204",
+    "/gen_204",
+    // iOS, macOS
+    "/hotspot-detect.html",
+    "/library/test/success.html",
+    // Windows
+    "/connecttest.txt",
+    "/ncsi.txt",
+    "/redirect",
+    // Firefox
+    "/success.txt",
+    "/canonical.html",
+];
+
+/// Redirects captive portal probes to the config site, which makes devices joining the access
+/// point open it.
+pub struct CaptivePortal<'a, C> {
+    location: &'a str,
+    _connection: PhantomData<C>,
+}
+
+impl<'a, C> CaptivePortal<'a, C> {
+    /// `location` is the absolute URL of the config site.
+    pub fn new(location: &'a str) -> Self {
+        Self {
+            location,
+            _connection: PhantomData,
+        }
+    }
+}
+
+impl<C: Connection> Handler for CaptivePortal<'_, C> {
+    type Connection = C;
+
+    fn handles(&self, request: &Request<'_, '_, C>) -> bool {
+        request.method == Method::Get && PROBE_PATHS.contains(&request.path)
+    }
+
+    async fn handle(&self, request: Request<'_, '_, C>) -> Result<(), HandleError<C>> {
+        let mut response = request.start_response(ResponseStatus::Found).await?;
+        response
+            .send_header(Header {
+                name: "Location",
+                value: self.location.as_bytes(),
+            })
+            .await?;
+
+        response.send_body(b"").await
+    }
+}
@@ -0,0 +1,70 @@
+use bad_server::{
+    connector::Connection, handler::RequestHandler, request::Request, response::ResponseStatus,
+    HandleError,
+};
+
+use crate::data::{SharedWebContext, MAX_CA_CERTIFICATE_LEN};
+
+pub struct ChangeServerCa<'a> {
+    pub context: &'a SharedWebContext,
+}
+
+impl<C: Connection> RequestHandler<C> for ChangeServerCa<'_> {
+    async fn handle(&self, mut request: Request<'_, '_, C>) -> Result<(), HandleError<C>> {
+        let mut buf = [0u8; MAX_CA_CERTIFICATE_LEN];
+
+        debug!("Reading POST data");
+        let post_data = request.read_all(&mut buf).await?;
+
+        if !request.is_complete() {
+            return request
+                .send_error_response(
+                    ResponseStatus::RequestEntityTooLarge,
+                    "Certificate is too large",
+                )
+                .await;
+        }
+
+        if !validate_certificate(post_data) {
+            return request
+                .send_error_response(ResponseStatus::BadRequest, "Input is not a certificate")
+                .await;
+        }
+
+        {
+            // Scope-limit the lock guard
+            let mut context = self.context.lock().await;
+            context.server_ca.clear();
+            unwrap!(context.server_ca.extend_from_slice(post_data).ok());
+        }
+
+        request.send_response("").await
+    }
+}
+
+/// Checks that `der` is a single DER encoded SEQUENCE, which is what a certificate looks like.
+/// An empty input removes the certificate.
+fn validate_certificate(der: &[u8]) -> bool {
+    if der.is_empty() {
+        return true;
+    }
+
+    let [0x30, len, rest @ ..] = der else {
+        return false;
+    };
+
+    let (len, content) = match *len {
+        len @ 0..=0x7F => (len as usize, rest),
+        0x81 => match rest {
+            [len, content @ ..] => (*len as usize, content),
+            _ => return false,
+        },
+        0x82 => match rest {
+            [hi, lo, content @ ..] => (u16::from_be_bytes([*hi, *lo]) as usize, content),
+            _ => return false,
+        },
+        _ => return false,
+    };
+
+    content.len() == len
+}
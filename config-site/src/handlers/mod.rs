@@ -1,8 +1,11 @@
 pub mod add_new_network;
 pub mod backend_url;
+pub mod captive_portal;
 pub mod change_backend_url;
+pub mod change_server_ca;
 pub mod delete_network;
 pub mod list_known_networks;
+pub mod server_ca;
 
 #[cfg(feature = "compress")]
 mod statics {
@@ -0,0 +1,31 @@
+use core::fmt::Write as _;
+
+use bad_server::{
+    connector::Connection, handler::RequestHandler, request::Request, response::ResponseStatus,
+    HandleError,
+};
+
+use crate::data::SharedWebContext;
+
+pub struct ServerCa<'a> {
+    pub context: &'a SharedWebContext,
+}
+
+impl<C: Connection> RequestHandler<C> for ServerCa<'_> {
+    async fn handle(&self, request: Request<'_, '_, C>) -> Result<(), HandleError<C>> {
+        let response = request.start_response(ResponseStatus::Ok).await?;
+        let mut response = response.start_chunked_body().await?;
+
+        // The certificate itself is not interesting, only whether one is set.
+        let mut description = heapless::String::<16>::new();
+        {
+            let context = self.context.lock().await;
+            if !context.server_ca.is_empty() {
+                _ = write!(description, "{} bytes", context.server_ca.len());
+            }
+        }
+        response.write(&description).await?;
+
+        response.end_chunked_response().await
+    }
+}
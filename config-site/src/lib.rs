@@ -18,14 +18,16 @@ use crate::{
     data::SharedWebContext,
     handlers::{
         add_new_network::AddNewNetwork, backend_url::BackendUrl,
-        change_backend_url::ChangeBackendUrl, delete_network::DeleteNetwork,
-        list_known_networks::ListKnownNetworks, HEADER_FONT, INDEX_HANDLER,
+        change_backend_url::ChangeBackendUrl, change_server_ca::ChangeServerCa,
+        delete_network::DeleteNetwork, list_known_networks::ListKnownNetworks, server_ca::ServerCa,
+        HEADER_FONT, INDEX_HANDLER,
     },
 };
 
 // MUST be the first module
 mod fmt;
 
+pub mod captive_portal;
 pub mod data;
 #[cfg(feature = "serve")]
 pub mod handlers;
@@ -56,4 +58,6 @@ where
         .with_handler(RequestHandler::post("/dn", DeleteNetwork { context }))
         .with_handler(RequestHandler::get("/bu", BackendUrl { context }))
         .with_handler(RequestHandler::post("/cbu", ChangeBackendUrl { context }))
+        .with_handler(RequestHandler::get("/ca", ServerCa { context }))
+        .with_handler(RequestHandler::post("/cca", ChangeServerCa { context }))
 }
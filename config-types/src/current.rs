@@ -1,4 +1,4 @@
-use config_site::data::network::WifiNetwork;
+use config_site::data::{network::WifiNetwork, CaCertificate};
 use embedded_io_async::{Read, Write};
 use gui::widgets::battery_small::BatteryStyle;
 use norfs::storable::{LoadError, Loadable, Storable};
@@ -6,8 +6,9 @@ use ssd1306::prelude::Brightness;
 
 use super::{
     types::{
-        DisplayBrightness, FilterStrength, Gain, LeadOffCurrent, LeadOffFrequency,
-        LeadOffThreshold, MeasurementAction,
+        ChannelMode, DisplayBrightness, FilterStrength, Gain, LeadOffCurrent, LeadOffFrequency,
+        LeadOffThreshold, MeasurementAction, RespirationFrequency, RespirationPhase,
+        RetentionPolicy, SampleRate,
     },
     CURRENT_VERSION,
 };
@@ -19,17 +20,27 @@ pub struct Config {
     pub known_networks: heapless::Vec<WifiNetwork, 8>,
     pub filter_strength: FilterStrength,
     pub backend_url: heapless::String<64>,
+    /// DER encoded certificate the backend's certificate must be signed by.
+    pub server_ca: CaCertificate,
     pub measurement_action: MeasurementAction,
+    pub retention_policy: RetentionPolicy,
     // ADC frontend config
     pub use_external_clock: bool,
     pub lead_off_current: LeadOffCurrent,
     pub lead_off_threshold: LeadOffThreshold,
     pub lead_off_frequency: LeadOffFrequency,
     pub gain: Gain,
+    pub channel_mode: ChannelMode,
+    pub respiration_frequency: RespirationFrequency,
+    pub respiration_phase: RespirationPhase,
+    pub sample_rate: SampleRate,
+    pub holter_mode: bool,
+    /// Passphrase of the configuration access point. Generated when first needed.
+    pub ap_passphrase: heapless::String<16>,
 }
 
-impl From<super::v5::Config> for Config {
-    fn from(value: super::v5::Config) -> Self {
+impl From<super::v6::Config> for Config {
+    fn from(value: super::v6::Config) -> Self {
         Self {
             battery_display_style: value.battery_display_style,
             display_brightness: value.display_brightness,
@@ -37,6 +48,11 @@ impl From<super::v5::Config> for Config {
             filter_strength: value.filter_strength,
             backend_url: value.backend_url,
             measurement_action: value.measurement_action,
+            use_external_clock: value.use_external_clock,
+            lead_off_current: value.lead_off_current,
+            lead_off_threshold: value.lead_off_threshold,
+            lead_off_frequency: value.lead_off_frequency,
+            gain: value.gain,
             ..Default::default()
         }
     }
@@ -51,12 +67,21 @@ impl Default for Config {
             known_networks: heapless::Vec::new(),
             filter_strength: FilterStrength::Weak,
             backend_url: heapless::String::try_from(crate::DEFAULT_BACKEND_URL).unwrap(),
+            server_ca: heapless::Vec::new(),
             measurement_action: MeasurementAction::Auto,
+            retention_policy: RetentionPolicy::Uploaded,
             use_external_clock: true,
             lead_off_current: LeadOffCurrent::Normal,
             lead_off_threshold: LeadOffThreshold::_95,
             lead_off_frequency: LeadOffFrequency::Dc,
             gain: Gain::X1,
+            channel_mode: ChannelMode::Single,
+            // Recommended by the datasheet for internal respiration.
+            respiration_frequency: RespirationFrequency::_32kHz,
+            respiration_phase: RespirationPhase::_112deg,
+            sample_rate: SampleRate::_1000,
+            holter_mode: false,
+            ap_passphrase: heapless::String::new(),
         }
     }
 }
@@ -89,12 +114,20 @@ impl Loadable for Config {
             known_networks: heapless::Vec::load(reader).await?,
             filter_strength: FilterStrength::load(reader).await?,
             backend_url: heapless::String::load(reader).await?,
+            server_ca: heapless::Vec::load(reader).await?,
             measurement_action: MeasurementAction::load(reader).await?,
+            retention_policy: RetentionPolicy::load(reader).await?,
             use_external_clock: bool::load(reader).await?,
             lead_off_current: LeadOffCurrent::load(reader).await?,
             lead_off_threshold: LeadOffThreshold::load(reader).await?,
             lead_off_frequency: LeadOffFrequency::load(reader).await?,
             gain: Gain::load(reader).await?,
+            channel_mode: ChannelMode::load(reader).await?,
+            respiration_frequency: RespirationFrequency::load(reader).await?,
+            respiration_phase: RespirationPhase::load(reader).await?,
+            sample_rate: SampleRate::load(reader).await?,
+            holter_mode: bool::load(reader).await?,
+            ap_passphrase: heapless::String::load(reader).await?,
         };
 
         Ok(data)
@@ -110,12 +143,20 @@ impl Storable for Config {
         self.known_networks.store(writer).await?;
         self.filter_strength.store(writer).await?;
         self.backend_url.store(writer).await?;
+        self.server_ca.store(writer).await?;
         self.measurement_action.store(writer).await?;
+        self.retention_policy.store(writer).await?;
         self.use_external_clock.store(writer).await?;
         self.lead_off_current.store(writer).await?;
         self.lead_off_threshold.store(writer).await?;
         self.lead_off_frequency.store(writer).await?;
         self.gain.store(writer).await?;
+        self.channel_mode.store(writer).await?;
+        self.respiration_frequency.store(writer).await?;
+        self.respiration_phase.store(writer).await?;
+        self.sample_rate.store(writer).await?;
+        self.holter_mode.store(writer).await?;
+        self.ap_passphrase.store(writer).await?;
 
         Ok(())
     }
@@ -5,6 +5,9 @@ mod fmt;
 
 pub const DEFAULT_BACKEND_URL: &str = "https://stingray-prime-monkey.ngrok-free.app";
 pub const LOW_BATTERY_PERCENTAGE: u8 = 5;
+/// New measurements are not started below this charge level, so a running one has enough energy
+/// left to store the recording when it is stopped at [`LOW_BATTERY_PERCENTAGE`].
+pub const MEASUREMENT_RESERVE_PERCENTAGE: u8 = 8;
 
 pub mod current;
 pub mod v1;
@@ -12,6 +15,7 @@ pub mod v2;
 pub mod v3;
 pub mod v4;
 pub mod v5;
+pub mod v6;
 
 pub mod types;
 
@@ -20,7 +24,7 @@ pub use current::Config;
 use embedded_io_async::Read;
 use norfs::storable::{LoadError, Loadable};
 
-const CURRENT_VERSION: u8 = 5;
+const CURRENT_VERSION: u8 = 6;
 
 #[derive(Clone)]
 #[allow(clippy::large_enum_variant)]
@@ -30,6 +34,7 @@ pub enum ConfigFile {
     V3(v3::Config),
     V4(v4::Config),
     V5(v5::Config),
+    V6(v6::Config),
     Current(Config),
 }
 
@@ -64,6 +69,10 @@ impl ConfigFile {
             self = Self::V5(v5::Config::from(config));
         }
         if let Self::V5(config) = self {
+            info!("Migrating config data to v6");
+            self = Self::V6(v6::Config::from(config));
+        }
+        if let Self::V6(config) = self {
             info!("Migrating config data to latest");
             self = Self::Current(Config::from(config));
         }
@@ -85,6 +94,7 @@ impl Loadable for ConfigFile {
             2 => Self::V3(v3::Config::load(reader).await?),
             3 => Self::V4(v4::Config::load(reader).await?),
             4 => Self::V5(v5::Config::load(reader).await?),
+            5 => Self::V6(v6::Config::load(reader).await?),
             CURRENT_VERSION => Self::Current(Config::load(reader).await?),
             _ => return Err(LoadError::InvalidValue),
         };
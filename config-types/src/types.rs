@@ -61,6 +61,19 @@ implement_enum! {
     }
 }
 
+implement_enum! {
+    /// What to delete when the storage is full.
+    pub enum RetentionPolicy {
+        /// Don't delete anything, new measurements can't be stored once the storage is full.
+        None = 0,
+        /// Delete the oldest uploaded measurements. New measurements can't be stored once only
+        /// the ones waiting for upload are left.
+        Uploaded = 1,
+        /// Delete the oldest uploaded measurements, then the oldest ones waiting for upload.
+        Oldest = 2,
+    }
+}
+
 implement_enum! {
     pub enum LeadOffCurrent {
         Weak = 0,
@@ -101,3 +114,64 @@ implement_enum! {
         X12 = 6,
     }
 }
+
+implement_enum! {
+    pub enum ChannelMode {
+        Single = 0,
+        Dual = 1,
+        /// ECG on channel 2, impedance pneumography on channel 1. Requires an ADS1292R.
+        Respiration = 2,
+    }
+}
+
+implement_enum! {
+    /// Modulation frequency of the respiration measurement.
+    pub enum RespirationFrequency {
+        _32kHz = 0,
+        _64kHz = 1,
+    }
+}
+
+implement_enum! {
+    /// Demodulation phase of the respiration measurement. Variant names describe the phase at
+    /// 32kHz. At 64kHz the step size doubles, and only phases up to `_78deg` can be used.
+    pub enum RespirationPhase {
+        _0deg = 0,
+        _11deg = 1,
+        _22deg = 2,
+        _33deg = 3,
+        _45deg = 4,
+        _56deg = 5,
+        _67deg = 6,
+        _78deg = 7,
+        _90deg = 8,
+        _101deg = 9,
+        _112deg = 10,
+        _123deg = 11,
+        _135deg = 12,
+        _146deg = 13,
+        _157deg = 14,
+        _168deg = 15,
+    }
+}
+
+implement_enum! {
+    /// ADC sample rate, in samples per second.
+    pub enum SampleRate {
+        _250 = 0,
+        _500 = 1,
+        _1000 = 2,
+        _2000 = 3,
+    }
+}
+
+impl SampleRate {
+    pub const fn sps(self) -> u16 {
+        match self {
+            SampleRate::_250 => 250,
+            SampleRate::_500 => 500,
+            SampleRate::_1000 => 1000,
+            SampleRate::_2000 => 2000,
+        }
+    }
+}
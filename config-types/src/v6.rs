@@ -0,0 +1,64 @@
+use config_site::data::network::WifiNetwork;
+use embedded_io_async::Read;
+use gui::widgets::battery_small::BatteryStyle;
+use norfs::storable::{LoadError, Loadable};
+
+use super::types::{
+    DisplayBrightness, FilterStrength, Gain, LeadOffCurrent, LeadOffFrequency, LeadOffThreshold,
+    MeasurementAction,
+};
+
+#[derive(Clone)]
+pub struct Config {
+    pub battery_display_style: BatteryStyle,
+    pub display_brightness: DisplayBrightness,
+    pub known_networks: heapless::Vec<WifiNetwork, 8>,
+    pub filter_strength: FilterStrength,
+    pub backend_url: heapless::String<64>,
+    pub measurement_action: MeasurementAction,
+    // ADC frontend config
+    pub use_external_clock: bool,
+    pub lead_off_current: LeadOffCurrent,
+    pub lead_off_threshold: LeadOffThreshold,
+    pub lead_off_frequency: LeadOffFrequency,
+    pub gain: Gain,
+}
+
+impl From<super::v5::Config> for Config {
+    fn from(value: super::v5::Config) -> Self {
+        let defaults = crate::Config::default();
+        Self {
+            battery_display_style: value.battery_display_style,
+            display_brightness: value.display_brightness,
+            known_networks: value.known_networks,
+            filter_strength: value.filter_strength,
+            backend_url: value.backend_url,
+            measurement_action: value.measurement_action,
+            use_external_clock: defaults.use_external_clock,
+            lead_off_current: defaults.lead_off_current,
+            lead_off_threshold: defaults.lead_off_threshold,
+            lead_off_frequency: defaults.lead_off_frequency,
+            gain: defaults.gain,
+        }
+    }
+}
+
+impl Loadable for Config {
+    async fn load<R: Read>(reader: &mut R) -> Result<Self, LoadError<R::Error>> {
+        let data = Self {
+            battery_display_style: BatteryStyle::load(reader).await?,
+            display_brightness: DisplayBrightness::load(reader).await?,
+            known_networks: heapless::Vec::load(reader).await?,
+            filter_strength: FilterStrength::load(reader).await?,
+            backend_url: heapless::String::load(reader).await?,
+            measurement_action: MeasurementAction::load(reader).await?,
+            use_external_clock: bool::load(reader).await?,
+            lead_off_current: LeadOffCurrent::load(reader).await?,
+            lead_off_threshold: LeadOffThreshold::load(reader).await?,
+            lead_off_frequency: LeadOffFrequency::load(reader).await?,
+            gain: Gain::load(reader).await?,
+        };
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,260 @@
+//! Compatibility checks based on what an application image says about itself.
+//!
+//! An ESP-IDF application image starts with an image header, which names the chip the image was
+//! built for. The first segment starts with the app description (`esp_app_desc_t`), where the
+//! firmware stores its version and the hardware it was built for:
+//!
+//! ```text
+//! project name: card_io_fw/{hardware version}
+//! version:      {major}.{minor}.{patch}+{build}.{commit}
+//! ```
+//!
+//! The build number is raised for every release build, so later builds of the same release compare
+//! greater.
+
+pub const CHIP_ID_ESP32S3: u16 = 9;
+pub const CHIP_ID_ESP32C6: u16 = 13;
+
+const IMAGE_MAGIC: u8 = 0xE9;
+const APP_DESC_MAGIC: u32 = 0xABCD_5432;
+
+const IMAGE_HEADER_LEN: usize = 24;
+const SEGMENT_HEADER_LEN: usize = 8;
+const APP_DESC_LEN: usize = 256;
+
+/// The length of the image start [`ImageInfo::parse`] needs.
+pub const INFO_LEN: usize = IMAGE_HEADER_LEN + SEGMENT_HEADER_LEN + APP_DESC_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Incompatibility {
+    /// The image is not an application image, or it has no app description.
+    NotAnApp,
+    WrongChip,
+    WrongHardware,
+    /// The image is older than the running firmware, or its version is unknown.
+    Downgrade,
+}
+
+impl Incompatibility {
+    pub fn message(self) -> &'static str {
+        match self {
+            Incompatibility::NotAnApp => "Update is not a firmware image",
+            Incompatibility::WrongChip => "Update is for another chip",
+            Incompatibility::WrongHardware => "Update is for another hardware version",
+            Incompatibility::Downgrade => "Update is older than the installed firmware",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageInfo<'a> {
+    pub chip_id: u16,
+    pub project_name: &'a str,
+    pub version: &'a str,
+}
+
+impl<'a> ImageInfo<'a> {
+    /// Reads the first [`INFO_LEN`] bytes of an image.
+    pub fn parse(image: &'a [u8]) -> Option<Self> {
+        if image.len() < INFO_LEN || image[0] != IMAGE_MAGIC || image[1] == 0 {
+            return None;
+        }
+
+        let desc = &image[IMAGE_HEADER_LEN + SEGMENT_HEADER_LEN..INFO_LEN];
+        if u32::from_le_bytes([desc[0], desc[1], desc[2], desc[3]]) != APP_DESC_MAGIC {
+            return None;
+        }
+
+        Some(Self {
+            chip_id: u16::from_le_bytes([image[12], image[13]]),
+            version: c_str(&desc[16..48])?,
+            project_name: c_str(&desc[48..80])?,
+        })
+    }
+
+    /// Checks if this image may replace the `running` one.
+    pub fn check_update(
+        &self,
+        running: &ImageInfo<'_>,
+        allow_downgrade: bool,
+    ) -> Result<(), Incompatibility> {
+        if self.chip_id != running.chip_id {
+            return Err(Incompatibility::WrongChip);
+        }
+        if self.project_name != running.project_name {
+            return Err(Incompatibility::WrongHardware);
+        }
+
+        if !allow_downgrade {
+            match (version_key(self.version), version_key(running.version)) {
+                (Some(new), Some(current)) if new >= current => {}
+                _ => return Err(Incompatibility::Downgrade),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn c_str(field: &[u8]) -> Option<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).ok()
+}
+
+/// Returns the version as `[major, minor, patch, build]`. A missing build number is 0.
+fn version_key(version: &str) -> Option<[u32; 4]> {
+    let (release, build) = match version.split_once('+') {
+        Some((release, build)) => (release, build.split('.').next()?.parse().ok()?),
+        None => (version, 0),
+    };
+
+    let mut parts = release.split('.').map(|part| part.parse().ok());
+    let key = [parts.next()??, parts.next()??, parts.next()??, build];
+
+    parts.next().is_none().then_some(key)
+}
+
+/// Holds back the start of an image until it is known to be compatible.
+pub struct CompatibilityCheck<'r> {
+    running: ImageInfo<'r>,
+    allow_downgrade: bool,
+    start: [u8; INFO_LEN],
+    start_len: usize,
+    passed: bool,
+}
+
+impl<'r> CompatibilityCheck<'r> {
+    pub fn new(running: ImageInfo<'r>, allow_downgrade: bool) -> Self {
+        Self {
+            running,
+            allow_downgrade,
+            start: [0; INFO_LEN],
+            start_len: 0,
+            passed: false,
+        }
+    }
+
+    /// Takes the next part of the image, and returns what may be written so far.
+    ///
+    /// Nothing is returned until the image start is checked. After that, the held back start and
+    /// then everything received is returned.
+    pub fn update<'s>(&'s mut self, data: &'s [u8]) -> Result<[&'s [u8]; 2], Incompatibility> {
+        if self.passed {
+            return Ok([&[], data]);
+        }
+
+        let len = data.len().min(INFO_LEN - self.start_len);
+        self.start[self.start_len..self.start_len + len].copy_from_slice(&data[..len]);
+        self.start_len += len;
+
+        if self.start_len < INFO_LEN {
+            return Ok([&[], &[]]);
+        }
+
+        ImageInfo::parse(&self.start)
+            .ok_or(Incompatibility::NotAnApp)?
+            .check_update(&self.running, self.allow_downgrade)?;
+        self.passed = true;
+
+        Ok([&self.start, &data[len..]])
+    }
+
+    /// Checks that the image was long enough to be checked.
+    pub fn finish(&self) -> Result<(), Incompatibility> {
+        if self.passed {
+            Ok(())
+        } else {
+            Err(Incompatibility::NotAnApp)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RUNNING: ImageInfo<'static> = ImageInfo {
+        chip_id: CHIP_ID_ESP32S3,
+        project_name: "card_io_fw/v6s3",
+        version: "0.1.0+120.abcdef0",
+    };
+
+    fn image(chip_id: u16, project_name: &str, version: &str) -> Vec<u8> {
+        let mut image = vec![0; INFO_LEN + 100];
+        image[0] = IMAGE_MAGIC;
+        image[1] = 3;
+        image[12..14].copy_from_slice(&chip_id.to_le_bytes());
+
+        let desc = &mut image[IMAGE_HEADER_LEN + SEGMENT_HEADER_LEN..];
+        desc[0..4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        desc[16..16 + version.len()].copy_from_slice(version.as_bytes());
+        desc[48..48 + project_name.len()].copy_from_slice(project_name.as_bytes());
+        image
+    }
+
+    fn check(image: &[u8], allow_downgrade: bool) -> Result<(), Incompatibility> {
+        ImageInfo::parse(image)
+            .ok_or(Incompatibility::NotAnApp)?
+            .check_update(&RUNNING, allow_downgrade)
+    }
+
+    #[test]
+    fn rejects_images_for_other_devices_and_downgrades() {
+        let s3 = |project_name, version| image(CHIP_ID_ESP32S3, project_name, version);
+
+        assert_eq!(
+            check(&s3("card_io_fw/v6s3", "0.1.0+121.1234567"), false),
+            Ok(())
+        );
+        assert_eq!(check(&s3("card_io_fw/v6s3", "0.2.0"), false), Ok(()));
+        assert_eq!(
+            check(&image(CHIP_ID_ESP32C6, "card_io_fw/v6s3", "0.2.0"), false),
+            Err(Incompatibility::WrongChip)
+        );
+        assert_eq!(
+            check(&s3("card_io_fw/v8s3", "0.2.0"), false),
+            Err(Incompatibility::WrongHardware)
+        );
+
+        for version in ["0.1.0+119.1234567", "0.1.0", "0.0.9+500.1234567", "latest"] {
+            assert_eq!(
+                check(&s3("card_io_fw/v6s3", version), false),
+                Err(Incompatibility::Downgrade),
+                "{version}"
+            );
+            assert_eq!(check(&s3("card_io_fw/v6s3", version), true), Ok(()));
+        }
+
+        let mut not_an_app = s3("card_io_fw/v6s3", "0.2.0");
+        not_an_app[IMAGE_HEADER_LEN + SEGMENT_HEADER_LEN] = 0;
+        assert_eq!(check(&not_an_app, false), Err(Incompatibility::NotAnApp));
+    }
+
+    #[test]
+    fn holds_back_the_image_start_until_checked() {
+        let image = image(CHIP_ID_ESP32S3, "card_io_fw/v6s3", "0.2.0");
+
+        for chunk_size in [1, 100, INFO_LEN, INFO_LEN + 1, 4096] {
+            let mut check = CompatibilityCheck::new(RUNNING, false);
+            let mut written = Vec::new();
+            let mut received = 0;
+            for chunk in image.chunks(chunk_size) {
+                received += chunk.len();
+                let parts = check.update(chunk).unwrap();
+                if received < INFO_LEN {
+                    assert_eq!(parts, [&[][..], &[][..]]);
+                }
+                for part in parts {
+                    written.extend_from_slice(part);
+                }
+            }
+            assert_eq!(check.finish(), Ok(()));
+            assert_eq!(written, image);
+        }
+
+        let mut check = CompatibilityCheck::new(RUNNING, false);
+        assert_eq!(check.update(&image[..INFO_LEN - 1]), Ok([&[][..], &[][..]]));
+        assert_eq!(check.finish(), Err(Incompatibility::NotAnApp));
+    }
+}
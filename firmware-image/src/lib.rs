@@ -0,0 +1,231 @@
+//! Signed firmware images.
+//!
+//! A signed image is the application image as produced by `espflash save-image`, followed by a
+//! trailer:
+//!
+//! ```text
+//! image length: u32, little endian
+//! signature:    [u8; 64], Ed25519 signature of the SHA-256 digest of the image
+//! magic:        b"CardIOfw"
+//! ```
+//!
+//! The trailer is written to the update partition together with the image. The bootloader only
+//! reads as much as the image header describes, so the trailer is ignored after activation.
+//!
+//! Before an image is written, [`app`] checks that it was built for the device.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod app;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+pub const MAGIC: [u8; 8] = *b"CardIOfw";
+pub const SIGNATURE_LEN: usize = 64;
+pub const TRAILER_LEN: usize = 4 + SIGNATURE_LEN + MAGIC.len();
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SECRET_KEY_LEN: usize = 32;
+
+pub type PublicKey = [u8; PUBLIC_KEY_LEN];
+pub type SecretKey = [u8; SECRET_KEY_LEN];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VerifyError {
+    /// The image does not end with a signature trailer.
+    NotSigned,
+    /// The image is shorter or longer than what was signed.
+    LengthMismatch,
+    /// The image was modified, or signed with a different key.
+    InvalidSignature,
+}
+
+impl VerifyError {
+    pub fn message(self) -> &'static str {
+        match self {
+            VerifyError::NotSigned => "Update is not signed",
+            VerifyError::LengthMismatch => "Update is incomplete",
+            VerifyError::InvalidSignature => "Update signature invalid",
+        }
+    }
+}
+
+/// Returns the public key that verifies images signed with `secret`.
+pub fn public_key(secret: &SecretKey) -> PublicKey {
+    SigningKey::from_bytes(secret).verifying_key().to_bytes()
+}
+
+/// Returns the trailer that has to be appended to `image`.
+pub fn sign(image: &[u8], secret: &SecretKey) -> [u8; TRAILER_LEN] {
+    let digest = Sha256::digest(image);
+    let signature = SigningKey::from_bytes(secret).sign(&digest);
+
+    let mut trailer = [0; TRAILER_LEN];
+    trailer[0..4].copy_from_slice(&(image.len() as u32).to_le_bytes());
+    trailer[4..4 + SIGNATURE_LEN].copy_from_slice(&signature.to_bytes());
+    trailer[4 + SIGNATURE_LEN..].copy_from_slice(&MAGIC);
+    trailer
+}
+
+/// Verifies a signed image as it is received.
+///
+/// The last [`TRAILER_LEN`] bytes received are held back, so that only the image is hashed.
+pub struct Verifier {
+    hasher: Sha256,
+    image_len: usize,
+    tail: [u8; TRAILER_LEN],
+    tail_len: usize,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            image_len: 0,
+            tail: [0; TRAILER_LEN],
+            tail_len: 0,
+        }
+    }
+
+    /// The number of bytes received so far.
+    pub fn received(&self) -> usize {
+        self.image_len + self.tail_len
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        let overflow = (self.tail_len + data.len()).saturating_sub(TRAILER_LEN);
+
+        // Hash the oldest held bytes first, then the ones that arrived now.
+        let from_tail = overflow.min(self.tail_len);
+        self.hash(from_tail, &[]);
+        self.tail.copy_within(from_tail..self.tail_len, 0);
+        self.tail_len -= from_tail;
+
+        let from_data = overflow - from_tail;
+        self.hash(0, &data[..from_data]);
+        data = &data[from_data..];
+
+        self.tail[self.tail_len..self.tail_len + data.len()].copy_from_slice(data);
+        self.tail_len += data.len();
+    }
+
+    fn hash(&mut self, from_tail: usize, data: &[u8]) {
+        self.hasher.update(&self.tail[..from_tail]);
+        self.hasher.update(data);
+        self.image_len += from_tail + data.len();
+    }
+
+    /// Checks the signature of everything received.
+    pub fn verify(self, key: &PublicKey) -> Result<(), VerifyError> {
+        if self.tail_len < TRAILER_LEN || self.tail[4 + SIGNATURE_LEN..] != MAGIC {
+            return Err(VerifyError::NotSigned);
+        }
+
+        let signed_len =
+            u32::from_le_bytes([self.tail[0], self.tail[1], self.tail[2], self.tail[3]]);
+        if signed_len as usize != self.image_len {
+            return Err(VerifyError::LengthMismatch);
+        }
+
+        let Ok(key) = VerifyingKey::from_bytes(key) else {
+            return Err(VerifyError::InvalidSignature);
+        };
+        let signature = Signature::from_slice(&self.tail[4..4 + SIGNATURE_LEN])
+            .map_err(|_| VerifyError::InvalidSignature)?;
+
+        key.verify_strict(&self.hasher.finalize(), &signature)
+            .map_err(|_| VerifyError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECRET: SecretKey = [7; SECRET_KEY_LEN];
+
+    fn signed_image(len: usize) -> Vec<u8> {
+        let mut image = (0..len).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>();
+        let trailer = sign(&image, &SECRET);
+        image.extend_from_slice(&trailer);
+        image
+    }
+
+    fn verify_in_chunks(
+        image: &[u8],
+        chunk_size: usize,
+        key: &PublicKey,
+    ) -> Result<(), VerifyError> {
+        let mut verifier = Verifier::new();
+        for chunk in image.chunks(chunk_size) {
+            verifier.update(chunk);
+        }
+        assert_eq!(verifier.received(), image.len());
+        verifier.verify(key)
+    }
+
+    #[test]
+    fn accepts_signed_images_in_any_chunk_size() {
+        let key = public_key(&SECRET);
+        for len in [0, 1, 75, 76, 77, 1000] {
+            let image = signed_image(len);
+            for chunk_size in [1, 3, TRAILER_LEN - 1, TRAILER_LEN, TRAILER_LEN + 1, 4096] {
+                assert_eq!(verify_in_chunks(&image, chunk_size, &key), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let key = public_key(&SECRET);
+        let image = signed_image(1000);
+
+        // Cut into the trailer, or the image is followed by someone else's trailer.
+        assert_eq!(
+            verify_in_chunks(&image[..image.len() - 1], 64, &key),
+            Err(VerifyError::NotSigned)
+        );
+        let mut truncated = image[..500].to_vec();
+        truncated.extend_from_slice(&image[1000..]);
+        assert_eq!(
+            verify_in_chunks(&truncated, 64, &key),
+            Err(VerifyError::LengthMismatch)
+        );
+        assert_eq!(verify_in_chunks(&[], 64, &key), Err(VerifyError::NotSigned));
+    }
+
+    #[test]
+    fn rejects_corrupted_images() {
+        let key = public_key(&SECRET);
+        let image = signed_image(1000);
+
+        for position in [0, 500, 999, 1004, 1067] {
+            let mut corrupted = image.clone();
+            corrupted[position] ^= 0x10;
+            assert_eq!(
+                verify_in_chunks(&corrupted, 100, &key),
+                Err(VerifyError::InvalidSignature),
+                "corrupted byte {position}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_images_signed_with_another_key() {
+        let image = signed_image(1000);
+        let other_key = public_key(&[8; SECRET_KEY_LEN]);
+
+        assert_eq!(
+            verify_in_chunks(&image, 100, &other_key),
+            Err(VerifyError::InvalidSignature)
+        );
+    }
+}
@@ -23,7 +23,9 @@ fn main() -> Result<(), Infallible> {
     'running: loop {
         display.clear(BinaryColor::Off).unwrap();
 
-        WifiApScreen::new().draw(&mut display).unwrap();
+        WifiApScreen::new("Card/IO-1A2B", "abcdefghjk")
+            .draw(&mut display)
+            .unwrap();
 
         window.update(&display);
 
@@ -3,8 +3,8 @@ use core::{cell::RefCell, num::NonZeroU8};
 use embedded_graphics::{
     image::{Image, ImageRaw},
     pixelcolor::BinaryColor,
-    prelude::{DrawTarget, OriginDimensions, Point},
-    primitives::{Line, Primitive, PrimitiveStyle},
+    prelude::{DrawTarget, OriginDimensions, Point, Size},
+    primitives::{Line, Primitive, PrimitiveStyle, Rectangle},
     text::{Baseline, Text},
     Drawable,
 };
@@ -105,29 +105,26 @@ impl Camera {
         (min, max)
     }
 
-    fn update(&mut self, min: f32, max: f32, display: &impl DrawTarget) -> Lerp {
+    fn update(&mut self, min: f32, max: f32, area: &Rectangle) -> Lerp {
         let (min, max) = self.update_range(min, max);
 
+        let top = area.top_left.y as f32;
         Lerp {
             from: Interval::new(min, max),
-            to: Interval::new(0.0, display.bounding_box().size.height as f32 - 1.0),
+            to: Interval::new(top, top + area.size.height as f32 - 1.0),
         }
     }
 }
 
-pub struct EcgScreen {
+struct Trace {
     buffer: SlidingWindow<128>,
-    pub heart_rate: Option<NonZeroU8>,
-    pub elapsed_secs: usize,
     camera: RefCell<Camera>,
 }
 
-impl EcgScreen {
-    pub fn new() -> Self {
+impl Trace {
+    fn new() -> Self {
         Self {
             buffer: SlidingWindow::new(),
-            heart_rate: None,
-            elapsed_secs: 0,
             camera: RefCell::new(Camera {
                 min_limit: Limit::new(LimitKind::Min),
                 max_limit: Limit::new(LimitKind::Max),
@@ -141,14 +138,6 @@ impl EcgScreen {
         }
     }
 
-    pub fn push(&mut self, sample: f32) {
-        self.buffer.push(sample);
-    }
-
-    pub fn buffer_full(&self) -> bool {
-        self.buffer.is_full()
-    }
-
     fn limits(&self) -> (f32, f32) {
         let mut samples = self.buffer.iter_unordered();
 
@@ -171,9 +160,82 @@ impl EcgScreen {
         (min, max)
     }
 
+    fn draw<DT: DrawTarget<Color = BinaryColor>>(
+        &self,
+        area: Rectangle,
+        display: &mut DT,
+    ) -> Result<(), DT::Error> {
+        let (min, max) = self.limits();
+
+        let scaler = unwrap!(self.camera.try_borrow_mut()).update(min, max, &area);
+
+        const LINE_STYLE: PrimitiveStyle<BinaryColor> =
+            PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+        let line_segments = self
+            .buffer
+            .iter()
+            .enumerate()
+            .map(|(x, y)| Point::new(area.top_left.x + x as i32, scaler.map(y) as i32))
+            .tuple_windows()
+            .map(|(from, to)| Line::new(from, to).into_styled(LINE_STYLE));
+
+        for line in line_segments {
+            line.draw(display)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct EcgScreen {
+    trace: Trace,
+    secondary_trace: Option<Trace>,
+    pub heart_rate: Option<NonZeroU8>,
+    pub breathing_rate: Option<NonZeroU8>,
+    pub elapsed_secs: usize,
+}
+
+impl EcgScreen {
+    pub fn new() -> Self {
+        Self {
+            trace: Trace::new(),
+            secondary_trace: None,
+            heart_rate: None,
+            breathing_rate: None,
+            elapsed_secs: 0,
+        }
+    }
+
+    /// Creates a screen that displays two signals below each other.
+    pub fn new_dual() -> Self {
+        Self {
+            secondary_trace: Some(Trace::new()),
+            ..Self::new()
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.trace.buffer.push(sample);
+    }
+
+    pub fn push_secondary(&mut self, sample: f32) {
+        if let Some(trace) = self.secondary_trace.as_mut() {
+            trace.buffer.push(sample);
+        }
+    }
+
+    pub fn buffer_full(&self) -> bool {
+        self.trace.buffer.is_full()
+    }
+
     pub fn update_heart_rate(&mut self, hr: Option<NonZeroU8>) {
         self.heart_rate = hr;
     }
+
+    pub fn update_breathing_rate(&mut self, rate: Option<NonZeroU8>) {
+        self.breathing_rate = rate;
+    }
 }
 
 impl Drawable for EcgScreen {
@@ -182,7 +244,7 @@ impl Drawable for EcgScreen {
 
     #[inline]
     fn draw<DT: DrawTarget<Color = BinaryColor>>(&self, display: &mut DT) -> Result<(), DT::Error> {
-        if !self.buffer.is_full() {
+        if !self.buffer_full() {
             MessageScreen {
                 message: "Collecting data...",
             }
@@ -219,27 +281,47 @@ impl Drawable for EcgScreen {
             str_buffer.clear();
             unwrap!(uwrite!(&mut str_buffer, "{}", hr).ok());
 
-            Text::with_baseline(&str_buffer, status_loc, NORMAL_TEXT, Baseline::Top)
+            status_loc = Text::with_baseline(&str_buffer, status_loc, NORMAL_TEXT, Baseline::Top)
                 .draw(display)?;
         }
 
-        let (min, max) = self.limits();
+        if let Some(rate) = self.breathing_rate {
+            const LUNGS: ImageRaw<'_, BinaryColor> = ImageRaw::new(
+                &[
+                    0b00000000, //
+                    0b00010000, //
+                    0b00010000, //
+                    0b01111100, //
+                    0b11010110, //
+                    0b11010110, //
+                    0b11000110, //
+                    0b01101100, //
+                ],
+                8,
+            );
 
-        let scaler = unwrap!(self.camera.try_borrow_mut()).update(min, max, display);
+            status_loc += Point::new(4, 0);
+            Image::new(&LUNGS, status_loc).draw(display)?;
+            status_loc += Point::new(LUNGS.size().width as i32, 0);
 
-        const LINE_STYLE: PrimitiveStyle<BinaryColor> =
-            PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+            str_buffer.clear();
+            unwrap!(uwrite!(&mut str_buffer, "{}", rate).ok());
 
-        let line_segments = self
-            .buffer
-            .iter()
-            .enumerate()
-            .map(|(x, y)| Point::new(x as i32, scaler.map(y) as i32))
-            .tuple_windows()
-            .map(|(from, to)| Line::new(from, to).into_styled(LINE_STYLE));
+            Text::with_baseline(&str_buffer, status_loc, NORMAL_TEXT, Baseline::Top)
+                .draw(display)?;
+        }
 
-        for line in line_segments {
-            line.draw(display)?;
+        let area = display.bounding_box();
+        match self.secondary_trace.as_ref() {
+            Some(secondary) => {
+                let half = Size::new(area.size.width, area.size.height / 2);
+                let bottom_half = area.top_left + Point::new(0, half.height as i32);
+
+                self.trace
+                    .draw(Rectangle::new(area.top_left, half), display)?;
+                secondary.draw(Rectangle::new(bottom_half, half), display)?;
+            }
+            None => self.trace.draw(area, display)?,
         }
 
         Ok(())
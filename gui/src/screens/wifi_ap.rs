@@ -10,20 +10,24 @@ use embedded_text::TextBox;
 use ufmt::uwrite;
 
 use crate::{
-    screens::{menu_style, BOTTOM_CENTERED_TEXTBOX, NORMAL_TEXT},
+    screens::{menu_style, qr::QrCodeScreen, BOTTOM_CENTERED_TEXTBOX, NORMAL_TEXT},
     widgets::wifi_access_point::WifiAccessPointState,
 };
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ApMenuEvents {
     Exit,
+    ShowQrCode,
 }
 
 pub struct WifiApScreen {
     pub menu: Menu<
         &'static str,
         SingleTouch,
-        chain! { MenuItem<&'static str, ApMenuEvents, (), true> },
+        chain! {
+            MenuItem<&'static str, ApMenuEvents, (), true>,
+            MenuItem<&'static str, ApMenuEvents, (), true>
+        },
         ApMenuEvents,
         AnimatedPosition,
         AnimatedTriangle,
@@ -31,16 +35,36 @@ pub struct WifiApScreen {
     >,
     pub state: WifiAccessPointState,
     pub timeout: Option<u8>,
+    /// Whether to show the network credentials as a QR code instead of the menu.
+    pub show_qr_code: bool,
+    network_name: heapless::String<16>,
+    passphrase: heapless::String<16>,
+    qr_code: heapless::String<64>,
 }
 
 impl WifiApScreen {
-    pub fn new() -> Self {
+    pub fn new(network_name: &str, passphrase: &str) -> Self {
+        // Neither the network name nor the passphrase contain characters that need escaping.
+        let mut qr_code = heapless::String::new();
+        unwrap!(uwrite!(
+            &mut qr_code,
+            "WIFI:T:WPA;S:{};P:{};;",
+            network_name,
+            passphrase
+        )
+        .map_err(|_| ()));
+
         Self {
             menu: Menu::with_style("WiFi Config", menu_style())
                 .add_item("Exit", (), |_| ApMenuEvents::Exit)
+                .add_item("Show QR code", (), |_| ApMenuEvents::ShowQrCode)
                 .build(),
             state: WifiAccessPointState::NotConnected,
             timeout: None,
+            show_qr_code: false,
+            network_name: unwrap!(heapless::String::try_from(network_name).map_err(|_| ())),
+            passphrase: unwrap!(heapless::String::try_from(passphrase).map_err(|_| ())),
+            qr_code,
         }
     }
 }
@@ -51,17 +75,28 @@ impl Drawable for WifiApScreen {
 
     #[inline]
     fn draw<DT: DrawTarget<Color = BinaryColor>>(&self, display: &mut DT) -> Result<(), DT::Error> {
-        self.menu.draw(display)?;
+        if self.show_qr_code {
+            return QrCodeScreen {
+                message: &self.qr_code,
+                countdown: self.timeout.map(usize::from),
+                invert: false,
+            }
+            .draw(display);
+        }
 
-        // TODO: use actual network name
-        let network_name = "Card/IO";
+        self.menu.draw(display)?;
 
         let mut text = heapless::String::<128>::new();
         if self.state == WifiAccessPointState::Connected {
             unwrap!(text.push_str("Connected. Open site at 192.168.2.1"));
         } else {
-            unwrap!(text.push_str("No client connected. Look for a network called "));
-            unwrap!(text.push_str(network_name));
+            unwrap!(uwrite!(
+                &mut text,
+                "Network: {}\nPassword: {}",
+                self.network_name.as_str(),
+                self.passphrase.as_str()
+            )
+            .map_err(|_| ()));
             if let Some(timeout) = self.timeout {
                 unwrap!(uwrite!(&mut text, "\nExiting in {}", timeout).map_err(|_| ()));
             }
@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 use embedded_hal_async::{
     delay::DelayNs as AsyncDelayNs,
@@ -7,6 +7,9 @@ use embedded_hal_async::{
 
 pub mod ll;
 
+#[cfg(feature = "norfs")]
+mod storage;
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConfigError<I>
@@ -150,6 +153,17 @@ pub struct LearnedParams {
     pub full_cap_nom: ll::FullCapNomFieldSet,
 }
 
+impl LearnedParams {
+    /// The bit of the Cycles register that should trigger saving the learned parameters.
+    const SAVE_CYCLES_BIT: u16 = 1 << 2;
+
+    /// Returns whether the learned parameters should be saved, based on the previously seen and
+    /// the current value of the Cycles register.
+    pub fn should_save(previous_cycles: u16, cycles: u16) -> bool {
+        (previous_cycles ^ cycles) & Self::SAVE_CYCLES_BIT != 0
+    }
+}
+
 pub struct Max17055<I> {
     driver: ll::Max17055<ll::Max17055I2cInterface<I>>,
     config: DesignData,
@@ -170,10 +184,13 @@ where
 {
     /// This function implements the Initialize Registers to Recommended Configuration
     /// procedure from the datasheet.
+    ///
+    /// Returns `true` if the device has been reset and was reconfigured. In this case, previously
+    /// saved learned parameters should be restored.
     pub async fn load_initial_config_async(
         &mut self,
         delay: &mut impl AsyncDelayNs,
-    ) -> Result<(), ConfigError<I>> {
+    ) -> Result<bool, ConfigError<I>> {
         if self
             .driver
             .status()
@@ -183,7 +200,7 @@ where
             .por()
             != ll::PowerOnReset::Reset
         {
-            return Ok(());
+            return Ok(false);
         }
 
         while self
@@ -251,7 +268,7 @@ where
                 .por()
                 == ll::PowerOnReset::NoReset
             {
-                return Ok(());
+                return Ok(true);
             }
 
             delay.delay_ms(1).await;
@@ -381,6 +398,12 @@ where
         Ok((raw / 100) as u16)
     }
 
+    /// Returns the raw value of the Cycles register, in % of a full cycle.
+    pub async fn read_raw_cycles(&mut self) -> Result<u16, I::Error> {
+        let reg = self.driver.cycles().read_async().await?;
+        Ok(reg.cycles_percentage())
+    }
+
     /// Returns the cell voltage in μV.
     pub async fn read_vcell(&mut self) -> Result<u32, I::Error> {
         let reg = self.driver.vcell().read_async().await?;
@@ -509,3 +532,112 @@ where
         Err(ConfigError::Verify)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock, Transaction},
+    };
+
+    const ADDR: u8 = 0x36;
+
+    fn read_reg(reg: u8, value: u16) -> [Transaction; 4] {
+        [
+            Transaction::transaction_start(ADDR),
+            Transaction::write(ADDR, vec![reg]),
+            Transaction::read(ADDR, value.to_le_bytes().to_vec()),
+            Transaction::transaction_end(ADDR),
+        ]
+    }
+
+    fn write_reg(reg: u8, value: u16) -> [Transaction; 4] {
+        [
+            Transaction::transaction_start(ADDR),
+            Transaction::write(ADDR, vec![reg]),
+            Transaction::write(ADDR, value.to_le_bytes().to_vec()),
+            Transaction::transaction_end(ADDR),
+        ]
+    }
+
+    fn learned_params() -> LearnedParams {
+        let mut params = LearnedParams::default();
+        params.rcomp0.set_bits(0x1234);
+        params.temp_co.set_bits(0x2345);
+        params.full_cap_rep.set_capacity(0x0640);
+        params.cycles.set_cycles_percentage(0x0105);
+        params.full_cap_nom.set_capacity(0x0650);
+        params
+    }
+
+    fn read_learned_params_transactions() -> Vec<Transaction> {
+        [
+            read_reg(0x38, 0x1234),
+            read_reg(0x39, 0x2345),
+            read_reg(0x10, 0x0640),
+            read_reg(0x17, 0x0105),
+            read_reg(0x23, 0x0650),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn learned_params_are_saved_when_cycles_bit_2_toggles() {
+        assert!(!LearnedParams::should_save(0, 3));
+        assert!(LearnedParams::should_save(3, 4));
+        assert!(!LearnedParams::should_save(4, 7));
+        assert!(LearnedParams::should_save(7, 8));
+        assert!(!LearnedParams::should_save(8, 8));
+    }
+
+    #[test]
+    fn initial_config_is_skipped_without_reset() {
+        let mut i2c = Mock::new(&read_reg(0x00, 0x0000));
+        let mut fg = Max17055::new(i2c.clone(), DesignData::default());
+
+        let reset = block_on(fg.load_initial_config_async(&mut NoopDelay)).unwrap();
+        assert!(!reset);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn read_learned_params() {
+        let mut i2c = Mock::new(&read_learned_params_transactions());
+        let mut fg = Max17055::new(i2c.clone(), DesignData::default());
+
+        let params = block_on(fg.read_learned_params()).unwrap();
+        assert_eq!(params, learned_params());
+
+        i2c.done();
+    }
+
+    #[test]
+    fn restore_learned_params() {
+        let mut expectations = [
+            write_reg(0x38, 0x1234),
+            write_reg(0x39, 0x2345),
+            write_reg(0x23, 0x0650),
+            read_reg(0x23, 0x0650),
+            // MixSOC = 50%
+            read_reg(0x0D, 0x3200),
+            // MixCap = MixSOC * FullCapNom / 25600
+            write_reg(0x0F, 808),
+            write_reg(0x10, 0x0640),
+            write_reg(0x46, 0x0C80),
+            write_reg(0x45, 0x0650 / 16),
+            write_reg(0x17, 0x0105),
+        ]
+        .concat();
+        expectations.extend(read_learned_params_transactions());
+
+        let mut i2c = Mock::new(&expectations);
+        let mut fg = Max17055::new(i2c.clone(), DesignData::default());
+
+        block_on(fg.restore_learned_params(&learned_params(), &mut NoopDelay)).unwrap();
+
+        i2c.done();
+    }
+}
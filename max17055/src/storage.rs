@@ -0,0 +1,54 @@
+use embedded_io_async::{Read, Write};
+use norfs::storable::{LoadError, Loadable, Storable};
+
+use crate::LearnedParams;
+
+impl Loadable for LearnedParams {
+    async fn load<R: Read>(reader: &mut R) -> Result<Self, LoadError<R::Error>> {
+        let mut params = Self::default();
+
+        params.rcomp0.set_bits(u16::load(reader).await?);
+        params.temp_co.set_bits(u16::load(reader).await?);
+        params.full_cap_rep.set_capacity(u16::load(reader).await?);
+        params
+            .cycles
+            .set_cycles_percentage(u16::load(reader).await?);
+        params.full_cap_nom.set_capacity(u16::load(reader).await?);
+
+        Ok(params)
+    }
+}
+
+impl Storable for LearnedParams {
+    async fn store<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        self.rcomp0.bits().store(writer).await?;
+        self.temp_co.bits().store(writer).await?;
+        self.full_cap_rep.capacity().store(writer).await?;
+        self.cycles.cycles_percentage().store(writer).await?;
+        self.full_cap_nom.capacity().store(writer).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn learned_params_round_trip() {
+        let mut params = LearnedParams::default();
+        params.rcomp0.set_bits(0x1234);
+        params.temp_co.set_bits(0x2345);
+        params.full_cap_rep.set_capacity(0x0640);
+        params.cycles.set_cycles_percentage(0x0105);
+        params.full_cap_nom.set_capacity(0x0650);
+
+        let mut buffer = [0; 16];
+        block_on(params.store(&mut &mut buffer[..])).unwrap();
+
+        let loaded = block_on(LearnedParams::load(&mut &buffer[..])).unwrap();
+        assert_eq!(loaded, params);
+    }
+}
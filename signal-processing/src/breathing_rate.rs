@@ -0,0 +1,200 @@
+//! Breathing rate estimation from an impedance pneumography signal.
+//!
+//! The input is decimated to a low rate, its baseline is removed by two cascaded high-pass
+//! stages (so that linear drift does not leave an offset) and breaths are detected as rising
+//! crossings of an amplitude-relative hysteresis band.
+
+use core::num::NonZeroU8;
+
+use crate::filter::{median::MedianFilter, Filter};
+
+#[allow(unused_imports)]
+use crate::compat::*;
+
+/// The rate the input signal is decimated to, in Hz.
+const DECIMATED_RATE: f32 = 10.0;
+
+/// Time constant of the baseline and amplitude trackers, in seconds.
+const TRACKING_TIME: f32 = 8.0;
+
+/// Hysteresis band relative to the tracked amplitude.
+const HYSTERESIS: f32 = 0.4;
+
+/// Accepted breath periods, in seconds. Corresponds to 3..40 breaths per minute.
+const MIN_PERIOD: f32 = 1.5;
+const MAX_PERIOD: f32 = 20.0;
+
+pub struct BreathingRateCalculator {
+    decimation: usize,
+    accumulated: f32,
+    accumulated_count: usize,
+
+    alpha: f32,
+    baseline: Option<f32>,
+    drift: f32,
+    amplitude: f32,
+
+    above: bool,
+    time: u32,
+    prev_breath: Option<u32>,
+    median: MedianFilter<3>,
+    current_rate: Option<NonZeroU8>,
+}
+
+impl BreathingRateCalculator {
+    pub fn new(fs: f32) -> Self {
+        Self {
+            decimation: ((fs / DECIMATED_RATE) as usize).max(1),
+            accumulated: 0.0,
+            accumulated_count: 0,
+
+            alpha: 1.0 / (TRACKING_TIME * DECIMATED_RATE),
+            baseline: None,
+            drift: 0.0,
+            amplitude: 0.0,
+
+            above: false,
+            time: 0,
+            prev_breath: None,
+            median: MedianFilter::new(),
+            current_rate: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.accumulated = 0.0;
+        self.accumulated_count = 0;
+        self.baseline = None;
+        self.drift = 0.0;
+        self.amplitude = 0.0;
+        self.above = false;
+        self.time = 0;
+        self.prev_breath = None;
+        self.median.clear();
+        self.current_rate = None;
+    }
+
+    pub fn update(&mut self, sample: f32) {
+        self.accumulated += sample;
+        self.accumulated_count += 1;
+
+        if self.accumulated_count < self.decimation {
+            return;
+        }
+
+        let sample = self.accumulated / self.accumulated_count as f32;
+        self.accumulated = 0.0;
+        self.accumulated_count = 0;
+
+        self.update_decimated(sample);
+    }
+
+    fn update_decimated(&mut self, sample: f32) {
+        self.time += 1;
+
+        let baseline = self.baseline.get_or_insert(sample);
+        let detrended = sample - *baseline;
+        *baseline += detrended * self.alpha;
+
+        // A ramp leaves a constant offset after the first stage, remove that, too.
+        let signal = detrended - self.drift;
+        self.drift += signal * self.alpha;
+
+        self.amplitude += (signal.abs() - self.amplitude) * self.alpha;
+
+        let threshold = self.amplitude * HYSTERESIS;
+        if self.above {
+            if signal < -threshold {
+                self.above = false;
+            }
+        } else if signal > threshold {
+            self.above = true;
+            self.on_breath();
+        }
+
+        if let Some(prev) = self.prev_breath {
+            if (self.time - prev) as f32 > MAX_PERIOD * DECIMATED_RATE {
+                // No breath detected for a long time, the last estimate is no longer valid.
+                self.prev_breath = None;
+                self.median.clear();
+                self.current_rate = None;
+            }
+        }
+    }
+
+    fn on_breath(&mut self) {
+        if let Some(prev) = self.prev_breath {
+            let period = (self.time - prev) as f32 / DECIMATED_RATE;
+
+            if period < MIN_PERIOD {
+                // Probably noise, ignore this crossing.
+                return;
+            }
+
+            let raw = 60.0 / period;
+            let rate = self.median.update(raw).unwrap_or(raw);
+
+            self.current_rate = NonZeroU8::new(rate.round() as u8);
+        }
+
+        self.prev_breath = Some(self.time);
+    }
+
+    /// Returns the current breathing rate in breaths per minute.
+    #[inline]
+    pub fn current_rate(&self) -> Option<NonZeroU8> {
+        self.current_rate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed(calculator: &mut BreathingRateCalculator, fs: f32, bpm: f32, seconds: f32) {
+        let samples = (fs * seconds) as usize;
+        for i in 0..samples {
+            let t = i as f32 / fs;
+            let breath = (core::f32::consts::TAU * bpm / 60.0 * t).sin();
+            // Impedance changes sit on top of a large, slowly drifting offset.
+            calculator.update(0.5 + 0.0005 * t + 0.002 * breath);
+        }
+    }
+
+    #[test]
+    fn no_rate_without_breathing() {
+        let mut calculator = BreathingRateCalculator::new(1000.0);
+
+        for _ in 0..60_000 {
+            calculator.update(0.5);
+        }
+
+        assert_eq!(calculator.current_rate(), None);
+    }
+
+    #[test]
+    fn detects_breathing_rate() {
+        for bpm in [6.0, 12.0, 15.0, 20.0, 30.0] {
+            let mut calculator = BreathingRateCalculator::new(1000.0);
+
+            feed(&mut calculator, 1000.0, bpm, 60.0);
+
+            let rate = calculator.current_rate().map(|r| r.get() as f32);
+            let rate = rate.unwrap_or_else(|| panic!("No rate detected for {bpm}"));
+            assert!((rate - bpm).abs() <= 1.0, "{rate} != {bpm}");
+        }
+    }
+
+    #[test]
+    fn rate_expires_when_breathing_stops() {
+        let mut calculator = BreathingRateCalculator::new(1000.0);
+
+        feed(&mut calculator, 1000.0, 15.0, 60.0);
+        assert!(calculator.current_rate().is_some());
+
+        for _ in 0..30_000 {
+            calculator.update(0.53);
+        }
+        assert_eq!(calculator.current_rate(), None);
+    }
+}
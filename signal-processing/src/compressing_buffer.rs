@@ -3,6 +3,9 @@
 //! This buffer tries to compress a sequence of i32 values by storing the varint-encoded
 //! difference from the last. This is useful for storing a sequence of values that are
 //! close to each other, such as a sequence of samples from a sensor.
+//!
+//! Multi-channel data is stored interleaved, with a separate difference encoder for each
+//! channel.
 
 use core::{fmt::Debug, slice};
 
@@ -10,6 +13,8 @@ use embedded_io::{Read, ReadExactError, Write};
 
 use crate::buffer::Buffer;
 
+pub mod header;
+
 #[derive(Clone, Copy, Default)]
 pub struct EkgFormat {
     previous: i32,
@@ -77,9 +82,69 @@ impl EkgFormat {
     }
 }
 
+/// The maximum number of channels an interleaved stream can contain.
+pub const MAX_CHANNELS: usize = 2;
+
+/// Interleaved multi-channel stream. Each channel is difference-encoded on its own, so a
+/// single-channel stream is identical to [`EkgFormat`].
+#[derive(Clone, Copy)]
+pub struct InterleavedFormat {
+    channels: [EkgFormat; MAX_CHANNELS],
+    channel_count: usize,
+    current: usize,
+}
+
+impl InterleavedFormat {
+    /// Format version of streams with more than one channel. The version byte is followed by
+    /// the channel count.
+    pub const VERSION: u8 = 1;
+
+    /// Sample rate of streams with a format version that does not record it, in samples/sec.
+    pub const DEFAULT_SAMPLE_RATE: u16 = 1000;
+
+    /// Creates a new stream. `channel_count` must be between 1 and [`MAX_CHANNELS`].
+    pub const fn new(channel_count: usize) -> Self {
+        Self {
+            channels: [EkgFormat::new(); MAX_CHANNELS],
+            channel_count,
+            current: 0,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    /// Returns whether the next sample belongs to the first channel.
+    pub fn is_frame_start(&self) -> bool {
+        self.current == 0
+    }
+
+    fn advance(&mut self) {
+        self.current += 1;
+        if self.current == self.channel_count {
+            self.current = 0;
+        }
+    }
+
+    pub fn write<W: Write>(&mut self, sample: i32, writer: &mut W) -> Result<usize, W::Error> {
+        let bytes = self.channels[self.current].write(sample, writer)?;
+        self.advance();
+        Ok(bytes)
+    }
+
+    pub fn read<R: Read>(&mut self, reader: &mut R) -> Result<Option<i32>, R::Error> {
+        let sample = self.channels[self.current].read(reader)?;
+        if sample.is_some() {
+            self.advance();
+        }
+        Ok(sample)
+    }
+}
+
 pub struct CompressingBuffer<const N: usize> {
-    reader: EkgFormat,
-    writer: EkgFormat,
+    reader: InterleavedFormat,
+    writer: InterleavedFormat,
     element_count: usize,
 
     buffer: Buffer<u8, N, true>,
@@ -112,19 +177,34 @@ impl<const N: usize> CompressingBuffer<N> {
 
     pub const fn new() -> Self {
         Self {
-            reader: EkgFormat::new(),
-            writer: EkgFormat::new(),
+            reader: InterleavedFormat::new(1),
+            writer: InterleavedFormat::new(1),
             element_count: 0,
             buffer: Buffer::EMPTY,
         }
     }
 
+    /// Changes the number of interleaved channels. Clears the buffer.
+    pub fn set_channel_count(&mut self, channel_count: usize) {
+        assert!(channel_count > 0 && channel_count <= MAX_CHANNELS);
+
+        self.reader = InterleavedFormat::new(channel_count);
+        self.writer = InterleavedFormat::new(channel_count);
+        self.element_count = 0;
+        self.buffer.clear();
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.writer.channel_count()
+    }
+
+    /// Pushes a single sample. Samples of different channels must be pushed in order.
     pub fn push(&mut self, item: i32) {
         let mut buffer = [0u8; 8];
         let bytes = unwrap!(self.writer.write(item, &mut &mut buffer[..]));
 
         while self.space() < bytes {
-            if self.pop().is_none() {
+            if self.pop_frame().is_none() {
                 return;
             }
         }
@@ -146,6 +226,15 @@ impl<const N: usize> CompressingBuffer<N> {
         sample
     }
 
+    /// Removes the oldest sample of every channel. Returns the first channel's sample.
+    fn pop_frame(&mut self) -> Option<i32> {
+        let first = self.pop()?;
+        while !self.reader.is_frame_start() {
+            self.pop()?;
+        }
+        Some(first)
+    }
+
     pub fn capacity(&self) -> usize {
         N
     }
@@ -158,6 +247,11 @@ impl<const N: usize> CompressingBuffer<N> {
         self.len() == 0
     }
 
+    /// Returns the number of samples stored per channel.
+    pub fn frame_count(&self) -> usize {
+        self.element_count / self.channel_count()
+    }
+
     pub fn byte_count(&self) -> usize {
         self.buffer.len()
     }
@@ -167,10 +261,7 @@ impl<const N: usize> CompressingBuffer<N> {
     }
 
     pub fn clear(&mut self) {
-        self.element_count = 0;
-        self.reader.previous = 0;
-        self.writer.previous = 0;
-        self.buffer.clear();
+        self.set_channel_count(self.channel_count());
     }
 
     pub fn as_slices(&self) -> (&[u8], &[u8]) {
@@ -285,4 +376,70 @@ mod test {
 
         assert_eq!(&output[output.len() - 4..], [32, 0, -6, 32]);
     }
+
+    #[test]
+    fn single_channel_interleaved_format_matches_ekg_format() {
+        let mut plain = [0; 32];
+        let mut interleaved = [0; 32];
+
+        let mut plain_format = EkgFormat::new();
+        let mut interleaved_format = InterleavedFormat::new(1);
+
+        let mut plain_writer = &mut plain[..];
+        let mut interleaved_writer = &mut interleaved[..];
+        for sample in [0, 5, -1000, 70000, 3] {
+            plain_format.write(sample, &mut plain_writer).unwrap();
+            interleaved_format
+                .write(sample, &mut interleaved_writer)
+                .unwrap();
+        }
+
+        assert_eq!(plain, interleaved);
+    }
+
+    #[test]
+    fn interleaved_channels_are_encoded_separately() {
+        let mut buffer = CompressingBuffer::<100>::new();
+        buffer.set_channel_count(2);
+
+        for i in 0..10 {
+            buffer.push(100_000 + i);
+            buffer.push(-100_000 - i);
+        }
+
+        assert_eq!(buffer.frame_count(), 10);
+        // The first frame needs 3 bytes for each channel, the rest are small differences.
+        assert_eq!(buffer.byte_count(), 6 + 18);
+
+        for i in 0..10 {
+            assert_eq!(buffer.pop(), Some(100_000 + i));
+            assert_eq!(buffer.pop(), Some(-100_000 - i));
+        }
+    }
+
+    #[test]
+    fn overwriting_interleaved_keeps_frames_aligned() {
+        let mut buffer = CompressingBuffer::<100>::new();
+        buffer.set_channel_count(2);
+
+        for input in 0..500 {
+            buffer.push(6 * input);
+            buffer.push(-1000 * input);
+        }
+
+        assert_eq!(buffer.len() % 2, 0);
+
+        let mut previous = None;
+        while let Some(ch1) = buffer.pop() {
+            let ch2 = buffer.pop().unwrap();
+            let input = ch1 / 6;
+
+            assert_eq!(ch2, -1000 * input);
+            if let Some(previous) = previous {
+                assert_eq!(input, previous + 1);
+            }
+            previous = Some(input);
+        }
+        assert_eq!(previous, Some(499));
+    }
 }
@@ -0,0 +1,656 @@
+//! Self-describing measurement header
+//!
+//! A stored measurement starts with a format version byte, followed by a version-specific
+//! header and the difference-encoded samples. The upload body uses the same layout, except the
+//! version is a little endian `u32`.
+//!
+//! Older formats only describe the channel count; [`MeasurementHeader::read`] migrates them by
+//! filling in the implied values and leaving everything else unknown. Version 2 was never
+//! released.
+//!
+//! Version 3 header layout, all values little endian:
+//!
+//! | Field                | Type          | Notes                                    |
+//! |----------------------|---------------|------------------------------------------|
+//! | header length        | `u16`         | number of header bytes after this field  |
+//! | channel count        | `u8`          |                                          |
+//! | channel kinds        | `u8` each     | one per channel, see [`ChannelKind`]     |
+//! | sample rate          | `u16`         | samples/sec                              |
+//! | gain                 | `u8`          | 0 if unknown                             |
+//! | start time           | `u64`         | seconds since the Unix epoch, 0 if unknown |
+//! | device serial        | `[u8; 6]`     | all zero if unknown                      |
+//! | firmware commit      | `u8` + bytes  | length-prefixed ASCII                    |
+//! | high-pass cutoff     | `u16`         | mHz, 0 if disabled                       |
+//! | power line frequency | `u8`          | Hz, 0 if disabled                        |
+//! | lead-off events      | `u8` + events | count, then `u32` frame + `u8` status    |
+//! | events truncated     | `u8`          | 1 if status changes were not recorded    |
+//! | session id           | `u32`         | 0 if the recording is a single file      |
+//! | segment index        | `u32`         | position of the file within the session  |
+//! | dropped frames       | `u32`         | frames lost right before this segment    |
+//!
+//! Readers must skip header bytes they don't understand, so fields can be appended without a
+//! version change.
+
+use core::slice;
+
+use embedded_io::{Read, ReadExactError, Write};
+
+use super::{EkgFormat, InterleavedFormat, MAX_CHANNELS};
+
+/// The maximum length of the firmware commit identifier.
+pub const MAX_COMMIT_LEN: usize = 16;
+
+/// The maximum number of lead-off events a header can hold.
+pub const MAX_LEAD_OFF_EVENTS: usize = 32;
+
+/// Lead connection status bits.
+pub mod lead_status {
+    pub const CH1_POSITIVE: u8 = 1 << 0;
+    pub const CH1_NEGATIVE: u8 = 1 << 1;
+    pub const CH2_POSITIVE: u8 = 1 << 2;
+    pub const CH2_NEGATIVE: u8 = 1 << 3;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HeaderError<E> {
+    Io(E),
+    UnexpectedEof,
+    UnknownVersion(u8),
+    Invalid,
+}
+
+impl<E> From<ReadExactError<E>> for HeaderError<E> {
+    fn from(value: ReadExactError<E>) -> Self {
+        match value {
+            ReadExactError::UnexpectedEof => HeaderError::UnexpectedEof,
+            ReadExactError::Other(e) => HeaderError::Io(e),
+        }
+    }
+}
+
+/// What a channel measures. Older formats only contain ECG channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelKind {
+    #[default]
+    Ecg,
+    /// Thoracic impedance, used to measure respiration.
+    Impedance,
+}
+
+impl ChannelKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ChannelKind::Ecg),
+            1 => Some(ChannelKind::Impedance),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            ChannelKind::Ecg => 0,
+            ChannelKind::Impedance => 1,
+        }
+    }
+}
+
+/// A change of the lead connection status.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeadOffEvent {
+    /// Index of the first frame with the new status.
+    pub frame: u32,
+    /// Connected leads, see [`lead_status`].
+    pub status: u8,
+}
+
+impl LeadOffEvent {
+    const ENCODED_LEN: usize = 5;
+}
+
+/// Identifies a part of a recording that was stored as multiple consecutive segments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Session {
+    /// Random, non-zero identifier shared by every segment of the recording.
+    pub id: u32,
+    /// Index of the segment, starting from 0.
+    pub segment: u32,
+    /// Frames lost between the previous segment and this one, because the segment was full
+    /// before the previous one was stored.
+    pub dropped_frames: u32,
+}
+
+impl Session {
+    const ENCODED_LEN: usize = 12;
+}
+
+/// Filters applied for display and heart rate detection. The stored samples are not filtered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterSettings {
+    /// Cutoff frequency of the baseline wander filter in mHz, 0 if disabled.
+    pub high_pass_cutoff_millihertz: u16,
+    /// Frequency of the power line interference filter in Hz, 0 if disabled.
+    pub power_line_frequency: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MeasurementHeader {
+    pub channel_count: u8,
+    /// Only the first `channel_count` entries are used.
+    pub channel_kinds: [ChannelKind; MAX_CHANNELS],
+    /// Samples/sec.
+    pub sample_rate: u16,
+    /// ADC gain, 0 if unknown.
+    pub gain: u8,
+    /// Seconds since the Unix epoch.
+    pub start_time: Option<u64>,
+    pub serial: Option<[u8; 6]>,
+    pub filters: FilterSettings,
+    /// Set if the recording is split into multiple files.
+    pub session: Option<Session>,
+    commit: [u8; MAX_COMMIT_LEN],
+    commit_len: u8,
+    lead_off_events: [LeadOffEvent; MAX_LEAD_OFF_EVENTS],
+    lead_off_event_count: u8,
+    lead_off_events_truncated: bool,
+}
+
+impl MeasurementHeader {
+    /// Format version of streams that begin with a complete header.
+    pub const VERSION: u8 = 3;
+
+    /// The maximum number of bytes [`Self::write`] produces, including the version byte.
+    pub const MAX_ENCODED_LEN: usize = 1
+        + 2
+        + 1
+        + MAX_CHANNELS
+        + 2
+        + 1
+        + 8
+        + 6
+        + 1
+        + MAX_COMMIT_LEN
+        + 2
+        + 1
+        + 1
+        + MAX_LEAD_OFF_EVENTS * LeadOffEvent::ENCODED_LEN
+        + 1
+        + Session::ENCODED_LEN;
+
+    pub const fn new(channel_count: u8, sample_rate: u16) -> Self {
+        Self {
+            channel_count,
+            channel_kinds: [ChannelKind::Ecg; MAX_CHANNELS],
+            sample_rate,
+            gain: 0,
+            start_time: None,
+            serial: None,
+            filters: FilterSettings {
+                high_pass_cutoff_millihertz: 0,
+                power_line_frequency: 0,
+            },
+            session: None,
+            commit: [0; MAX_COMMIT_LEN],
+            commit_len: 0,
+            lead_off_events: [LeadOffEvent {
+                frame: 0,
+                status: 0,
+            }; MAX_LEAD_OFF_EVENTS],
+            lead_off_event_count: 0,
+            lead_off_events_truncated: false,
+        }
+    }
+
+    /// Sets the firmware commit identifier. Longer identifiers are truncated.
+    pub fn set_commit(&mut self, commit: &str) {
+        let len = commit.len().min(MAX_COMMIT_LEN);
+        self.commit.fill(0);
+        self.commit[..len].copy_from_slice(&commit.as_bytes()[..len]);
+        self.commit_len = len as u8;
+    }
+
+    /// Returns the firmware commit identifier, or an empty string if unknown.
+    pub fn commit(&self) -> &str {
+        core::str::from_utf8(&self.commit[..self.commit_len as usize]).unwrap_or("")
+    }
+
+    pub fn lead_off_events(&self) -> &[LeadOffEvent] {
+        &self.lead_off_events[..self.lead_off_event_count as usize]
+    }
+
+    /// Returns whether status changes were dropped because the header was full.
+    pub fn lead_off_events_truncated(&self) -> bool {
+        self.lead_off_events_truncated
+    }
+
+    /// Records a lead status change. If the header is full, the last event is replaced, so the
+    /// initial and the latest status are kept, and the events are marked as truncated.
+    pub fn push_lead_off_event(&mut self, event: LeadOffEvent) {
+        let count = self.lead_off_event_count as usize;
+        if count == MAX_LEAD_OFF_EVENTS {
+            self.lead_off_events[count - 1] = event;
+            self.lead_off_events_truncated = true;
+        } else {
+            self.lead_off_events[count] = event;
+            self.lead_off_event_count += 1;
+        }
+    }
+
+    /// Drops the first `frames` frames of the recording. Events are shifted accordingly and the
+    /// status at the new first frame is kept.
+    pub fn drop_frames(&mut self, frames: u32) {
+        let events = self.lead_off_events();
+
+        // The last event before the cut describes the status at the new first frame.
+        let first_kept = events
+            .iter()
+            .rposition(|event| event.frame <= frames)
+            .unwrap_or(0);
+
+        let count = events.len() - first_kept;
+        self.lead_off_events.copy_within(first_kept.., 0);
+        self.lead_off_events[count..].fill(LeadOffEvent::default());
+        self.lead_off_event_count = count as u8;
+        if count <= 1 {
+            // Only the current status is left, nothing is missing.
+            self.lead_off_events_truncated = false;
+        }
+
+        for event in self.lead_off_events[..count].iter_mut() {
+            event.frame = event.frame.saturating_sub(frames);
+        }
+    }
+
+    /// Returns the kinds of the recorded channels.
+    pub fn channel_kinds(&self) -> &[ChannelKind] {
+        &self.channel_kinds[..self.channel_count as usize]
+    }
+
+    fn header_len(&self) -> usize {
+        1 + self.channel_count as usize
+            + 2
+            + 1
+            + 8
+            + 6
+            + 1
+            + self.commit_len as usize
+            + 2
+            + 1
+            + 1
+            + self.lead_off_events().len() * LeadOffEvent::ENCODED_LEN
+            + 1
+            + Session::ENCODED_LEN
+    }
+
+    /// Returns the number of bytes [`Self::write`] produces.
+    pub fn encoded_len(&self) -> usize {
+        1 + 2 + self.header_len()
+    }
+
+    /// Writes the version byte and the header.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        writer.write_all(&[Self::VERSION])?;
+        self.write_header(writer)
+    }
+
+    /// Writes the header without the version byte.
+    pub fn write_header<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        writer.write_all(&(self.header_len() as u16).to_le_bytes())?;
+
+        writer.write_all(&[self.channel_count])?;
+        for kind in self.channel_kinds() {
+            writer.write_all(&[kind.to_u8()])?;
+        }
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&[self.gain])?;
+        writer.write_all(&self.start_time.unwrap_or(0).to_le_bytes())?;
+        writer.write_all(&self.serial.unwrap_or([0; 6]))?;
+
+        writer.write_all(&[self.commit_len])?;
+        writer.write_all(self.commit().as_bytes())?;
+
+        writer.write_all(&self.filters.high_pass_cutoff_millihertz.to_le_bytes())?;
+        writer.write_all(&[self.filters.power_line_frequency])?;
+
+        writer.write_all(&[self.lead_off_event_count])?;
+        for event in self.lead_off_events() {
+            writer.write_all(&event.frame.to_le_bytes())?;
+            writer.write_all(&[event.status])?;
+        }
+        writer.write_all(&[self.lead_off_events_truncated as u8])?;
+
+        let session = self.session.unwrap_or(Session {
+            id: 0,
+            segment: 0,
+            dropped_frames: 0,
+        });
+        writer.write_all(&session.id.to_le_bytes())?;
+        writer.write_all(&session.segment.to_le_bytes())?;
+        writer.write_all(&session.dropped_frames.to_le_bytes())?;
+
+        Ok(())
+    }
+
+    /// Reads the version byte and the header, leaving `reader` at the first sample.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, HeaderError<R::Error>> {
+        let version = read_u8(reader)?;
+        Self::read_with_version(version, reader)
+    }
+
+    /// Reads the header of a stream with the given format version, leaving `reader` at the
+    /// first sample.
+    pub fn read_with_version<R: Read>(
+        version: u8,
+        reader: &mut R,
+    ) -> Result<Self, HeaderError<R::Error>> {
+        match version {
+            EkgFormat::VERSION => Ok(Self::new(1, InterleavedFormat::DEFAULT_SAMPLE_RATE)),
+            InterleavedFormat::VERSION => {
+                let channel_count = read_channel_count(reader)?;
+                Ok(Self::new(
+                    channel_count,
+                    InterleavedFormat::DEFAULT_SAMPLE_RATE,
+                ))
+            }
+            Self::VERSION => Self::read_v3(reader),
+            other => Err(HeaderError::UnknownVersion(other)),
+        }
+    }
+
+    fn read_v3<R: Read>(reader: &mut R) -> Result<Self, HeaderError<R::Error>> {
+        let header_len = read_u16(reader)? as usize;
+        let mut reader = Limited {
+            inner: reader,
+            remaining: header_len,
+        };
+
+        let channel_count = read_channel_count(&mut reader)?;
+        let mut channel_kinds = [ChannelKind::Ecg; MAX_CHANNELS];
+        for kind in channel_kinds[..channel_count as usize].iter_mut() {
+            *kind = ChannelKind::from_u8(read_u8(&mut reader)?).ok_or(HeaderError::Invalid)?;
+        }
+        let sample_rate = read_u16(&mut reader)?;
+
+        let mut header = Self::new(channel_count, sample_rate);
+        header.channel_kinds = channel_kinds;
+        header.gain = read_u8(&mut reader)?;
+
+        let mut start_time = [0; 8];
+        reader.read_exact(&mut start_time)?;
+        header.start_time = Some(u64::from_le_bytes(start_time)).filter(|t| *t != 0);
+
+        let mut serial = [0; 6];
+        reader.read_exact(&mut serial)?;
+        header.serial = Some(serial).filter(|s| *s != [0; 6]);
+
+        let commit_len = read_u8(&mut reader)? as usize;
+        if commit_len > MAX_COMMIT_LEN {
+            return Err(HeaderError::Invalid);
+        }
+        reader.read_exact(&mut header.commit[..commit_len])?;
+        header.commit_len = commit_len as u8;
+
+        header.filters.high_pass_cutoff_millihertz = read_u16(&mut reader)?;
+        header.filters.power_line_frequency = read_u8(&mut reader)?;
+
+        let event_count = read_u8(&mut reader)? as usize;
+        if event_count > MAX_LEAD_OFF_EVENTS {
+            return Err(HeaderError::Invalid);
+        }
+        for _ in 0..event_count {
+            let mut frame = [0; 4];
+            reader.read_exact(&mut frame)?;
+            let status = read_u8(&mut reader)?;
+
+            header.push_lead_off_event(LeadOffEvent {
+                frame: u32::from_le_bytes(frame),
+                status,
+            });
+        }
+        header.lead_off_events_truncated = read_u8(&mut reader)? != 0;
+
+        let id = read_u32(&mut reader)?;
+        let segment = read_u32(&mut reader)?;
+        let dropped_frames = read_u32(&mut reader)?;
+        header.session = (id != 0).then_some(Session {
+            id,
+            segment,
+            dropped_frames,
+        });
+
+        // Skip fields added by newer firmware.
+        while reader.remaining > 0 {
+            read_u8(&mut reader)?;
+        }
+
+        Ok(header)
+    }
+}
+
+/// Reader that returns EOF after a number of bytes.
+struct Limited<'a, R> {
+    inner: &'a mut R,
+    remaining: usize,
+}
+
+impl<R: Read> embedded_io::ErrorType for Limited<'_, R> {
+    type Error = R::Error;
+}
+
+impl<R: Read> Read for Limited<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, HeaderError<R::Error>> {
+    let mut byte = 0;
+    reader.read_exact(slice::from_mut(&mut byte))?;
+    Ok(byte)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, HeaderError<R::Error>> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, HeaderError<R::Error>> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_channel_count<R: Read>(reader: &mut R) -> Result<u8, HeaderError<R::Error>> {
+    match read_u8(reader)? {
+        count @ 1..=2 => Ok(count),
+        _ => Err(HeaderError::Invalid),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn full_header() -> MeasurementHeader {
+        let mut header = MeasurementHeader::new(2, 500);
+        header.channel_kinds = [ChannelKind::Impedance, ChannelKind::Ecg];
+        header.gain = 6;
+        header.start_time = Some(1_700_000_000);
+        header.serial = Some([1, 2, 3, 4, 5, 6]);
+        header.filters = FilterSettings {
+            high_pass_cutoff_millihertz: 750,
+            power_line_frequency: 50,
+        };
+        header.set_commit("abc1234");
+        header.push_lead_off_event(LeadOffEvent {
+            frame: 0,
+            status: lead_status::CH1_POSITIVE | lead_status::CH1_NEGATIVE,
+        });
+        header.push_lead_off_event(LeadOffEvent {
+            frame: 1234,
+            status: lead_status::CH1_NEGATIVE,
+        });
+        header.session = Some(Session {
+            id: 0xC0FFEE,
+            segment: 3,
+            dropped_frames: 250,
+        });
+        header
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = full_header();
+
+        let mut buffer = [0; MeasurementHeader::MAX_ENCODED_LEN + 3];
+        let mut writer = &mut buffer[..];
+        header.write(&mut writer).unwrap();
+        assert_eq!(writer.len(), buffer.len() - header.encoded_len());
+
+        // Samples follow the header
+        buffer[header.encoded_len()..][..3].copy_from_slice(&[7, 8, 9]);
+
+        let mut reader = &buffer[..];
+        assert_eq!(MeasurementHeader::read(&mut reader).unwrap(), header);
+        assert_eq!(&reader[..3], &[7, 8, 9]);
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        let header = full_header();
+
+        let mut buffer = [0; MeasurementHeader::MAX_ENCODED_LEN + 2];
+        header.write(&mut &mut buffer[..]).unwrap();
+
+        // Pretend a newer firmware appended two bytes to the header.
+        let len = header.encoded_len();
+        let header_len = u16::from_le_bytes([buffer[1], buffer[2]]) + 2;
+        buffer[1..3].copy_from_slice(&header_len.to_le_bytes());
+        buffer[len..len + 2].copy_from_slice(&[0xAA, 0xBB]);
+
+        let mut reader = &buffer[..len + 2];
+        assert_eq!(MeasurementHeader::read(&mut reader).unwrap(), header);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn legacy_formats_are_migrated() {
+        let mut reader = &[EkgFormat::VERSION, 0x10][..];
+        assert_eq!(
+            MeasurementHeader::read(&mut reader).unwrap(),
+            MeasurementHeader::new(1, 1000)
+        );
+        assert_eq!(reader, &[0x10]);
+
+        let mut reader = &[InterleavedFormat::VERSION, 2, 0x10][..];
+        assert_eq!(
+            MeasurementHeader::read(&mut reader).unwrap(),
+            MeasurementHeader::new(2, 1000)
+        );
+        assert_eq!(reader, &[0x10]);
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        assert_eq!(
+            MeasurementHeader::read(&mut &[42][..]),
+            Err(HeaderError::UnknownVersion(42))
+        );
+        assert_eq!(
+            MeasurementHeader::read(&mut &[2, 1, 0xF4, 0x01][..]),
+            Err(HeaderError::UnknownVersion(2))
+        );
+        assert_eq!(
+            MeasurementHeader::read(&mut &[InterleavedFormat::VERSION, 0][..]),
+            Err(HeaderError::Invalid)
+        );
+        assert_eq!(
+            MeasurementHeader::read(&mut &[MeasurementHeader::VERSION, 10, 0, 1][..]),
+            Err(HeaderError::UnexpectedEof)
+        );
+        assert_eq!(
+            MeasurementHeader::read(&mut &[MeasurementHeader::VERSION, 10, 0, 1, 7][..]),
+            Err(HeaderError::Invalid)
+        );
+    }
+
+    #[test]
+    fn dropping_frames_keeps_current_status() {
+        let mut header = MeasurementHeader::new(1, 1000);
+        for (frame, status) in [(0, 3), (100, 1), (200, 3), (300, 2)] {
+            header.push_lead_off_event(LeadOffEvent { frame, status });
+        }
+
+        header.drop_frames(150);
+        assert_eq!(
+            header.lead_off_events(),
+            &[
+                LeadOffEvent {
+                    frame: 0,
+                    status: 1
+                },
+                LeadOffEvent {
+                    frame: 50,
+                    status: 3
+                },
+                LeadOffEvent {
+                    frame: 150,
+                    status: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn initial_and_latest_status_are_kept_when_full() {
+        let mut header = MeasurementHeader::new(1, 1000);
+        for frame in 0..MAX_LEAD_OFF_EVENTS as u32 {
+            header.push_lead_off_event(LeadOffEvent { frame, status: 0 });
+        }
+        assert!(!header.lead_off_events_truncated());
+
+        header.push_lead_off_event(LeadOffEvent {
+            frame: 100,
+            status: 3,
+        });
+
+        let events = header.lead_off_events();
+        assert_eq!(events.len(), MAX_LEAD_OFF_EVENTS);
+        assert_eq!(events[0].frame, 0);
+        assert_eq!(
+            events[MAX_LEAD_OFF_EVENTS - 2].frame,
+            MAX_LEAD_OFF_EVENTS as u32 - 2
+        );
+        assert_eq!(
+            events[MAX_LEAD_OFF_EVENTS - 1],
+            LeadOffEvent {
+                frame: 100,
+                status: 3
+            }
+        );
+        assert!(header.lead_off_events_truncated());
+
+        let mut buffer = [0; MeasurementHeader::MAX_ENCODED_LEN];
+        header.write(&mut &mut buffer[..]).unwrap();
+        assert_eq!(MeasurementHeader::read(&mut &buffer[..]).unwrap(), header);
+
+        header.drop_frames(100);
+        assert_eq!(
+            header.lead_off_events(),
+            &[LeadOffEvent {
+                frame: 0,
+                status: 3
+            }]
+        );
+        assert!(!header.lead_off_events_truncated());
+    }
+}
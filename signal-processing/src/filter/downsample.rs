@@ -91,3 +91,67 @@ impl Filter for DownSampler {
         }
     }
 }
+
+/// Cascade of up to `N` [`DownSampler`] stages, of which the first `stages` are used. Decimates
+/// by `2^stages`.
+pub struct MultiStageDownSampler<const N: usize> {
+    stages: [DownSampler; N],
+    active: usize,
+}
+
+impl<const N: usize> MultiStageDownSampler<N> {
+    #[inline]
+    pub const fn new(stages: usize) -> Self {
+        assert!(stages <= N);
+
+        Self {
+            stages: [DownSampler::DEFAULT; N],
+            active: stages,
+        }
+    }
+}
+
+impl<const N: usize> Filter for MultiStageDownSampler<N> {
+    #[inline]
+    fn clear(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.clear();
+        }
+    }
+
+    #[inline]
+    fn update(&mut self, sample: f32) -> Option<f32> {
+        self.stages[..self.active]
+            .iter_mut()
+            .try_fold(sample, |sample, stage| stage.update(sample))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output_count(downsampler: &mut impl Filter, samples: usize) -> usize {
+        (0..samples)
+            .filter_map(|i| downsampler.update(i as f32))
+            .count()
+    }
+
+    #[test]
+    fn stages_set_decimation_factor() {
+        assert_eq!(
+            output_count(&mut MultiStageDownSampler::<4>::new(0), 1000),
+            1000
+        );
+
+        let mut one_stage = MultiStageDownSampler::<4>::new(1);
+        let mut four_stages = MultiStageDownSampler::<4>::new(4);
+
+        let one = output_count(&mut one_stage, 10_000);
+        let four = output_count(&mut four_stages, 10_000);
+
+        // The FIR filters need to fill up before producing output.
+        assert!((4950..=5000).contains(&one), "{one}");
+        assert!((580..=625).contains(&four), "{four}");
+    }
+}
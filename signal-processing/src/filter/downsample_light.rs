@@ -3,12 +3,38 @@ use crate::filter::{
     Filter,
 };
 
+/// Low-pass filters and decimates the ECG signal to 125Hz.
 pub struct DownsamplerLight {
     filter: Iir<'static, LowPass, 2>,
+    period: u8,
     counter: u8,
 }
 
 impl DownsamplerLight {
+    pub const ECG_SR_250HZ: DownsamplerLight = DownsamplerLight {
+        #[rustfmt::skip]
+        filter: macros::designfilt!(
+            "lowpassiir",
+            "FilterOrder", 2,
+            "HalfPowerFrequency", 35,
+            "SampleRate", 250
+        ),
+        period: 1,
+        counter: 1,
+    };
+
+    pub const ECG_SR_500HZ: DownsamplerLight = DownsamplerLight {
+        #[rustfmt::skip]
+        filter: macros::designfilt!(
+            "lowpassiir",
+            "FilterOrder", 2,
+            "HalfPowerFrequency", 35,
+            "SampleRate", 500
+        ),
+        period: 3,
+        counter: 3,
+    };
+
     pub const ECG_SR_1000HZ: DownsamplerLight = DownsamplerLight {
         #[rustfmt::skip]
         filter: macros::designfilt!(
@@ -17,8 +43,21 @@ impl DownsamplerLight {
             "HalfPowerFrequency", 35,
             "SampleRate", 1000
         ),
+        period: 7,
         counter: 7,
     };
+
+    pub const ECG_SR_2000HZ: DownsamplerLight = DownsamplerLight {
+        #[rustfmt::skip]
+        filter: macros::designfilt!(
+            "lowpassiir",
+            "FilterOrder", 2,
+            "HalfPowerFrequency", 35,
+            "SampleRate", 2000
+        ),
+        period: 15,
+        counter: 15,
+    };
 }
 
 impl Filter for DownsamplerLight {
@@ -30,7 +69,7 @@ impl Filter for DownsamplerLight {
     fn update(&mut self, sample: f32) -> Option<f32> {
         let filtered = self.filter.update(sample)?;
         if self.counter == 0 {
-            self.counter = 7;
+            self.counter = self.period;
             Some(filtered)
         } else {
             self.counter -= 1;
@@ -13,6 +13,54 @@ pub mod precomputed {
     use super::{HighPass, Iir};
 
     pub const ALL_PASS: Iir<'static, HighPass, 2> = Iir::new(&[1.], &[]);
+    #[rustfmt::skip]
+    pub const WEAK_EKG_250HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
+        "highpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 0.75,
+        "SampleRate", 250
+    );
+
+    #[rustfmt::skip]
+    pub const STRONG_EKG_250HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
+        "highpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 1.5,
+        "SampleRate", 250
+    );
+
+    #[rustfmt::skip]
+    pub const HR_NOISE_FILTER_250HZ: Iir<'static, LowPass, 2> = macros::designfilt!(
+        "lowpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 20,
+        "SampleRate", 250
+    );
+
+    #[rustfmt::skip]
+    pub const WEAK_EKG_500HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
+        "highpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 0.75,
+        "SampleRate", 500
+    );
+
+    #[rustfmt::skip]
+    pub const STRONG_EKG_500HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
+        "highpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 1.5,
+        "SampleRate", 500
+    );
+
+    #[rustfmt::skip]
+    pub const HR_NOISE_FILTER_500HZ: Iir<'static, LowPass, 2> = macros::designfilt!(
+        "lowpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 20,
+        "SampleRate", 500
+    );
+
     #[rustfmt::skip]
     pub const WEAK_EKG_1000HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
         "highpassiir",
@@ -20,6 +68,7 @@ pub mod precomputed {
         "HalfPowerFrequency", 0.75,
         "SampleRate", 1000
     );
+
     #[rustfmt::skip]
     pub const STRONG_EKG_1000HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
         "highpassiir",
@@ -29,12 +78,36 @@ pub mod precomputed {
     );
 
     #[rustfmt::skip]
-    pub const HR_NOISE_FILTER: Iir<'static, LowPass, 2> = macros::designfilt!(
+    pub const HR_NOISE_FILTER_1000HZ: Iir<'static, LowPass, 2> = macros::designfilt!(
         "lowpassiir",
         "FilterOrder", 2,
         "HalfPowerFrequency", 20,
         "SampleRate", 1000
     );
+
+    #[rustfmt::skip]
+    pub const WEAK_EKG_2000HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
+        "highpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 0.75,
+        "SampleRate", 2000
+    );
+
+    #[rustfmt::skip]
+    pub const STRONG_EKG_2000HZ: Iir<'static, HighPass, 2> = macros::designfilt!(
+        "highpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 1.5,
+        "SampleRate", 2000
+    );
+
+    #[rustfmt::skip]
+    pub const HR_NOISE_FILTER_2000HZ: Iir<'static, LowPass, 2> = macros::designfilt!(
+        "lowpassiir",
+        "FilterOrder", 2,
+        "HalfPowerFrequency", 20,
+        "SampleRate", 2000
+    );
 }
 
 pub trait IirFilter {
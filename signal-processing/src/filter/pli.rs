@@ -228,27 +228,66 @@ impl<ADB, const N_FS: usize> PowerLineFilter<ADB, Iir<'static, HighPass, 2>, N_F
 where
     ADB: adaptation_blocking::AdaptationBlockingTrait,
 {
+    #[inline]
+    pub fn new_250sps(frequencies: [f32; N_FS]) -> Self {
+        #[rustfmt::skip]
+        const FILTER: Iir<HighPass, 2> = macros::designfilt!(
+            "highpassiir",
+            "FilterOrder", 2,
+            "HalfPowerFrequency", 50,
+            "SampleRate", 250
+        );
+        Self::with_filters(250.0, frequencies, FILTER, FILTER)
+    }
+
+    #[inline]
+    pub fn new_500sps(frequencies: [f32; N_FS]) -> Self {
+        #[rustfmt::skip]
+        const FILTER: Iir<HighPass, 2> = macros::designfilt!(
+            "highpassiir",
+            "FilterOrder", 2,
+            "HalfPowerFrequency", 50,
+            "SampleRate", 500
+        );
+        Self::with_filters(500.0, frequencies, FILTER, FILTER)
+    }
+
     #[inline]
     pub fn new_1ksps(frequencies: [f32; N_FS]) -> Self {
         #[rustfmt::skip]
-        const SIGNATURE_FILTER: Iir<HighPass, 2> = macros::designfilt!(
+        const FILTER: Iir<HighPass, 2> = macros::designfilt!(
             "highpassiir",
             "FilterOrder", 2,
             "HalfPowerFrequency", 50,
             "SampleRate", 1000
         );
+        Self::with_filters(1000.0, frequencies, FILTER, FILTER)
+    }
+
+    #[inline]
+    pub fn new_2ksps(frequencies: [f32; N_FS]) -> Self {
         #[rustfmt::skip]
-        const ERROR_FILTER: Iir<HighPass, 2> = macros::designfilt!(
+        const FILTER: Iir<HighPass, 2> = macros::designfilt!(
             "highpassiir",
             "FilterOrder", 2,
             "HalfPowerFrequency", 50,
-            "SampleRate", 1000
+            "SampleRate", 2000
         );
+        Self::with_filters(2000.0, frequencies, FILTER, FILTER)
+    }
+
+    #[inline(always)]
+    fn with_filters(
+        fs: f32,
+        frequencies: [f32; N_FS],
+        signature_filter: Iir<'static, HighPass, 2>,
+        error_filter: Iir<'static, HighPass, 2>,
+    ) -> Self {
         Self {
-            consts: Constants::new(1000.0),
-            cores: frequencies.map(|f| FilterCore::new(1000.0, f, SIGNATURE_FILTER)),
-            adaptation_blocking: ADB::new(1000.0),
-            error_filter: ERROR_FILTER,
+            consts: Constants::new(fs),
+            cores: frequencies.map(|f| FilterCore::new(fs, f, signature_filter.clone())),
+            adaptation_blocking: ADB::new(fs),
+            error_filter,
             sample_idx: 0,
         }
     }
@@ -7,6 +7,7 @@ extern crate alloc;
 mod fmt;
 
 pub mod battery;
+pub mod breathing_rate;
 pub mod buffer;
 pub mod compressing_buffer;
 pub mod filter;
@@ -0,0 +1,497 @@
+//! Index of the stored measurements.
+//!
+//! Measurements are stored in `meas.N` files. The catalog records their metadata and upload
+//! state, so that finding, uploading and cleaning up measurements doesn't need to scan the
+//! directory.
+//!
+//! The catalog file is updated after every change. A measurement is added as
+//! [`EntryState::Writing`] before its file is written, so that an interrupted write can be
+//! cleaned up on the next mount. A missing or unreadable catalog is rebuilt from the directory.
+
+use config_types::types::RetentionPolicy;
+use embedded_io_async::{Read, Write};
+use norfs::{
+    medium::StorageMedium,
+    storable::{LoadError, Loadable, Storable},
+    OnCollision, Storage, StorageError,
+};
+use signal_processing::compressing_buffer::header::MeasurementHeader;
+use ufmt::uwrite;
+
+pub const CATALOG_FILE: &str = "meas_index";
+
+/// The maximum number of measurements the catalog can hold.
+pub const MAX_ENTRIES: usize = 128;
+
+/// Space measurements can't use, so that the config, the catalog and the fuel gauge parameters
+/// can always be rewritten.
+pub const RESERVED_SPACE: usize = 16 * 1024;
+
+const CATALOG_VERSION: u8 = 0;
+
+pub type MeasurementFileName = heapless::String<16>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EntryState {
+    /// The file is being written, or the write was interrupted.
+    Writing = 0,
+    /// The measurement is waiting to be uploaded.
+    Stored = 1,
+    /// The measurement has been uploaded, the file is only kept until space is needed.
+    Uploaded = 2,
+}
+
+impl Loadable for EntryState {
+    async fn load<R: Read>(reader: &mut R) -> Result<Self, LoadError<R::Error>> {
+        let data = match u8::load(reader).await? {
+            0 => Self::Writing,
+            1 => Self::Stored,
+            2 => Self::Uploaded,
+            _ => return Err(LoadError::InvalidValue),
+        };
+
+        Ok(data)
+    }
+}
+
+impl Storable for EntryState {
+    async fn store<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        (*self as u8).store(writer).await
+    }
+}
+
+/// Describes a recording, independent of where it's stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RecordingInfo {
+    /// Seconds since the Unix epoch.
+    pub start_time: Option<u64>,
+    /// Length of the recording in milliseconds, 0 if unknown.
+    pub duration_ms: u32,
+}
+
+impl RecordingInfo {
+    pub fn new(header: &MeasurementHeader, frame_count: usize) -> Self {
+        Self {
+            start_time: header.start_time,
+            duration_ms: (frame_count as u64 * 1000 / header.sample_rate as u64) as u32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CatalogEntry {
+    /// The measurement is stored in `meas.{index}`.
+    pub index: u32,
+    pub state: EntryState,
+    pub info: RecordingInfo,
+    /// Size of the file in bytes.
+    pub size: u32,
+}
+
+impl CatalogEntry {
+    pub fn file_name(&self) -> MeasurementFileName {
+        file_name(self.index)
+    }
+}
+
+impl Loadable for CatalogEntry {
+    async fn load<R: Read>(reader: &mut R) -> Result<Self, LoadError<R::Error>> {
+        let index = u32::load(reader).await?;
+        let state = EntryState::load(reader).await?;
+        let start_time = u64::load(reader).await?;
+        let duration_ms = u32::load(reader).await?;
+        let size = u32::load(reader).await?;
+
+        Ok(Self {
+            index,
+            state,
+            info: RecordingInfo {
+                start_time: (start_time != 0).then_some(start_time),
+                duration_ms,
+            },
+            size,
+        })
+    }
+}
+
+impl Storable for CatalogEntry {
+    async fn store<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        self.index.store(writer).await?;
+        self.state.store(writer).await?;
+        self.info.start_time.unwrap_or(0).store(writer).await?;
+        self.info.duration_ms.store(writer).await?;
+        self.size.store(writer).await?;
+
+        Ok(())
+    }
+}
+
+fn file_name(index: u32) -> MeasurementFileName {
+    let mut name = MeasurementFileName::new();
+    unwrap!(uwrite!(&mut name, "meas.{}", index));
+    name
+}
+
+pub struct Catalog {
+    next_index: u32,
+    /// Entries in the order the measurements were stored.
+    entries: heapless::Vec<CatalogEntry, MAX_ENTRIES>,
+}
+
+impl Catalog {
+    const fn new() -> Self {
+        Self {
+            next_index: 0,
+            entries: heapless::Vec::new(),
+        }
+    }
+
+    /// Loads the catalog and cleans up interrupted writes. If the catalog can't be read, it is
+    /// rebuilt from the stored files.
+    pub async fn load<M>(storage: &mut Storage<M>) -> Self
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        let loaded = match storage.read(CATALOG_FILE).await {
+            Ok(mut file) => match file.read_loadable::<Catalog>(storage).await {
+                Ok(catalog) => Some(catalog),
+                Err(e) => {
+                    warn!("Failed to read measurement catalog: {:?}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("Failed to load measurement catalog: {:?}", e);
+                None
+            }
+        };
+
+        let mut catalog = match loaded {
+            Some(catalog) => catalog,
+            None => Self::rebuild(storage).await,
+        };
+
+        let interrupted = catalog
+            .entries
+            .iter()
+            .filter(|entry| entry.state == EntryState::Writing)
+            .count();
+        if interrupted > 0 {
+            warn!("Removing {} incomplete measurements", interrupted);
+            for entry in catalog.entries.iter() {
+                if entry.state == EntryState::Writing {
+                    // The file may not have been created.
+                    _ = storage.delete(&entry.file_name()).await;
+                }
+            }
+            catalog
+                .entries
+                .retain(|entry| entry.state != EntryState::Writing);
+            if let Err(e) = catalog.save(storage).await {
+                warn!("Failed to save measurement catalog: {:?}", e);
+            }
+        }
+
+        catalog
+    }
+
+    /// Creates a catalog of the `meas.N` files. Upload states are lost, every measurement is
+    /// considered not uploaded.
+    async fn rebuild<M>(storage: &mut Storage<M>) -> Self
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        info!("Rebuilding measurement catalog");
+
+        let mut catalog = Self::new();
+
+        let mut dir = match storage.read_dir().await {
+            Ok(dir) => dir,
+            Err(e) => {
+                warn!("Failed to open directory: {:?}", e);
+                return catalog;
+            }
+        };
+
+        let mut name_buffer = [0; 64];
+        loop {
+            let file = match dir.next(storage).await {
+                Ok(Some(file)) => file,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Failed to read directory: {:?}", e);
+                    break;
+                }
+            };
+
+            let index = match file.name(storage, &mut name_buffer).await {
+                Ok(name) => name
+                    .strip_prefix("meas.")
+                    .and_then(|index| index.parse::<u32>().ok()),
+                // not a measurement file, ignore
+                Err(StorageError::InsufficientBuffer) => None,
+                Err(e) => {
+                    warn!("Failed to read file name: {:?}", e);
+                    None
+                }
+            };
+            let Some(index) = index else {
+                continue;
+            };
+
+            let Ok(size) = file.size(storage).await else {
+                warn!("Failed to read size");
+                continue;
+            };
+
+            // The header tells when the measurement was recorded. The duration would need
+            // decoding the whole file, so it's left unknown.
+            let mut header = [0; MeasurementHeader::MAX_ENCODED_LEN];
+            let header_len = size.min(header.len());
+            let mut reader = file.open();
+            let start_time = match reader.read_all(storage, &mut header[..header_len]).await {
+                Ok(_) => MeasurementHeader::read(&mut &header[..header_len])
+                    .ok()
+                    .and_then(|header| header.start_time),
+                Err(_) => None,
+            };
+
+            let entry = CatalogEntry {
+                index,
+                state: EntryState::Stored,
+                info: RecordingInfo {
+                    start_time,
+                    duration_ms: 0,
+                },
+                size: size as u32,
+            };
+
+            catalog.next_index = catalog.next_index.max(index + 1);
+            if catalog.entries.push(entry).is_err() {
+                warn!("Measurement catalog is full, ignoring {}", index);
+            }
+        }
+
+        // Directory order is not recording order.
+        catalog.entries.sort_unstable_by_key(|entry| entry.index);
+
+        if let Err(e) = catalog.save(storage).await {
+            warn!("Failed to save measurement catalog: {:?}", e);
+        }
+
+        catalog
+    }
+
+    async fn save<M>(&self, storage: &mut Storage<M>) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        storage
+            .store_writer(CATALOG_FILE, self, OnCollision::Overwrite)
+            .await
+    }
+
+    pub fn entries(&self) -> &[CatalogEntry] {
+        &self.entries
+    }
+
+    /// Returns the measurements that have not been uploaded yet, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = &CatalogEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state == EntryState::Stored)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending().next().is_some()
+    }
+
+    fn position(&self, index: u32) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.index == index)
+    }
+
+    /// Adds an entry for a measurement that is about to be written to the returned file.
+    /// Deletes measurements according to `policy` if there is not enough space.
+    pub async fn begin_store<M>(
+        &mut self,
+        storage: &mut Storage<M>,
+        info: RecordingInfo,
+        size: usize,
+        policy: RetentionPolicy,
+    ) -> Result<CatalogEntry, StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        self.make_room(storage, size, policy).await?;
+
+        let entry = CatalogEntry {
+            index: self.next_index,
+            state: EntryState::Writing,
+            info,
+            size: size as u32,
+        };
+
+        if self.entries.push(entry).is_err() {
+            return Err(StorageError::InsufficientSpace);
+        }
+        self.next_index += 1;
+        self.save(storage).await?;
+
+        Ok(entry)
+    }
+
+    /// Marks the measurement written by [`Self::begin_store`] as complete, or removes it if
+    /// the write failed.
+    pub async fn finish_store<M>(
+        &mut self,
+        storage: &mut Storage<M>,
+        entry: &CatalogEntry,
+        written: bool,
+    ) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        if written {
+            self.set_state(storage, entry.index, EntryState::Stored)
+                .await
+        } else {
+            self.delete(storage, entry.index).await
+        }
+    }
+
+    pub async fn mark_uploaded<M>(
+        &mut self,
+        storage: &mut Storage<M>,
+        index: u32,
+    ) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        self.set_state(storage, index, EntryState::Uploaded).await
+    }
+
+    async fn set_state<M>(
+        &mut self,
+        storage: &mut Storage<M>,
+        index: u32,
+        state: EntryState,
+    ) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        if let Some(position) = self.position(index) {
+            self.entries[position].state = state;
+            self.save(storage).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the measurement file and its entry.
+    pub async fn delete<M>(
+        &mut self,
+        storage: &mut Storage<M>,
+        index: u32,
+    ) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        if let Err(e) = storage.delete(&file_name(index)).await {
+            // The entry is removed anyway, there is nothing else we can do with it.
+            warn!("Failed to delete measurement {}: {:?}", index, e);
+        }
+
+        if let Some(position) = self.position(index) {
+            self.entries.remove(position);
+            self.save(storage).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the measurement to delete when space is needed, if `policy` allows deleting
+    /// any. Uploaded measurements are deleted before the ones waiting for upload.
+    fn reclaimable(&self, policy: RetentionPolicy) -> Option<&CatalogEntry> {
+        let uploaded = || {
+            self.entries
+                .iter()
+                .find(|entry| entry.state == EntryState::Uploaded)
+        };
+
+        match policy {
+            RetentionPolicy::None => None,
+            RetentionPolicy::Uploaded => uploaded(),
+            RetentionPolicy::Oldest => uploaded().or(self.entries.first()),
+        }
+    }
+
+    /// Deletes measurements until `size` bytes and a catalog entry are available.
+    async fn make_room<M>(
+        &mut self,
+        storage: &mut Storage<M>,
+        size: usize,
+        policy: RetentionPolicy,
+    ) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        while self.entries.is_full() || storage.free_bytes() < size + RESERVED_SPACE {
+            let Some(oldest) = self.reclaimable(policy) else {
+                warn!("Not enough space for {} bytes", size);
+                return Err(StorageError::InsufficientSpace);
+            };
+
+            debug!("Deleting measurement {} to make room", oldest.index);
+            self.delete(storage, oldest.index).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Loadable for Catalog {
+    async fn load<R: Read>(reader: &mut R) -> Result<Self, LoadError<R::Error>> {
+        if u8::load(reader).await? != CATALOG_VERSION {
+            return Err(LoadError::InvalidValue);
+        }
+
+        let mut catalog = Self::new();
+        catalog.next_index = u32::load(reader).await?;
+
+        let count = u16::load(reader).await?;
+        for _ in 0..count {
+            let entry = CatalogEntry::load(reader).await?;
+            if catalog.entries.push(entry).is_err() {
+                return Err(LoadError::InvalidValue);
+            }
+        }
+
+        Ok(catalog)
+    }
+}
+
+impl Storable for Catalog {
+    async fn store<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        CATALOG_VERSION.store(writer).await?;
+        self.next_index.store(writer).await?;
+
+        (self.entries.len() as u16).store(writer).await?;
+        for entry in self.entries.iter() {
+            entry.store(writer).await?;
+        }
+
+        Ok(())
+    }
+}
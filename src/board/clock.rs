@@ -0,0 +1,52 @@
+use esp_hal::rtc_cntl::Rtc;
+
+/// Wall-clock time, kept by the RTC.
+///
+/// The RTC keeps counting in deep sleep and the time it was set to is retained in RTC memory, so
+/// the clock stays valid until the device loses power.
+pub struct WallClock {
+    rtc: Rtc<'static>,
+    synchronized: bool,
+}
+
+impl WallClock {
+    /// Until it's first set, the RTC counts from power-up. Anything before 2024-01-01 is
+    /// considered unset.
+    const MIN_VALID_TIMESTAMP: u64 = 1_704_067_200;
+
+    pub fn new(rtc: Rtc<'static>) -> Self {
+        Self {
+            rtc,
+            synchronized: false,
+        }
+    }
+
+    /// Returns the current time in microseconds since the Unix epoch, if known.
+    pub fn now_us(&self) -> Option<u64> {
+        let now = self.rtc.current_time_us();
+        (now / 1_000_000 >= Self::MIN_VALID_TIMESTAMP).then_some(now)
+    }
+
+    /// Returns the current time in seconds since the Unix epoch, if known.
+    pub fn now(&self) -> Option<u64> {
+        self.now_us().map(|now| now / 1_000_000)
+    }
+
+    /// Sets the current time in microseconds since the Unix epoch.
+    pub fn set_us(&mut self, now: u64) {
+        if let Some(old) = self.now_us() {
+            debug!("Adjusting clock by {}ms", (now as i64 - old as i64) / 1000);
+        }
+        self.rtc.set_current_time_us(now);
+        self.synchronized = true;
+    }
+
+    /// Returns whether the clock has been synchronized since boot.
+    pub fn is_synchronized(&self) -> bool {
+        self.synchronized
+    }
+
+    pub fn into_rtc(self) -> Rtc<'static> {
+        self.rtc
+    }
+}
@@ -0,0 +1,189 @@
+//! The secret the backend issues to the device when it registers.
+//!
+//! Requests to the backend are signed with HMAC-SHA256. The signed message is
+//!
+//! ```text
+//! {method}\n{path}\n{time}\n{X-Timestamp}\n{X-Upload-Offset}\n{X-Upload-Length}\n{hex(sha256(body))}
+//! ```
+//!
+//! where `path` is relative to the backend URL and `time` is the current Unix time in seconds.
+//! The values of the [`SIGNED_HEADERS`] are signed as sent, or as empty strings if the request
+//! doesn't have them. The time and the hex encoded signature are sent in the `X-Auth-Time` and
+//! `X-Auth-Signature` headers.
+//!
+//! The key is kept in its own partition, so formatting the storage doesn't unregister the device.
+//! The partition is not in the table of devices flashed before it was added, see "Partitions" in
+//! README.md for why that is safe.
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use hmac::{Hmac, Mac};
+use macros::partition;
+use norfs::medium::StorageMedium;
+use norfs_driver::medium::MediumError;
+use sha2::{Digest, Sha256};
+use ufmt::uwrite;
+
+#[cfg(feature = "esp32s3")]
+use norfs_esp32s3 as norfs_impl;
+
+#[cfg(feature = "esp32c6")]
+use norfs_esp32c6 as norfs_impl;
+
+use norfs_impl::{InternalPartition, SmallInternalDriver};
+
+use crate::board::clock::WallClock;
+
+pub const KEY_LEN: usize = 32;
+
+#[partition("devkey")]
+pub struct DeviceKeyPartition;
+
+/// Marks a written key. An erased partition means the device is not registered.
+const RECORD_MAGIC: [u8; 4] = *b"CKEY";
+
+/// The magic, the key and the CRC of the key.
+const RECORD_LEN: usize = RECORD_MAGIC.len() + KEY_LEN + 4;
+
+/// Request headers covered by the signature, in the order they are signed.
+pub const SIGNED_HEADERS: [&str; 3] = ["X-Timestamp", "X-Upload-Offset", "X-Upload-Length"];
+
+/// A hex encoded HMAC-SHA256.
+pub type Signature = heapless::String<64>;
+
+#[derive(Clone)]
+pub struct DeviceKey([u8; KEY_LEN]);
+
+impl DeviceKey {
+    /// Returns the stored key, or `None` if the device is not registered.
+    pub async fn load() -> Option<Self> {
+        let mut partition = SmallInternalDriver::new(DeviceKeyPartition);
+
+        let mut record = [0; RECORD_LEN];
+        if partition.read(0, 0, &mut record).await.is_err() {
+            warn!("Failed to read device key");
+            return None;
+        }
+
+        let (magic, rest) = record.split_at(RECORD_MAGIC.len());
+        let (key, crc) = rest.split_at(KEY_LEN);
+        if magic != RECORD_MAGIC {
+            return None;
+        }
+        if crc != checksum(key).to_le_bytes() {
+            warn!("Device key is corrupted");
+            return None;
+        }
+
+        Some(Self(unwrap!(key.try_into().ok())))
+    }
+
+    pub async fn save(&self) -> Result<(), MediumError> {
+        let mut record = [0; RECORD_LEN];
+        let (magic, rest) = record.split_at_mut(RECORD_MAGIC.len());
+        let (key, crc) = rest.split_at_mut(KEY_LEN);
+        magic.copy_from_slice(&RECORD_MAGIC);
+        key.copy_from_slice(&self.0);
+        crc.copy_from_slice(&checksum(&self.0).to_le_bytes());
+
+        let mut partition = SmallInternalDriver::new(DeviceKeyPartition);
+        partition.erase(0).await?;
+        partition.write(0, 0, &record).await
+    }
+
+    /// Parses the hex encoded key sent by the backend.
+    pub fn from_hex(hex: &[u8]) -> Option<Self> {
+        if hex.len() != 2 * KEY_LEN {
+            return None;
+        }
+
+        let mut key = [0; KEY_LEN];
+        for (byte, digits) in key.iter_mut().zip(hex.chunks_exact(2)) {
+            let digits = core::str::from_utf8(digits).ok()?;
+            *byte = u8::from_str_radix(digits, 16).ok()?;
+        }
+
+        Some(Self(key))
+    }
+
+    /// Signs a request. `headers` are the other headers of the request, the body is passed in
+    /// parts, concatenated they are what's sent.
+    pub fn sign(
+        &self,
+        method: &str,
+        path: &str,
+        time: &str,
+        headers: &[(&str, &str)],
+        body: &[&[u8]],
+    ) -> Signature {
+        let mut hasher = Sha256::new();
+        for part in body {
+            hasher.update(part);
+        }
+        let body_hash = hex(&hasher.finalize());
+
+        let mut mac = unwrap!(Hmac::<Sha256>::new_from_slice(&self.0).ok());
+        for part in [method, "\n", path, "\n", time, "\n"] {
+            mac.update(part.as_bytes());
+        }
+        for name in SIGNED_HEADERS {
+            let value = headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map_or("", |(_, value)| value);
+            mac.update(value.as_bytes());
+            mac.update(b"\n");
+        }
+        mac.update(body_hash.as_bytes());
+
+        hex(&mac.finalize().into_bytes())
+    }
+}
+
+fn checksum(key: &[u8]) -> u32 {
+    Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(key)
+}
+
+fn hex(bytes: &[u8]) -> Signature {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let mut out = Signature::new();
+    for byte in bytes {
+        _ = out.push(DIGITS[(byte >> 4) as usize] as char);
+        _ = out.push(DIGITS[(byte & 0xF) as usize] as char);
+    }
+    out
+}
+
+/// Authentication headers for a single request.
+pub struct AuthHeaders {
+    time: heapless::String<20>,
+    signature: Signature,
+}
+
+impl AuthHeaders {
+    /// Signs a request with the current time. `headers` are the other headers of the request.
+    /// Returns `None` if the clock is not set, the backend would reject the request.
+    pub fn new(
+        key: &DeviceKey,
+        clock: &WallClock,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[&[u8]],
+    ) -> Option<Self> {
+        let mut time = heapless::String::new();
+        unwrap!(uwrite!(&mut time, "{}", clock.now()?));
+
+        Some(Self {
+            signature: key.sign(method, path, &time, headers, body),
+            time,
+        })
+    }
+
+    pub fn headers(&self) -> [(&str, &str); 2] {
+        [
+            ("X-Auth-Time", self.time.as_str()),
+            ("X-Auth-Signature", self.signature.as_str()),
+        ]
+    }
+}
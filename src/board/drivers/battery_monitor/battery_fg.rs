@@ -1,7 +1,7 @@
 use embassy_time::{Delay, Duration, Ticker, Timer};
 use embedded_hal::digital::OutputPin;
 use embedded_hal_async::i2c::I2c;
-use max17055::Max17055;
+use max17055::{LearnedParams, Max17055};
 
 use crate::{task_control::TaskControlToken, Shared};
 
@@ -15,6 +15,9 @@ pub struct BatteryFgData {
 pub struct BatteryFg<I2C, EN> {
     pub fg: Max17055<I2C>,
     pub enable: EN,
+    /// Set when the fuel gauge has been reset and lost its learned parameters.
+    needs_restore: bool,
+    last_cycles: Option<u16>,
 }
 
 impl<I2C, EN> BatteryFg<I2C, EN>
@@ -23,19 +26,53 @@ where
     I2C: I2c,
 {
     pub fn new(fg: Max17055<I2C>, enable: EN) -> Self {
-        Self { fg, enable }
+        Self {
+            fg,
+            enable,
+            needs_restore: false,
+            last_cycles: None,
+        }
     }
 
     pub async fn enable(&mut self) -> Result<(), ()> {
         self.enable.set_high().map_err(|_| ())?;
         Timer::after(Duration::from_millis(10)).await;
-        self.fg
+        self.needs_restore = self
+            .fg
             .load_initial_config_async(&mut Delay)
             .await
             .map_err(|_| ())?;
         Ok(())
     }
 
+    /// Restores previously saved learned parameters, if the fuel gauge has lost them.
+    pub async fn restore_learned_params(&mut self, params: &LearnedParams) -> Result<(), ()> {
+        if !self.needs_restore {
+            return Ok(());
+        }
+
+        self.fg
+            .restore_learned_params(params, &mut Delay)
+            .await
+            .map_err(|_| ())?;
+        self.needs_restore = false;
+
+        Ok(())
+    }
+
+    /// Returns the learned parameters if they should be saved.
+    pub async fn poll_learned_params(&mut self) -> Result<Option<LearnedParams>, ()> {
+        let cycles = self.fg.read_raw_cycles().await.map_err(|_| ())?;
+        let previous = self.last_cycles.replace(cycles);
+
+        if !previous.is_some_and(|previous| LearnedParams::should_save(previous, cycles)) {
+            return Ok(None);
+        }
+
+        let params = self.fg.read_learned_params().await.map_err(|_| ())?;
+        Ok(Some(params))
+    }
+
     pub async fn read_data(&mut self) -> Result<BatteryFgData, ()> {
         let voltage_uv = self.fg.read_vcell().await.map_err(|_| ())?;
         let percentage = self.fg.read_reported_soc().await.map_err(|_| ())?;
@@ -71,6 +108,21 @@ pub async fn monitor_task_fg(
                     } else {
                         error!("Failed to read battery data");
                     }
+
+                    if let Some(params) = sensor.params_to_restore.take() {
+                        if sensor.restore_learned_params(&params).await.is_err() {
+                            error!("Failed to restore learned parameters");
+                        }
+                    }
+
+                    match sensor.poll_learned_params().await {
+                        Ok(Some(params)) => {
+                            debug!("Learned parameters changed");
+                            sensor.params_to_save = Some(params);
+                        }
+                        Ok(None) => {}
+                        Err(_) => error!("Failed to read learned parameters"),
+                    }
                 }
 
                 timer.next().await;
@@ -7,6 +7,7 @@ use alloc::rc::Rc;
 use embassy_sync::mutex::Mutex;
 use embedded_hal::digital::InputPin;
 use gui::screens::{BatteryInfo, ChargingState};
+use max17055::LearnedParams;
 
 pub mod battery_fg;
 
@@ -14,7 +15,7 @@ use crate::board::drivers::battery_monitor::battery_fg::{
     monitor_task_fg as monitor_task, BatteryFgData as BatteryData,
 };
 
-use config_types::LOW_BATTERY_PERCENTAGE;
+use config_types::{LOW_BATTERY_PERCENTAGE, MEASUREMENT_RESERVE_PERCENTAGE};
 
 use embassy_executor::Spawner;
 
@@ -26,6 +27,10 @@ struct BatteryState {
 pub struct BatterySensor {
     state: BatteryState,
     sensor: BatterySensorDriver,
+    /// Learned fuel gauge parameters loaded from storage, to be restored by the monitor task.
+    params_to_restore: Option<LearnedParams>,
+    /// Learned fuel gauge parameters read by the monitor task, to be written to storage.
+    params_to_save: Option<LearnedParams>,
 }
 
 impl BatterySensor {
@@ -70,6 +75,8 @@ where
             sensor: Rc::new(Mutex::new(BatterySensor {
                 state: BatteryState::default(),
                 sensor,
+                params_to_restore: None,
+                params_to_save: None,
             })),
             vbus_detect,
             charger_status,
@@ -125,10 +132,28 @@ where
             .unwrap_or(false)
     }
 
+    /// Returns whether the remaining charge is only enough to save a recording.
+    pub fn is_below_measurement_reserve(&mut self) -> bool {
+        self.last_battery_data()
+            .map(|data| data.percentage < MEASUREMENT_RESERVE_PERCENTAGE)
+            .unwrap_or(false)
+    }
+
     pub async fn sensor(&self) -> SharedGuard<'_, BatterySensor> {
         self.sensor.lock().await
     }
 
+    /// Hands saved learned parameters over to the monitor task. They are only written to the
+    /// fuel gauge if it has been reset.
+    pub async fn restore_learned_params(&self, params: LearnedParams) {
+        self.sensor.lock().await.params_to_restore = Some(params);
+    }
+
+    /// Returns learned parameters that have changed since the last call and should be saved.
+    pub async fn take_learned_params(&self) -> Option<LearnedParams> {
+        self.sensor.lock().await.params_to_save.take()
+    }
+
     pub async fn stop(self) {
         _ = self.signal.stop().await;
     }
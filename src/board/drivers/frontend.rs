@@ -1,4 +1,6 @@
-use ads129x::{ll, Ads129x, AdsConfigError, AdsData, ConfigRegisters};
+use core::convert::Infallible;
+
+use ads129x::{ll, Ads129x, AdsConfig, AdsConfigError, AdsData, SelfTestReport};
 use embassy_time::{Delay, Duration, Timer};
 use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_hal_async::{digital::Wait, spi::SpiDevice as AsyncSpiDevice};
@@ -44,97 +46,17 @@ where
     I: InputPin + Wait,
     O: OutputPin,
 {
-    fn config(&self) -> ConfigRegisters {
-        ConfigRegisters {
-            config1: {
-                let mut r = ll::Config1FieldSet::new();
-                r.set_data_rate(ll::DataRate::_1ksps);
-                r.set_sampling(ll::Sampling::Continuous);
-                r
-            },
-
-            config2: {
-                let mut r = ll::Config2FieldSet::new();
-                r.set_pdb_loff_comp(ll::Buffer::Enabled);
-                r.set_ref_voltage(ll::ReferenceVoltage::_2_42v);
-                r.set_clock_pin(ll::ClockPin::Disabled);
-                r.set_test_signal(ll::TestSignal::Disabled);
-                r
-            },
-
-            loff: {
-                let mut r = ll::LoffFieldSet::new();
-                r.set_comp_th(ll::ComparatorThreshold::_95);
-                r.set_leadoff_current(ll::LeadOffCurrent::_22nA);
-                r.set_leadoff_frequency(ll::LeadOffFrequency::Dc);
-                r
-            },
-
-            ch1set: {
-                let mut r = ll::Ch1setFieldSet::new();
-                r.set_enabled(ll::Channel::Enabled);
-                r.set_gain(ll::Gain::X1);
-                r.set_mux(ll::Ch1mux::Normal);
-                r
-            },
-
-            ch2set: {
-                let mut r = ll::Ch2setFieldSet::new();
-                r.set_enabled(ll::Channel::PowerDown);
-                r.set_gain(ll::Gain::X1);
-                r.set_mux(ll::Ch2mux::Shorted);
-                r
-            },
-
-            rldsens: {
-                let mut r = ll::RldSensFieldSet::new();
-                r.set_chop(ll::ChopFrequency::Fmod2);
-                r.set_pdb_rld(ll::Buffer::Enabled);
-                r.set_loff_sense(ll::Input::NotConnected);
-                r.set_rld2n(ll::Input::NotConnected);
-                r.set_rld2p(ll::Input::NotConnected);
-                r.set_rld1n(ll::Input::Connected);
-                r.set_rld1p(ll::Input::Connected);
-                r
-            },
-
-            loffsens: {
-                let mut r = ll::LoffSensFieldSet::new();
-                r.set_flip2(ll::CurrentDirection::Normal);
-                r.set_flip1(ll::CurrentDirection::Normal);
-                r.set_loff2n(ll::Input::NotConnected);
-                r.set_loff2p(ll::Input::NotConnected);
-                r.set_loff1n(ll::Input::Connected);
-                r.set_loff1p(ll::Input::Connected);
-                r
-            },
-
-            loffstat: {
-                let mut r = ll::LoffStatFieldSet::new();
-                r.set_clk_div(ll::ClockDivider::External512kHz);
-                r
-            },
-
-            resp1: ll::Resp1FieldSet::default(),
-            resp2: {
-                let mut r = ll::Resp2FieldSet::new();
-                r.set_rld_reference(ll::RldReference::MidSupply);
-                r
-            },
-
-            gpio: {
-                let mut r = ll::GpioFieldSet::new();
-                r.set_c2(ll::PinDirection::Input);
-                r.set_c1(ll::PinDirection::Output);
-                r.set_d1(ll::PinState::High); // disable touch detector circuitry
-                r
-            },
-        }
+    fn gpio_config(&self) -> ll::GpioFieldSet {
+        let mut r = ll::GpioFieldSet::new();
+        r.set_c2(ll::PinDirection::Input);
+        r.set_c1(ll::PinDirection::Output);
+        r.set_d1(ll::PinState::High); // disable touch detector circuitry
+        r
     }
 
     pub async fn enable_async(
         self,
-        config: impl Fn(&mut ConfigRegisters),
+        config: impl Fn(&mut AdsConfig),
     ) -> Result<PoweredFrontend<S, I, O>, (Self, AdsConfigError<S>)> {
         let mut frontend = PoweredFrontend {
             frontend: self,
@@ -170,6 +92,10 @@ where
     pub fn spi_mut(&mut self) -> &mut S {
         self.frontend.spi_mut()
     }
+
+    pub fn device_id(&self) -> Option<ll::DeviceId> {
+        self.frontend.device_id()
+    }
 }
 
 impl<S, I, O> PoweredFrontend<S, I, O>
@@ -178,10 +104,7 @@ where
     I: InputPin + Wait,
     O: OutputPin,
 {
-    async fn enable(
-        &mut self,
-        config: impl Fn(&mut ConfigRegisters),
-    ) -> Result<(), AdsConfigError<S>> {
+    async fn enable(&mut self, config: impl Fn(&mut AdsConfig)) -> Result<(), AdsConfigError<S>> {
         // Enable external clock if it is separately controlled.
         if let Some(clken) = self.frontend.clken.as_mut() {
             unwrap!(clken.set_high().ok());
@@ -220,8 +143,11 @@ where
             }
         }
 
-        let mut config_regs = self.frontend.config();
-        config(&mut config_regs);
+        let mut ads_config = AdsConfig::default();
+        config(&mut ads_config);
+
+        let mut config_regs = ads_config.render().map_err(AdsConfigError::Invalid)?;
+        config_regs.gpio = self.frontend.gpio_config();
         self.frontend.adc.apply_config_async(config_regs).await?;
 
         Ok(())
@@ -301,6 +227,18 @@ where
         self.touched
     }
 
+    /// Runs the ADC self test. The ADC configuration is lost, the frontend should be shut down
+    /// afterwards.
+    pub async fn self_test(&mut self) -> Result<SelfTestReport, AdsConfigError<S>>
+    where
+        I: Wait<Error = Infallible>,
+    {
+        self.frontend
+            .adc
+            .self_test_async(&mut self.frontend.drdy)
+            .await
+    }
+
     pub async fn shut_down(mut self) -> Frontend<S, I, O> {
         let _ = self.frontend.adc.stop_command_async().await;
         let _ = self.frontend.adc.reset_command_async().await;
@@ -4,14 +4,16 @@ use core::{
 };
 
 #[cfg(feature = "wifi")]
-use crate::{
-    board::wifi::{ap::Ap, sta::Sta, WifiDriver},
-    saved_measurement_exists,
+use crate::board::wifi::{
+    ap::{Ap, ApCredentials},
+    captive_portal::{AP_ADDRESS, AP_PREFIX_LEN},
+    sta::Sta,
+    WifiDriver,
 };
 use crate::{
     board::{
-        drivers::battery_monitor::BatteryMonitor, startup::Display, storage::FileSystem,
-        EcgFrontend,
+        clock::WallClock, drivers::battery_monitor::BatteryMonitor, startup::Display,
+        storage::FileSystem, EcgFrontend,
     },
     states::MESSAGE_MIN_DURATION,
 };
@@ -20,7 +22,7 @@ use display_interface::DisplayError;
 use embassy_executor::SendSpawner;
 
 #[cfg(feature = "wifi")]
-use embassy_net::{Config as NetConfig, Ipv4Address, Ipv4Cidr, StaticConfigV4};
+use embassy_net::{Config as NetConfig, Ipv4Cidr, StaticConfigV4};
 
 use embassy_time::{Duration, Instant, Timer};
 use embedded_graphics::Drawable;
@@ -34,6 +36,8 @@ use gui::{
 };
 use norfs::OnCollision;
 
+pub const LEARNED_PARAMS_FILE: &str = "fg_params";
+
 #[cfg(feature = "wifi")]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StaMode {
@@ -45,6 +49,7 @@ pub struct InnerContext {
     pub display: &'static mut Display,
     pub high_prio_spawner: SendSpawner,
     pub battery_monitor: BatteryMonitor<Input<'static>, Input<'static>>,
+    pub clock: WallClock,
     #[cfg(feature = "wifi")]
     pub wifi: &'static mut WifiDriver,
     pub config: &'static mut Config,
@@ -94,6 +99,25 @@ impl Context {
         }
     }
 
+    pub async fn save_learned_params(&mut self) {
+        let Some(params) = self.inner.battery_monitor.take_learned_params().await else {
+            return;
+        };
+
+        info!("Saving fuel gauge parameters");
+
+        if let Some(storage) = self.storage.as_mut() {
+            if let Err(e) = storage
+                .store_writer(LEARNED_PARAMS_FILE, &params, OnCollision::Overwrite)
+                .await
+            {
+                error!("Failed to save fuel gauge parameters: {:?}", e);
+            }
+        } else {
+            warn!("Storage unavailable");
+        }
+    }
+
     #[cfg(feature = "wifi")]
     pub async fn sta_has_work(&mut self) -> bool {
         // TODO: we can do a flag that is true on boot, so that entering the menu will always
@@ -102,8 +126,8 @@ impl Context {
         // it is in on-demand mode?
 
         if self.inner.sta_work_available.is_none() {
-            if let Some(storage) = self.storage.as_mut() {
-                if saved_measurement_exists(storage).await {
+            if let Some(storage) = self.storage.as_ref() {
+                if storage.catalog().has_pending() {
                     self.inner.sta_work_available = Some(true);
                 }
             }
@@ -132,6 +156,19 @@ impl Context {
 
         self.enable_sta(can_enable).await
     }
+
+    /// Returns the name and passphrase of the configuration access point. The passphrase is
+    /// generated and saved when first needed.
+    #[cfg(feature = "wifi")]
+    pub async fn ap_credentials(&mut self) -> ApCredentials {
+        if self.config.ap_passphrase.is_empty() {
+            let passphrase = ApCredentials::generate_passphrase();
+            self.update_config(|config| config.ap_passphrase = passphrase);
+            self.save_config().await;
+        }
+
+        ApCredentials::new(&self.config.ap_passphrase)
+    }
 }
 
 impl InnerContext {
@@ -191,7 +228,7 @@ impl InnerContext {
 
     #[cfg(feature = "wifi")]
     #[allow(unused)]
-    pub async fn enable_wifi_ap(&mut self) -> Option<Ap> {
+    pub async fn enable_wifi_ap(&mut self, credentials: ApCredentials) -> Option<Ap> {
         if !self.can_enable_wifi() {
             self.wifi.stop_if().await;
             return None;
@@ -199,18 +236,21 @@ impl InnerContext {
 
         let ap = self
             .wifi
-            .configure_ap(NetConfig::ipv4_static(StaticConfigV4 {
-                address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 2, 1), 24),
-                gateway: Some(Ipv4Address::new(192, 168, 2, 1)),
-                dns_servers: Default::default(),
-            }))
+            .configure_ap(
+                NetConfig::ipv4_static(StaticConfigV4 {
+                    address: Ipv4Cidr::new(AP_ADDRESS, AP_PREFIX_LEN),
+                    gateway: Some(AP_ADDRESS),
+                    dns_servers: Default::default(),
+                }),
+                credentials,
+            )
             .await;
 
         Some(ap)
     }
 
     #[cfg(feature = "wifi")]
-    pub async fn enable_wifi_ap_sta(&mut self) -> Option<(Ap, Sta)> {
+    pub async fn enable_wifi_ap_sta(&mut self, credentials: ApCredentials) -> Option<(Ap, Sta)> {
         if !self.can_enable_wifi() {
             self.wifi.stop_if().await;
             return None;
@@ -220,11 +260,12 @@ impl InnerContext {
             .wifi
             .configure_ap_sta(
                 NetConfig::ipv4_static(StaticConfigV4 {
-                    address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 2, 1), 24),
-                    gateway: Some(Ipv4Address::new(192, 168, 2, 1)),
+                    address: Ipv4Cidr::new(AP_ADDRESS, AP_PREFIX_LEN),
+                    gateway: Some(AP_ADDRESS),
                     dns_servers: Default::default(),
                 }),
                 NetConfig::dhcpv4(Default::default()),
+                credentials,
             )
             .await;
 
@@ -15,9 +15,13 @@
 )]
 pub mod hardware;
 
+pub mod catalog;
+pub mod clock;
+pub mod device_key;
 pub mod drivers;
 pub mod initialized;
-#[cfg(feature = "wifi")]
+// Without Wi-Fi, only confirming the running app is needed.
+#[cfg_attr(not(feature = "wifi"), allow(dead_code))]
 pub mod ota;
 pub mod startup;
 pub mod storage;
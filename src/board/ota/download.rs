@@ -0,0 +1,90 @@
+//! Progress of an interrupted firmware download.
+//!
+//! The downloaded part of the image stays in the update partition. Progress is saved periodically
+//! while downloading and when the connection is lost. The next download continues with a `Range`
+//! request if the backend still serves the same image, and the partition still holds what was
+//! written.
+
+use embedded_io_async::{Read, Write};
+use norfs::{
+    medium::StorageMedium,
+    storable::{LoadError, Loadable, Storable},
+    OnCollision, Storage, StorageError,
+};
+
+pub const DOWNLOAD_FILE: &str = "fw_download";
+
+/// The backend's ETag for an image, which names the commit it was built from.
+pub type ImageTag = heapless::String<64>;
+
+pub struct PartialDownload {
+    pub etag: ImageTag,
+    /// The number of bytes written to the update partition.
+    pub written: u32,
+    /// SHA-256 of the written bytes.
+    pub digest: [u8; 32],
+}
+
+impl PartialDownload {
+    pub async fn load<M>(storage: &mut Storage<M>) -> Option<Self>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        // Usually, there is nothing to resume.
+        let mut file = storage.read(DOWNLOAD_FILE).await.ok()?;
+
+        match file.read_loadable::<PartialDownload>(storage).await {
+            Ok(download) => Some(download),
+            Err(e) => {
+                warn!("Failed to read download progress: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn save<M>(&self, storage: &mut Storage<M>) -> Result<(), StorageError>
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        storage
+            .store_writer(DOWNLOAD_FILE, self, OnCollision::Overwrite)
+            .await
+    }
+
+    pub async fn clear<M>(storage: &mut Storage<M>)
+    where
+        M: StorageMedium,
+        [(); M::BLOCK_COUNT]:,
+    {
+        // Fails if there was no download to resume.
+        _ = storage.delete(DOWNLOAD_FILE).await;
+    }
+}
+
+impl Loadable for PartialDownload {
+    async fn load<R: Read>(reader: &mut R) -> Result<Self, LoadError<R::Error>> {
+        let etag = ImageTag::load(reader).await?;
+        let written = u32::load(reader).await?;
+
+        let mut digest = [0; 32];
+        for byte in digest.iter_mut() {
+            *byte = u8::load(reader).await?;
+        }
+
+        Ok(Self {
+            etag,
+            written,
+            digest,
+        })
+    }
+}
+
+impl Storable for PartialDownload {
+    async fn store<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        self.etag.store(writer).await?;
+        self.written.store(writer).await?;
+        writer.write_all(&self.digest).await
+    }
+}
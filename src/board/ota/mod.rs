@@ -1,6 +1,19 @@
+//! Over-the-air updates.
+//!
+//! An update is written to the inactive app partition and activated as `New`. The bootloader
+//! doesn't roll back by itself, so the app does it: on the first boot of an update,
+//! [`RunningApp::begin_verification`] marks it `PendingVerify`. If the app can't
+//! [`RunningApp::confirm`] that it works before the device restarts, the next boot marks it
+//! `Aborted` and the bootloader starts the previous app again.
+//!
+//! The bootloader boots the slot with the highest sequence number, but older firmware finds the
+//! running slot by the sequence number alone. So that it doesn't overwrite itself with the next
+//! update, a rollback also gives the previous app a new, highest sequence number.
+
 use core::marker::PhantomData;
 
 use crc::{Algorithm, Crc};
+use firmware_image::{app::ImageInfo, PublicKey};
 use macros::partition;
 use norfs::medium::StorageMedium;
 use norfs_driver::medium::MediumError;
@@ -13,9 +26,37 @@ use norfs_esp32c6 as norfs_impl;
 
 use norfs_impl::{InternalDriver, InternalPartition, SmallInternalDriver};
 
+pub mod download;
+
+/// The key updates must be signed with, or `None` if the firmware was built without one.
+pub const FIRMWARE_PUBLIC_KEY: Option<PublicKey> =
+    include!(concat!(env!("OUT_DIR"), "/firmware_key.rs"));
+
+/// How the running firmware describes itself in its app description, see `main.rs`.
+pub const RUNNING_IMAGE: ImageInfo<'static> = ImageInfo {
+    #[cfg(feature = "esp32s3")]
+    chip_id: firmware_image::app::CHIP_ID_ESP32S3,
+    #[cfg(feature = "esp32c6")]
+    chip_id: firmware_image::app::CHIP_ID_ESP32C6,
+    project_name: concat!("card_io_fw/", env!("HW_VERSION")),
+    version: env!("APP_VERSION"),
+};
+
 #[partition("otadata")]
 pub struct OtaDataPartition;
 
+/// The CRC the bootloader checks the sequence number with.
+static CRC_ALGO: Algorithm<u32> = Algorithm {
+    width: 32,
+    poly: 0x04c11db7,
+    init: 0,
+    refin: true,
+    refout: true,
+    xorout: 0xffffffff,
+    check: 0,
+    residue: 0,
+};
+
 #[partition("ota_0")]
 pub struct Ota0Partition;
 
@@ -69,6 +110,14 @@ struct OtaHeader {
 }
 
 impl OtaHeader {
+    fn new(ota_seq: u32, ota_state: OtaState) -> Self {
+        Self {
+            ota_seq,
+            ota_state: Some(ota_state),
+            crc: Crc::<u32>::new(&CRC_ALGO).checksum(&ota_seq.to_le_bytes()),
+        }
+    }
+
     async fn read<P>(
         partition: &mut SmallInternalDriver<P>,
         slot: Slot,
@@ -95,6 +144,14 @@ impl OtaHeader {
         }
     }
 
+    /// Returns the sequence number the bootloader sees. Aborted and invalid apps are not booted.
+    fn bootable_seq(&self) -> u32 {
+        match self.ota_state {
+            Some(OtaState::Invalid | OtaState::Aborted) => u32::MAX,
+            _ => self.ota_seq,
+        }
+    }
+
     fn into_buffer(self) -> [u8; 32] {
         let mut output = [0; 32];
         output[0..4].copy_from_slice(&self.ota_seq.to_le_bytes());
@@ -167,7 +224,14 @@ where
     }
 
     fn app_slot(&self) -> Slot {
-        Slot::current(self.slot0.ota_seq, self.slot1.ota_seq)
+        Slot::current(self.slot0.bootable_seq(), self.slot1.bootable_seq())
+    }
+
+    fn header(&self, slot: Slot) -> OtaHeader {
+        match slot {
+            Slot::Ota0 => self.slot0,
+            Slot::Ota1 => self.slot1,
+        }
     }
 
     fn update_slot(&self) -> Slot {
@@ -182,6 +246,32 @@ where
         }
     }
 
+    /// Returns the next sequence number that the bootloader maps to `slot`. It boots the slot
+    /// `(ota_seq - 1) % 2`.
+    fn next_sequence_count_for(&self, slot: Slot) -> u32 {
+        let seq = self.next_sequence_count();
+        if (seq - 1) as usize % 2 == slot.block() {
+            seq
+        } else {
+            seq + 1
+        }
+    }
+
+    /// Makes `slot` the one the bootloader starts.
+    async fn stamp(&mut self, slot: Slot, state: OtaState) -> Result<(), MediumError> {
+        let header = OtaHeader::new(self.next_sequence_count_for(slot), state);
+
+        self.erase(slot).await?;
+        self.write(slot, &header.into_buffer()).await?;
+
+        match slot {
+            Slot::Ota0 => self.slot0 = header,
+            Slot::Ota1 => self.slot1 = header,
+        }
+
+        Ok(())
+    }
+
     async fn erase(&mut self, slot: Slot) -> Result<(), MediumError> {
         self.partition.erase(slot.block()).await
     }
@@ -189,6 +279,21 @@ where
     async fn write(&mut self, slot: Slot, data: &[u8]) -> Result<(), MediumError> {
         self.partition.write(slot.block(), 0, data).await
     }
+
+    async fn set_state(&mut self, slot: Slot, state: OtaState) -> Result<(), MediumError> {
+        let mut header = self.header(slot);
+        header.ota_state = Some(state);
+
+        self.erase(slot).await?;
+        self.write(slot, &header.into_buffer()).await?;
+
+        match slot {
+            Slot::Ota0 => self.slot0 = header,
+            Slot::Ota1 => self.slot1 = header,
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -269,38 +374,108 @@ where
         Ok(())
     }
 
-    pub async fn activate(&mut self) -> Result<(), OtaError> {
-        static CRC_ALGO: Algorithm<u32> = Algorithm {
-            width: 32,
-            poly: 0x04c11db7,
-            init: 0,
-            refin: true,
-            refout: true,
-            xorout: 0xffffffff,
-            check: 0,
-            residue: 0,
+    /// Continues writing an update that was interrupted after `offset` bytes, without erasing it.
+    pub fn resume_at(&mut self, offset: usize) {
+        self.update_offset = offset;
+    }
+
+    /// Reads back a part of the update that was written before.
+    pub async fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), OtaError> {
+        match self.update_slot {
+            Slot::Ota0 => self.ota0.read(0, offset, buffer).await?,
+            Slot::Ota1 => self.ota1.read(0, offset, buffer).await?,
         };
 
+        Ok(())
+    }
+
+    pub async fn activate(&mut self) -> Result<(), OtaError> {
         debug!("Activating {:?}", self.update_slot);
 
-        self.ota_data.erase(self.update_slot).await?;
+        // Confirmed by the new app, see `RunningApp`.
+        self.ota_data.stamp(self.update_slot, OtaState::New).await?;
 
-        let ota_seq = self.ota_data.next_sequence_count();
+        Ok(())
+    }
+}
 
-        let crc = Crc::<u32>::new(&CRC_ALGO);
-        let mut digest = crc.digest();
-        digest.update(&ota_seq.to_le_bytes());
+/// What the running app has to do to stay installed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootVerification {
+    /// The app is confirmed, or was not installed as an update.
+    NotNeeded,
+    /// This is the first boot of an update, which must be confirmed once it is known to work.
+    Pending,
+    /// The update was not confirmed on its first boot. The previous app will be started after a
+    /// restart.
+    RolledBack,
+}
 
-        let header = OtaHeader {
-            ota_seq,
-            ota_state: Some(OtaState::Valid),
-            crc: digest.finalize(),
-        };
+/// The app the device booted.
+pub struct RunningApp<D>
+where
+    D: InternalPartition,
+{
+    slot: Slot,
+    ota_data: OtaData<D>,
+}
+
+impl<D> RunningApp<D>
+where
+    D: InternalPartition,
+    SmallInternalDriver<D>: StorageMedium,
+{
+    pub async fn read(data: D) -> Result<Self, OtaError> {
+        let ota_data = OtaData::read(SmallInternalDriver::new(data)).await?;
+
+        Ok(Self {
+            slot: ota_data.app_slot(),
+            ota_data,
+        })
+    }
+
+    /// Must be called once after every boot, before anything that may fail in a broken update.
+    pub async fn begin_verification(&mut self) -> Result<BootVerification, OtaError> {
+        let header = self.ota_data.header(self.slot);
+        if header.ota_seq == u32::MAX {
+            // Flashed directly, not by an update.
+            return Ok(BootVerification::NotNeeded);
+        }
 
+        match header.ota_state {
+            Some(OtaState::New) => {
+                debug!("First boot of {:?}", self.slot);
+                self.ota_data
+                    .set_state(self.slot, OtaState::PendingVerify)
+                    .await?;
+                Ok(BootVerification::Pending)
+            }
+            Some(OtaState::PendingVerify) => {
+                debug!("{:?} was not confirmed", self.slot);
+                self.abort().await?;
+                Ok(BootVerification::RolledBack)
+            }
+            _ => Ok(BootVerification::NotNeeded),
+        }
+    }
+
+    /// Marks the running app as working, so that it is booted from now on.
+    pub async fn confirm(&mut self) -> Result<(), OtaError> {
+        self.ota_data.set_state(self.slot, OtaState::Valid).await?;
+        Ok(())
+    }
+
+    /// Marks the running app as broken. The previous app will be started after a restart.
+    pub async fn abort(&mut self) -> Result<(), OtaError> {
+        // The previous app is made the newest first, so that it is booted even if marking this one
+        // is interrupted.
         self.ota_data
-            .write(self.update_slot, &header.into_buffer())
+            .stamp(self.slot.next(), OtaState::Valid)
+            .await?;
+        self.ota_data
+            .set_state(self.slot, OtaState::Aborted)
             .await?;
-
         Ok(())
     }
 }
@@ -3,6 +3,7 @@ use core::{
     ptr::addr_of_mut,
 };
 
+use crate::board::catalog::Catalog;
 use macros::partition;
 use norfs::{medium::cache::ReadCache, Storage, StorageError};
 
@@ -50,6 +51,7 @@ use token::Token;
 
 pub struct FileSystem {
     storage: Storage<&'static mut Cache>,
+    catalog: Catalog,
     _token: Token,
 }
 
@@ -71,7 +73,8 @@ impl FileSystem {
         };
 
         match storage {
-            Ok(storage) => Some(Self {
+            Ok(mut storage) => Some(Self {
+                catalog: Catalog::load(&mut storage).await,
                 storage,
                 _token: token,
             }),
@@ -82,6 +85,15 @@ impl FileSystem {
         }
     }
 
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// Returns the measurement catalog and the storage it describes.
+    pub fn split_catalog(&mut self) -> (&mut Catalog, &mut Storage<&'static mut Cache>) {
+        (&mut self.catalog, &mut self.storage)
+    }
+
     pub async fn format() {
         let _ = Token::take();
 
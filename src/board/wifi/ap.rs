@@ -6,14 +6,75 @@ use gui::widgets::wifi_access_point::WifiAccessPointState;
 use crate::{
     board::wifi::net_task,
     task_control::{TaskControlToken, TaskController},
+    SerialNumber,
 };
 use embassy_executor::Spawner;
 use embassy_net::{Runner, Stack};
+use esp_hal::rng::Rng;
 use esp_radio::wifi::{
-    ap::AccessPointConfig, ap::EventInfo, Config, Interface, WifiController,
+    ap::AccessPointConfig, ap::EventInfo, AuthMethod, Config, Interface, WifiController,
 };
 use macros as cardio;
 
+/// Characters that can't be confused with each other on the display.
+const PASSPHRASE_CHARS: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+/// The longest that still fits into the QR code shown on the display.
+const PASSPHRASE_LEN: usize = 10;
+
+pub type Passphrase = heapless::String<16>;
+
+/// The name and passphrase of the configuration access point.
+#[derive(Clone)]
+pub struct ApCredentials {
+    pub ssid: heapless::String<16>,
+    pub passphrase: Passphrase,
+}
+
+impl ApCredentials {
+    pub fn new(passphrase: &str) -> Self {
+        // The last bytes of the serial are enough to tell devices in the same room apart.
+        let serial = SerialNumber::bytes();
+        let mut ssid = heapless::String::new();
+        unwrap!(ssid.push_str("Card/IO-").ok());
+        for byte in &serial[4..] {
+            unwrap!(ssid.push(hex_digit(byte >> 4)).ok());
+            unwrap!(ssid.push(hex_digit(byte & 0xF)).ok());
+        }
+
+        Self {
+            ssid,
+            passphrase: unwrap!(Passphrase::try_from(passphrase).ok()),
+        }
+    }
+
+    pub fn generate_passphrase() -> Passphrase {
+        let rng = Rng::new();
+
+        let mut passphrase = Passphrase::new();
+        while passphrase.len() < PASSPHRASE_LEN {
+            // Rejection sampling, to keep the characters equally likely.
+            let random = rng.random() as u8;
+            if let Some(&c) = PASSPHRASE_CHARS.get((random >> 3) as usize) {
+                unwrap!(passphrase.push(c as char).ok());
+            }
+        }
+
+        passphrase
+    }
+
+    pub(super) fn access_point_config(&self) -> AccessPointConfig {
+        AccessPointConfig::default()
+            .with_ssid(alloc::string::String::from(self.ssid.as_str()))
+            .with_password(alloc::string::String::from(self.passphrase.as_str()))
+            .with_auth_method(AuthMethod::Wpa2Personal)
+            .with_max_connections(1)
+    }
+}
+
+fn hex_digit(nibble: u8) -> char {
+    unwrap!(char::from_digit(nibble as u32, 16)).to_ascii_uppercase()
+}
+
 pub(super) struct ApConnectionState {
     client_count: AtomicU32,
 }
@@ -65,6 +126,7 @@ impl ApState {
         controller: WifiController<'static>,
         ap_stack: Stack<'static>,
         ap_runner: Runner<'static, Interface>,
+        credentials: ApCredentials,
         spawner: Spawner,
     ) -> Self {
         info!("Starting AP");
@@ -77,7 +139,7 @@ impl ApState {
 
         info!("Starting AP tasks");
         spawner.spawn(unwrap!(ap_task(
-            ApController::new(state.clone()),
+            ApController::new(state.clone(), credentials),
             connection_task_control.token(),
         )));
         spawner.spawn(unwrap!(net_task(ap_runner, net_task_control.token())));
@@ -112,21 +174,22 @@ unsafe impl Send for ApTaskResources {}
 
 pub(super) struct ApController {
     state: Rc<ApConnectionState>,
+    credentials: ApCredentials,
 }
 
 impl ApController {
-    pub fn new(state: Rc<ApConnectionState>) -> Self {
-        Self { state }
+    pub fn new(state: Rc<ApConnectionState>, credentials: ApCredentials) -> Self {
+        Self { state, credentials }
+    }
+
+    pub fn access_point_config(&self) -> AccessPointConfig {
+        self.credentials.access_point_config()
     }
 
     pub async fn setup(&mut self, controller: &mut WifiController<'static>) {
         info!("Configuring AP");
 
-        let ap_config = Config::AccessPoint(
-            AccessPointConfig::default()
-                .with_ssid(alloc::string::String::from("Card/IO"))
-                .with_max_connections(1),
-        );
+        let ap_config = Config::AccessPoint(self.access_point_config());
         unwrap!(controller.set_config(&ap_config));
     }
 
@@ -6,7 +6,7 @@ use embassy_time::{Duration, Timer};
 
 use crate::{
     board::wifi::{
-        ap::{Ap, ApConnectionState, ApController},
+        ap::{Ap, ApConnectionState, ApController, ApCredentials},
         net_task,
         sta::{CommandQueue, InitialStaControllerState, Sta, StaConnectionState, StaController},
     },
@@ -18,9 +18,7 @@ use embassy_futures::{
     select::{select3, Either3},
 };
 use embassy_net::{Runner, Stack};
-use esp_radio::wifi::{
-    ap::AccessPointConfig, sta::StationConfig, Config, Interface, WifiController,
-};
+use esp_radio::wifi::{sta::StationConfig, Config, Interface, WifiController};
 use macros as cardio;
 
 pub(super) struct ApStaState {
@@ -38,6 +36,7 @@ impl ApStaState {
         ap_runner: Runner<'static, Interface>,
         sta_stack: Stack<'static>,
         sta_runner: Runner<'static, Interface>,
+        credentials: ApCredentials,
         spawner: Spawner,
     ) -> Self {
         info!("Configuring AP-STA");
@@ -63,7 +62,7 @@ impl ApStaState {
                 command_queue.clone(),
                 InitialStaControllerState::Idle,
             ),
-            ApController::new(ap_state.clone()),
+            ApController::new(ap_state.clone(), credentials),
             connection_task_control.token(),
         )));
 
@@ -121,9 +120,7 @@ async fn ap_sta_task(
 ) {
     task_control
         .run_cancellable(|resources| async {
-            let ap_config = AccessPointConfig::default()
-                .with_ssid(alloc::string::String::from("Card/IO"))
-                .with_max_connections(1);
+            let ap_config = ap_controller.access_point_config();
             let client_config = StationConfig::default();
             unwrap!(resources
                 .controller
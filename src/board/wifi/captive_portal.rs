@@ -0,0 +1,91 @@
+//! DHCP and DNS servers that make devices joining the configuration access point open the config
+//! site. The messages are built by [`config_site::captive_portal`].
+
+use config_site::captive_portal::{
+    dhcp_reply, dns_response, DHCP_CLIENT_PORT, DHCP_MAX_LEN, DHCP_SERVER_PORT, DNS_MAX_LEN,
+    DNS_PORT,
+};
+use embassy_futures::join::join;
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpEndpoint, Ipv4Address, Stack,
+};
+
+pub use config_site::captive_portal::{AP_ADDRESS, AP_PREFIX_LEN, PORTAL_URL};
+
+/// Runs the DHCP and DNS servers until cancelled.
+pub async fn serve(stack: Stack<'_>) {
+    join(dhcp_server(stack), dns_server(stack)).await;
+}
+
+async fn dns_server(stack: Stack<'_>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0; DNS_MAX_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0; DNS_MAX_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(DNS_PORT) {
+        warn!("Failed to start DNS server: {:?}", e);
+        return;
+    }
+
+    let mut query = [0; DNS_MAX_LEN];
+    let mut response = [0; DNS_MAX_LEN];
+    loop {
+        let Ok((len, meta)) = socket.recv_from(&mut query).await else {
+            continue;
+        };
+
+        let Some(len) = dns_response(&query[..len], &mut response) else {
+            debug!("Ignoring invalid DNS query");
+            continue;
+        };
+
+        if let Err(e) = socket.send_to(&response[..len], meta.endpoint).await {
+            warn!("Failed to send DNS response: {:?}", e);
+        }
+    }
+}
+
+async fn dhcp_server(stack: Stack<'_>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0; DHCP_MAX_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0; DHCP_MAX_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(DHCP_SERVER_PORT) {
+        warn!("Failed to start DHCP server: {:?}", e);
+        return;
+    }
+
+    // The client has no address yet, so replies are broadcast.
+    let client = IpEndpoint::new(Ipv4Address::BROADCAST.into(), DHCP_CLIENT_PORT);
+
+    let mut request = [0; DHCP_MAX_LEN];
+    let mut reply = [0; DHCP_MAX_LEN];
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut request).await else {
+            continue;
+        };
+
+        let Some(len) = dhcp_reply(&request[..len], &mut reply) else {
+            continue;
+        };
+
+        if let Err(e) = socket.send_to(&reply[..len], client).await {
+            warn!("Failed to send DHCP reply: {:?}", e);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use core::hint::unreachable_unchecked;
 
 use crate::{
     board::wifi::{
-        ap::{Ap, ApState},
+        ap::{Ap, ApCredentials, ApState},
         ap_sta::ApStaState,
         sta::{Sta, StaState},
     },
@@ -24,11 +24,14 @@ macro_rules! mk_static {
     }};
 }
 
-const STACK_SOCKET_COUNT: usize = 3;
+const STACK_SOCKET_COUNT: usize = 5;
 
 pub mod ap;
 pub mod ap_sta;
+pub mod captive_portal;
+pub mod sntp;
 pub mod sta;
+pub mod tls;
 
 pub struct WifiDriver {
     state: WifiDriverState,
@@ -131,7 +134,7 @@ impl WifiDriver {
     }
 
     #[allow(unused)]
-    pub async fn configure_ap(&mut self, ap_config: Config) -> Ap {
+    pub async fn configure_ap(&mut self, ap_config: Config, credentials: ApCredentials) -> Ap {
         // Prepare, stop STA if running
         if !matches!(self.state, WifiDriverState::Ap(_)) {
             let spawner = unsafe { Spawner::for_current_executor().await };
@@ -139,7 +142,13 @@ impl WifiDriver {
                 .initialize(
                     move |controller, ap_stack, ap_runner, _sta_stack, _sta_runner| {
                         ap_stack.set_config_v4(ap_config.ipv4);
-                        WifiDriverState::Ap(ApState::init(controller, ap_stack, ap_runner, spawner))
+                        WifiDriverState::Ap(ApState::init(
+                            controller,
+                            ap_stack,
+                            ap_runner,
+                            credentials,
+                            spawner,
+                        ))
                     },
                     unsafe { &mut *(self.ap_resources as *mut _) },
                     unsafe { &mut *(self.sta_resources as *mut _) },
@@ -154,7 +163,12 @@ impl WifiDriver {
         }
     }
 
-    pub async fn configure_ap_sta(&mut self, ap_config: Config, sta_config: Config) -> (Ap, Sta) {
+    pub async fn configure_ap_sta(
+        &mut self,
+        ap_config: Config,
+        sta_config: Config,
+        credentials: ApCredentials,
+    ) -> (Ap, Sta) {
         // Prepare, stop STA if running
         if !matches!(self.state, WifiDriverState::ApSta(_)) {
             let spawner = unsafe { Spawner::for_current_executor().await };
@@ -164,7 +178,13 @@ impl WifiDriver {
                         ap_stack.set_config_v4(ap_config.ipv4);
                         sta_stack.set_config_v4(sta_config.ipv4);
                         WifiDriverState::ApSta(ApStaState::init(
-                            controller, ap_stack, ap_runner, sta_stack, sta_runner, spawner,
+                            controller,
+                            ap_stack,
+                            ap_runner,
+                            sta_stack,
+                            sta_runner,
+                            credentials,
+                            spawner,
                         ))
                     },
                     unsafe { &mut *(self.ap_resources as *mut _) },
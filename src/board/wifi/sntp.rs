@@ -0,0 +1,146 @@
+//! Minimal SNTP (RFC 4330) client.
+//!
+//! The server can be overridden at build time with the `NTP_SERVER` environment variable, in
+//! `host` or `host:port` form, e.g. to test against `cargo xtask ntp-server`.
+
+use embassy_net::{
+    dns::DnsQueryType,
+    udp::{PacketMetadata, UdpSocket},
+    IpEndpoint, Stack,
+};
+use embassy_time::{with_timeout, Duration, Instant};
+
+use crate::board::clock::WallClock;
+
+pub const NTP_SERVER: &str = match option_env!("NTP_SERVER") {
+    Some(server) => server,
+    None => "pool.ntp.org",
+};
+
+const NTP_PORT: u16 = 123;
+const PACKET_LEN: usize = 48;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Seconds between the NTP epoch (1900-01-01) and the Unix epoch.
+const NTP_TO_UNIX_SECONDS: u64 = 2_208_988_800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SntpError {
+    InvalidServer,
+    Dns,
+    Network,
+    Timeout,
+    InvalidResponse,
+}
+
+/// Queries the NTP server and sets the clock.
+pub async fn synchronize(stack: Stack<'_>, clock: &mut WallClock) -> Result<(), SntpError> {
+    let now = with_timeout(TIMEOUT, request_time(stack, NTP_SERVER))
+        .await
+        .map_err(|_| SntpError::Timeout)??;
+
+    clock.set_us(now);
+    info!("Clock synchronized to {}", now / 1_000_000);
+
+    Ok(())
+}
+
+/// Returns the current time in microseconds since the Unix epoch.
+async fn request_time(stack: Stack<'_>, server: &str) -> Result<u64, SntpError> {
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>().map_err(|_| SntpError::InvalidServer)?,
+        ),
+        None => (server, NTP_PORT),
+    };
+
+    let addresses = stack
+        .dns_query(host, DnsQueryType::A)
+        .await
+        .map_err(|_| SntpError::Dns)?;
+    let Some(address) = addresses.first() else {
+        return Err(SntpError::Dns);
+    };
+    let server = IpEndpoint::new(*address, port);
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; PACKET_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).map_err(|_| SntpError::Network)?;
+
+    // The transmit timestamp is echoed back as the originate timestamp. We only use it to match
+    // the response to the request, so the local uptime is good enough.
+    let sent_at = Instant::now();
+    let origin = sent_at.as_micros().to_be_bytes();
+
+    let mut request = [0; PACKET_LEN];
+    request[0] = (4 << 3) | 3; // LI = 0, VN = 4, Mode = client
+    request[40..48].copy_from_slice(&origin);
+
+    socket
+        .send_to(&request, server)
+        .await
+        .map_err(|_| SntpError::Network)?;
+
+    let mut response = [0; PACKET_LEN];
+    loop {
+        let (len, meta) = socket
+            .recv_from(&mut response)
+            .await
+            .map_err(|_| SntpError::Network)?;
+
+        if meta.endpoint == server && len == PACKET_LEN && response[24..32] == origin {
+            break;
+        }
+
+        debug!("Ignoring unexpected NTP packet");
+    }
+    let round_trip = sent_at.elapsed().as_micros();
+
+    let mode = response[0] & 0x7;
+    let leap_indicator = response[0] >> 6;
+    let stratum = response[1];
+    if mode != 4 || leap_indicator == 3 || stratum == 0 {
+        warn!("Unsynchronized or invalid NTP response");
+        return Err(SntpError::InvalidResponse);
+    }
+
+    let received = ntp_to_unix_us(&response[32..40])?;
+    let transmitted = ntp_to_unix_us(&response[40..48])?;
+
+    // Assume the network delay is symmetric.
+    let server_delay = transmitted.saturating_sub(received);
+    Ok(transmitted + round_trip.saturating_sub(server_delay) / 2)
+}
+
+fn ntp_to_unix_us(timestamp: &[u8]) -> Result<u64, SntpError> {
+    let mut seconds = u32::from_be_bytes(unwrap!(timestamp[0..4].try_into().ok())) as u64;
+    let fraction = u32::from_be_bytes(unwrap!(timestamp[4..8].try_into().ok())) as u64;
+
+    // A server that doesn't know the time sends 0.
+    if seconds == 0 && fraction == 0 {
+        return Err(SntpError::InvalidResponse);
+    }
+
+    // RFC 4330 section 3: timestamps with the most significant bit clear are in the next era,
+    // which starts in 2036.
+    if seconds & 0x8000_0000 == 0 {
+        seconds += 1 << 32;
+    }
+
+    let Some(seconds) = seconds.checked_sub(NTP_TO_UNIX_SECONDS) else {
+        return Err(SntpError::InvalidResponse);
+    };
+
+    Ok(seconds * 1_000_000 + ((fraction * 1_000_000) >> 32))
+}
@@ -1,7 +1,13 @@
 use core::{alloc::AllocError, future::pending, ptr::addr_of, sync::atomic::Ordering};
 
 use crate::{
-    board::{initialized::Context, wifi::net_task},
+    board::{
+        initialized::Context,
+        wifi::{
+            net_task, sntp,
+            tls::{BackendConnector, HostnameResolver, TlsFailure, TlsSession},
+        },
+    },
     task_control::{TaskControlToken, TaskController},
     Shared,
 };
@@ -20,14 +26,13 @@ use embassy_sync::{
     signal::Signal,
 };
 use embassy_time::{with_timeout, Duration, Timer};
-use esp_hal::rng::Rng;
 use esp_radio::wifi::{
     ap::AccessPointInfo, scan::ScanConfig, sta::StationConfig, Config, Interface, WifiController,
 };
 use gui::widgets::wifi_client::WifiClientState;
 use heapless::String;
 use macros as cardio;
-use reqwless::client::{HttpClient, TlsConfig, TlsVerify};
+use reqwless::client::HttpClient;
 
 pub(super) const SCAN_RESULTS: usize = 20;
 
@@ -162,6 +167,11 @@ impl Sta {
         }
 
         if self.connection_state() == WifiClientState::Connected {
+            if !context.clock.is_synchronized() {
+                if let Err(e) = sntp::synchronize(self.sta_stack, &mut context.clock).await {
+                    warn!("Failed to synchronize clock: {:?}", e);
+                }
+            }
             true
         } else {
             debug!("No network connection");
@@ -170,15 +180,28 @@ impl Sta {
     }
 
     /// Allocates resources for an HTTPS capable [`HttpClient`].
-    pub fn https_client_resources(&self) -> Result<HttpsClientResources<'_>, AllocError> {
+    pub fn https_client_resources(
+        &self,
+        context: &Context,
+    ) -> Result<HttpsClientResources<'_>, AllocError> {
         // The client state must be heap allocated, because we take a reference to it.
-        let resources = Box::try_new(TlsClientState::EMPTY)?;
+        let mut resources = Box::try_new(TlsClientState::EMPTY)?;
+        resources.session.configure(
+            &context.config.backend_url,
+            &context.config.server_ca,
+            context.clock.now(),
+        );
+
         let client_state = unsafe { unwrap!(addr_of!(resources.tcp_state).as_ref()) };
+        let session = unsafe { unwrap!(addr_of!(resources.session).as_ref()) };
 
         Ok(HttpsClientResources {
+            connector: BackendConnector::new(
+                TcpClient::new(self.sta_stack.clone(), client_state),
+                session,
+            ),
+            resolver: HostnameResolver::new(DnsSocket::new(self.sta_stack.clone()), session),
             resources,
-            tcp_client: TcpClient::new(self.sta_stack.clone(), client_state),
-            dns_client: DnsSocket::new(self.sta_stack.clone()),
         })
     }
 
@@ -201,55 +224,43 @@ impl Sta {
     }
 }
 
-const SOCKET_COUNT: usize = 1;
-const SOCKET_TX_BUFFER: usize = 8 * 1024;
-const SOCKET_RX_BUFFER: usize = 16 * 1024;
-
-const TLS_READ_BUFFER: usize = 16 * 1024 + 256;
-const TLS_WRITE_BUFFER: usize = 4096;
+pub(super) const SOCKET_COUNT: usize = 1;
+pub(super) const SOCKET_TX_BUFFER: usize = 8 * 1024;
+pub(super) const SOCKET_RX_BUFFER: usize = 16 * 1024;
 
 type TcpClientState =
     embassy_net::tcp::client::TcpClientState<SOCKET_COUNT, SOCKET_TX_BUFFER, SOCKET_RX_BUFFER>;
-type TcpClient<'a> =
+pub(super) type TcpClient<'a> =
     embassy_net::tcp::client::TcpClient<'a, SOCKET_COUNT, SOCKET_TX_BUFFER, SOCKET_RX_BUFFER>;
 
 struct TlsClientState {
     tcp_state: TcpClientState,
-    tls_read_buffer: [u8; TLS_READ_BUFFER], // must be 16K
-    tls_write_buffer: [u8; TLS_WRITE_BUFFER],
+    session: TlsSession,
 }
 
 impl TlsClientState {
     pub const EMPTY: Self = Self {
         tcp_state: TcpClientState::new(),
-        tls_read_buffer: [0; TLS_READ_BUFFER],
-        tls_write_buffer: [0; TLS_WRITE_BUFFER],
+        session: TlsSession::EMPTY,
     };
 }
 
 pub struct HttpsClientResources<'a> {
+    connector: BackendConnector<'a>,
+    resolver: HostnameResolver<'a>,
+    // Referenced by the fields above, so it must be dropped last.
     resources: Box<TlsClientState>,
-    tcp_client: TcpClient<'a>,
-    dns_client: DnsSocket<'a>,
 }
 
 impl<'a> HttpsClientResources<'a> {
-    pub fn client(&mut self) -> HttpClient<'_, TcpClient<'a>, DnsSocket<'a>> {
-        let rng = Rng::new();
-        let upper = rng.random() as u64;
-        let lower = rng.random() as u64;
-        let seed = (upper << 32) | lower;
-
-        HttpClient::new_with_tls(
-            &self.tcp_client,
-            &self.dns_client,
-            TlsConfig::new(
-                seed,
-                &mut self.resources.tls_read_buffer,
-                &mut self.resources.tls_write_buffer,
-                TlsVerify::None,
-            ),
-        )
+    pub fn client(&mut self) -> HttpClient<'_, BackendConnector<'a>, HostnameResolver<'a>> {
+        // TLS is handled by the connector, reqwless only sees plain connections.
+        HttpClient::new(&self.connector, &self.resolver)
+    }
+
+    /// Returns why the last connection failed, if the server could not be verified.
+    pub fn tls_failure(&self) -> Option<TlsFailure> {
+        self.resources.session.failure()
     }
 }
 
@@ -0,0 +1,313 @@
+//! TLS connections that verify the server's certificate.
+//!
+//! reqwless can't verify certificates, so connections are opened by [`BackendConnector`] and
+//! handed to reqwless as plain connections. If the backend URL is `https`, the server's
+//! certificate must be signed by the CA certificate set in the config. Without one, the connection
+//! is refused, unless it goes to the default backend.
+//!
+//! The certificate must be signed by the CA directly, the verifier doesn't follow intermediate
+//! certificates and only checks ECDSA P-256 signatures. The default backend's certificate is issued
+//! through an intermediate, so there is no CA to build in for it. Without a CA set, it is used like
+//! before certificates were verified: encrypted, but the server is not authenticated. Requests are
+//! still signed with the device key and firmware images are verified on their own.
+
+use core::{
+    cell::{Cell, RefCell, UnsafeCell},
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use config_site::data::CaCertificate;
+use config_types::DEFAULT_BACKEND_URL;
+use embassy_net::dns::DnsSocket;
+use embedded_io_async::{Error as _, ErrorType, Read, Write};
+use embedded_nal_async::{AddrType, Dns, TcpConnect};
+use embedded_tls::{
+    pki::CertVerifier, Aes128GcmSha256, Certificate, CryptoProvider, TlsClock, TlsConfig,
+    TlsConnection, TlsContext, TlsError, TlsVerifier, UnsecureProvider,
+};
+use esp_hal::rng::Rng;
+use rand_chacha::ChaCha8Rng;
+use rand_core::{CryptoRngCore, SeedableRng};
+
+use crate::board::wifi::sta::{TcpClient, SOCKET_COUNT, SOCKET_RX_BUFFER, SOCKET_TX_BUFFER};
+
+const TLS_READ_BUFFER: usize = 16 * 1024 + 256;
+const TLS_WRITE_BUFFER: usize = 4096;
+
+type TcpConnection<'c> =
+    embassy_net::tcp::client::TcpConnection<'c, SOCKET_COUNT, SOCKET_TX_BUFFER, SOCKET_RX_BUFFER>;
+
+/// The largest server certificate that can be verified.
+const MAX_CERTIFICATE_LEN: usize = 4096;
+
+/// Seconds since the Unix epoch when the connection was set up, or 0 if the time is not known.
+static CONNECTION_TIME: AtomicU32 = AtomicU32::new(0);
+
+/// Why a TLS connection could not be established.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TlsFailure {
+    /// No CA certificate is set for a backend other than the default one.
+    NotConfigured,
+    /// The server's certificate is not signed by the CA, expired, or issued for another host.
+    Untrusted,
+    /// The handshake failed for some other reason.
+    Handshake,
+}
+
+impl TlsFailure {
+    pub fn message(self) -> &'static str {
+        match self {
+            TlsFailure::NotConfigured => "Server certificate not set",
+            TlsFailure::Untrusted => "Server not trusted",
+            TlsFailure::Handshake => "Secure connection failed",
+        }
+    }
+}
+
+impl From<&TlsError> for TlsFailure {
+    fn from(error: &TlsError) -> Self {
+        match error {
+            TlsError::InvalidCertificate | TlsError::InvalidSignature => TlsFailure::Untrusted,
+            _ => TlsFailure::Handshake,
+        }
+    }
+}
+
+struct TlsBuffers {
+    read: [u8; TLS_READ_BUFFER], // must be 16K
+    write: [u8; TLS_WRITE_BUFFER],
+}
+
+/// State shared by the connector and the resolver.
+pub(super) struct TlsSession {
+    buffers: UnsafeCell<TlsBuffers>,
+    secure: bool,
+    /// Cleared for the default backend without a CA, see the module documentation.
+    verify: bool,
+    ca: CaCertificate,
+    /// The host name of the last DNS query, which is the server we connect to next.
+    server_name: RefCell<heapless::String<64>>,
+    failure: Cell<Option<TlsFailure>>,
+}
+
+impl TlsSession {
+    pub const EMPTY: Self = Self {
+        buffers: UnsafeCell::new(TlsBuffers {
+            read: [0; TLS_READ_BUFFER],
+            write: [0; TLS_WRITE_BUFFER],
+        }),
+        secure: false,
+        verify: true,
+        ca: heapless::Vec::new(),
+        server_name: RefCell::new(heapless::String::new()),
+        failure: Cell::new(None),
+    };
+
+    pub fn configure(&mut self, backend_url: &str, ca: &CaCertificate, now: Option<u64>) {
+        self.secure = backend_url.starts_with("https://");
+        self.verify = !ca.is_empty() || backend_url != DEFAULT_BACKEND_URL;
+        self.ca.clone_from(ca);
+        CONNECTION_TIME.store(now.unwrap_or(0) as u32, Ordering::Relaxed);
+    }
+
+    /// Returns why the last connection attempt failed, if it failed during the TLS handshake.
+    pub fn failure(&self) -> Option<TlsFailure> {
+        self.failure.get()
+    }
+
+    fn fail(&self, failure: TlsFailure) {
+        warn!("TLS connection failed: {:?}", failure);
+        self.failure.set(Some(failure));
+    }
+}
+
+/// A connection to the backend, encrypted if the backend URL is `https`.
+pub enum Connection<'c> {
+    Plain(TcpConnection<'c>),
+    Tls(TlsConnection<'c, TcpConnection<'c>, Aes128GcmSha256>),
+}
+
+impl ErrorType for Connection<'_> {
+    type Error = TlsError;
+}
+
+impl Read for Connection<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, TlsError> {
+        match self {
+            Connection::Plain(socket) => socket.read(buf).await.map_err(io_error),
+            Connection::Tls(connection) => connection.read(buf).await,
+        }
+    }
+}
+
+impl Write for Connection<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, TlsError> {
+        match self {
+            Connection::Plain(socket) => socket.write(buf).await.map_err(io_error),
+            Connection::Tls(connection) => connection.write(buf).await,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), TlsError> {
+        match self {
+            Connection::Plain(socket) => socket.flush().await.map_err(io_error),
+            Connection::Tls(connection) => connection.flush().await,
+        }
+    }
+}
+
+fn io_error(error: impl embedded_io_async::Error) -> TlsError {
+    TlsError::Io(error.kind())
+}
+
+/// Opens connections to the backend, and verifies the server before returning them.
+pub struct BackendConnector<'a> {
+    tcp_client: TcpClient<'a>,
+    session: &'a TlsSession,
+}
+
+impl<'a> BackendConnector<'a> {
+    pub(super) fn new(tcp_client: TcpClient<'a>, session: &'a TlsSession) -> Self {
+        Self {
+            tcp_client,
+            session,
+        }
+    }
+}
+
+impl TcpConnect for BackendConnector<'_> {
+    type Error = TlsError;
+    type Connection<'c>
+        = Connection<'c>
+    where
+        Self: 'c;
+
+    async fn connect<'c>(&'c self, remote: SocketAddr) -> Result<Connection<'c>, TlsError> {
+        let session = self.session;
+        session.failure.set(None);
+
+        if !session.secure {
+            let socket = self.tcp_client.connect(remote).await.map_err(io_error)?;
+            return Ok(Connection::Plain(socket));
+        }
+
+        if session.verify && session.ca.is_empty() {
+            session.fail(TlsFailure::NotConfigured);
+            return Err(TlsError::InvalidCertificate);
+        }
+
+        let server_name = session.server_name.borrow().clone();
+        let mut config = TlsConfig::new().with_server_name(&server_name);
+        if session.verify {
+            config = config.with_ca(Certificate::X509(&session.ca));
+        }
+
+        let socket = self.tcp_client.connect(remote).await.map_err(io_error)?;
+
+        // SAFETY: the TCP client has a single socket, so there is at most one connection using the
+        // buffers at any time.
+        let buffers = unsafe { &mut *session.buffers.get() };
+        let mut connection = TlsConnection::new(socket, &mut buffers.read, &mut buffers.write);
+
+        let result = if session.verify {
+            connection
+                .open(TlsContext::new(&config, VerifyingProvider::new()))
+                .await
+        } else {
+            let provider = UnsecureProvider::new::<Aes128GcmSha256>(seeded_rng());
+            connection.open(TlsContext::new(&config, provider)).await
+        };
+        if let Err(e) = result {
+            session.fail(TlsFailure::from(&e));
+            return Err(e);
+        }
+
+        Ok(Connection::Tls(connection))
+    }
+}
+
+/// Resolves host names and remembers them for server name indication and verification.
+pub struct HostnameResolver<'a> {
+    dns: DnsSocket<'a>,
+    session: &'a TlsSession,
+}
+
+impl<'a> HostnameResolver<'a> {
+    pub(super) fn new(dns: DnsSocket<'a>, session: &'a TlsSession) -> Self {
+        Self { dns, session }
+    }
+}
+
+impl Dns for HostnameResolver<'_> {
+    type Error = <DnsSocket<'static> as Dns>::Error;
+
+    async fn get_host_by_name(
+        &self,
+        host: &str,
+        addr_type: AddrType,
+    ) -> Result<IpAddr, Self::Error> {
+        {
+            let mut server_name = self.session.server_name.borrow_mut();
+            server_name.clear();
+            // A name that doesn't fit can't be verified, the handshake will fail.
+            _ = server_name.push_str(host);
+        }
+
+        self.dns.get_host_by_name(host, addr_type).await
+    }
+
+    async fn get_host_by_address(
+        &self,
+        addr: IpAddr,
+        result: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.dns.get_host_by_address(addr, result).await
+    }
+}
+
+struct ConnectionTime;
+
+impl TlsClock for ConnectionTime {
+    fn now() -> Option<u64> {
+        match CONNECTION_TIME.load(Ordering::Relaxed) {
+            0 => None,
+            now => Some(now as u64),
+        }
+    }
+}
+
+struct VerifyingProvider {
+    rng: ChaCha8Rng,
+    verifier: CertVerifier<Aes128GcmSha256, ConnectionTime, MAX_CERTIFICATE_LEN>,
+}
+
+impl VerifyingProvider {
+    fn new() -> Self {
+        Self {
+            rng: seeded_rng(),
+            verifier: CertVerifier::new(),
+        }
+    }
+}
+
+fn seeded_rng() -> ChaCha8Rng {
+    let rng = Rng::new();
+    let upper = rng.random() as u64;
+    let lower = rng.random() as u64;
+    ChaCha8Rng::seed_from_u64((upper << 32) | lower)
+}
+
+impl CryptoProvider for VerifyingProvider {
+    type CipherSuite = Aes128GcmSha256;
+    type Signature = &'static [u8];
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        &mut self.rng
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Self::CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+}
@@ -20,23 +20,23 @@ use embassy_sync::{
     mutex::{Mutex, MutexGuard},
 };
 use embassy_time::{Duration, Timer};
-#[cfg(feature = "wifi")]
-use norfs::StorageError;
 use norfs::{medium::StorageMedium, Storage};
-use signal_processing::compressing_buffer::CompressingBuffer;
+use signal_processing::compressing_buffer::{header::MeasurementHeader, CompressingBuffer};
 use static_cell::StaticCell;
 
 #[cfg(feature = "wifi")]
 use crate::states::{
-    firmware_update::firmware_update, throughput::throughput,
+    firmware_update::firmware_update, registration::register_device, throughput::throughput,
     upload_or_store_measurement::upload_stored_measurements,
 };
 use crate::{
     board::{
-        initialized::{Context, InnerContext},
+        clock::WallClock,
+        initialized::{Context, InnerContext, LEARNED_PARAMS_FILE},
+        ota::{BootVerification, OtaDataPartition, RunningApp},
         startup::StartupResources,
         storage::FileSystem,
-        TOUCH_PIN, VBUS_DETECT_PIN,
+        EcgFrontend, TOUCH_PIN, VBUS_DETECT_PIN,
     },
     states::{
         charging::charging,
@@ -44,11 +44,14 @@ use crate::{
         init::initialize,
         measure::{measure, ECG_BUFFER_SIZE},
         menu::{display_menu_screen, AppMenu},
-        upload_or_store_measurement::upload_or_store_measurement,
+        upload_or_store_measurement::{
+            store_measurement_on_low_battery, upload_or_store_measurement,
+        },
         MESSAGE_DURATION,
     },
 };
 use config_types::{Config, ConfigFile};
+use max17055::LearnedParams;
 
 use esp_hal::{
     gpio::AnyPin,
@@ -57,7 +60,17 @@ use esp_hal::{
 };
 use esp_rtos::embassy::InterruptExecutor;
 
-esp_bootloader_esp_idf::esp_app_desc!();
+// Updates are checked against these, see `board::ota::RUNNING_IMAGE`.
+esp_bootloader_esp_idf::esp_app_desc!(
+    env!("APP_VERSION"),
+    concat!("card_io_fw/", env!("HW_VERSION")),
+    esp_bootloader_esp_idf::BUILD_TIME,
+    esp_bootloader_esp_idf::BUILD_DATE,
+    esp_bootloader_esp_idf::ESP_IDF_COMPATIBLE_VERSION,
+    esp_bootloader_esp_idf::MMU_PAGE_SIZE,
+    0,
+    u16::MAX
+);
 
 #[cfg(feature = "esp32s3")]
 use esp_hal::gpio::RtcPin as RtcWakeupPin;
@@ -117,10 +130,19 @@ pub enum AppState {
     FirmwareUpdate,
     #[cfg(feature = "wifi")]
     Throughput,
+    #[cfg(feature = "wifi")]
+    Register,
     Shutdown,
     #[cfg(feature = "wifi")]
     UploadStored(AppMenu),
-    UploadOrStore(Box<CompressingBuffer<ECG_BUFFER_SIZE>>),
+    UploadOrStore(
+        Box<CompressingBuffer<ECG_BUFFER_SIZE>>,
+        Box<MeasurementHeader>,
+    ),
+    StoreOnLowBattery(
+        Box<CompressingBuffer<ECG_BUFFER_SIZE>>,
+        Box<MeasurementHeader>,
+    ),
 }
 
 async fn load_config<M: StorageMedium>(storage: Option<&mut Storage<M>>) -> &'static mut Config
@@ -153,47 +175,79 @@ where
     CONFIG.init(Config::default())
 }
 
-#[cfg(feature = "wifi")]
-async fn saved_measurement_exists<M>(storage: &mut Storage<M>) -> bool
+async fn load_learned_params<M: StorageMedium>(storage: &mut Storage<M>) -> Option<LearnedParams>
 where
-    M: StorageMedium,
     [(); M::BLOCK_COUNT]:,
 {
-    let mut dir = match storage.read_dir().await {
-        Ok(dir) => dir,
+    match storage.read(LEARNED_PARAMS_FILE).await {
+        Ok(mut file) => match file.read_loadable::<LearnedParams>(storage).await {
+            Ok(params) => Some(params),
+            Err(e) => {
+                warn!("Failed to read fuel gauge parameters: {:?}", e);
+                None
+            }
+        },
         Err(e) => {
-            warn!("Failed to open directory: {:?}", e);
-            return false;
+            warn!("Failed to load fuel gauge parameters: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Returns the running app if it is a new update that has to be confirmed. Restarts the device if
+/// the update was not confirmed on its first boot.
+async fn begin_update_verification() -> Option<RunningApp<OtaDataPartition>> {
+    let mut app = match RunningApp::read(OtaDataPartition).await {
+        Ok(app) => app,
+        Err(e) => {
+            warn!("Failed to read OTA data: {:?}", e);
+            return None;
         }
     };
 
-    let mut buffer = [0; 64];
-    loop {
-        match dir.next(storage).await {
-            Ok(file) => {
-                let Some(file) = file else {
-                    return false;
-                };
-
-                match file.name(storage, &mut buffer).await {
-                    Ok(name) => {
-                        if name.starts_with("meas.") {
-                            return true;
-                        }
-                    }
-                    Err(StorageError::InsufficientBuffer) => {
-                        // not a measurement file, ignore
-                    }
-                    Err(e) => {
-                        warn!("Failed to read file name: {:?}", e);
-                        return false;
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("Failed to read directory: {:?}", e);
-                return false;
-            }
+    match app.begin_verification().await {
+        Ok(BootVerification::NotNeeded) => None,
+        Ok(BootVerification::Pending) => {
+            info!("First boot of an update");
+            Some(app)
+        }
+        Ok(BootVerification::RolledBack) => {
+            warn!("Update was not confirmed, returning to the previous firmware");
+            esp_hal::system::software_reset()
+        }
+        Err(e) => {
+            warn!("Failed to verify update: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Keeps the update if the device can still measure, store and display, returns to the previous
+/// firmware otherwise.
+async fn finish_update_verification(app: &mut RunningApp<OtaDataPartition>, works: bool) {
+    if works {
+        match app.confirm().await {
+            Ok(()) => info!("Update confirmed"),
+            // The next boot returns to the previous firmware.
+            Err(e) => warn!("Failed to confirm update: {:?}", e),
+        }
+        return;
+    }
+
+    warn!("Update failed the health check, returning to the previous firmware");
+    if let Err(e) = app.abort().await {
+        warn!("Failed to abort update: {:?}", e);
+    }
+    esp_hal::system::software_reset()
+}
+
+/// Returns whether the ADC responds with a known device ID.
+async fn check_adc(frontend: EcgFrontend) -> (bool, EcgFrontend) {
+    match frontend.enable_async(|_| {}).await {
+        Ok(frontend) => (frontend.device_id().is_some(), frontend.shut_down().await),
+        Err((frontend, _)) => {
+            warn!("Failed to start ADC");
+            (false, frontend)
         }
     }
 }
@@ -212,6 +266,9 @@ async fn main(_spawner: Spawner) {
     esp_alloc::heap_allocator!(#[esp_hal::ram(reclaimed)] size: RECLAIMED_SIZE);
     esp_alloc::heap_allocator!(size: 96 * 1024);
 
+    // Before any peripheral is set up, so that an update that fails to start is rolled back.
+    let mut unconfirmed_update = begin_update_verification().await;
+
     let resources = StartupResources::initialize().await;
 
     static INTERRUPT_EXECUTOR: StaticCell<InterruptExecutor<2>> = StaticCell::new();
@@ -223,15 +280,22 @@ async fn main(_spawner: Spawner) {
     let mut storage = FileSystem::mount().await;
     let config = load_config(storage.as_deref_mut()).await;
 
+    let (update_works, frontend) = match unconfirmed_update {
+        Some(_) if storage.is_some() => check_adc(resources.frontend).await,
+        Some(_) => (false, resources.frontend),
+        None => (true, resources.frontend),
+    };
+
     // We're boxing Context because we will need to move out of it during shutdown.
     let mut board = Box::new(Context {
         // If the device is awake, the display should be enabled.
-        frontend: resources.frontend,
+        frontend,
         storage,
         inner: InnerContext {
             display: resources.display,
             high_prio_spawner: interrupt_executor.start(Priority::Priority2),
             battery_monitor: resources.battery_monitor,
+            clock: WallClock::new(resources.rtc),
             #[cfg(feature = "wifi")]
             wifi: {
                 use board::wifi::WifiDriver;
@@ -245,8 +309,22 @@ async fn main(_spawner: Spawner) {
         },
     });
 
+    if let Some(storage) = board.storage.as_deref_mut() {
+        if let Some(params) = load_learned_params(storage).await {
+            board
+                .inner
+                .battery_monitor
+                .restore_learned_params(params)
+                .await;
+        }
+    }
+
     unwrap!(board.inner.display.enable().await.ok());
 
+    if let Some(app) = unconfirmed_update.as_mut() {
+        finish_update_verification(app, update_works).await;
+    }
+
     board.apply_hw_config_changes().await;
     board.config_changed = false;
 
@@ -272,18 +350,26 @@ async fn main(_spawner: Spawner) {
             #[cfg(feature = "wifi")]
             AppState::Throughput => throughput(&mut board).await,
             #[cfg(feature = "wifi")]
+            AppState::Register => register_device(&mut board).await,
+            #[cfg(feature = "wifi")]
             AppState::UploadStored(next_state) => {
                 upload_stored_measurements(&mut board, AppState::Menu(next_state)).await
             }
-            AppState::UploadOrStore(buffer) => {
-                upload_or_store_measurement(&mut board, buffer, AppState::Shutdown).await
+            AppState::UploadOrStore(buffer, header) => {
+                upload_or_store_measurement(&mut board, buffer, header, AppState::Shutdown).await
+            }
+            AppState::StoreOnLowBattery(buffer, header) => {
+                store_measurement_on_low_battery(&mut board, buffer, header).await
             }
             AppState::Shutdown => break,
         };
 
+        board.save_learned_params().await;
         board.wait_for_message(MESSAGE_DURATION).await;
     }
 
+    board.save_learned_params().await;
+
     board.inner.display.shut_down();
 
     board.frontend.wait_for_release().await;
@@ -292,7 +378,7 @@ async fn main(_spawner: Spawner) {
     let is_charging = board.inner.battery_monitor.is_plugged();
     board.inner.battery_monitor.stop().await;
 
-    enter_sleep(resources.rtc, is_charging);
+    enter_sleep(board.inner.clock.into_rtc(), is_charging);
     // Shouldn't reach this. If we do, we just exit the task, which means the executor
     // will have nothing else to do. Not ideal, but again, we shouldn't reach this.
 }
@@ -3,35 +3,62 @@ use core::cell::Cell;
 use embassy_futures::select::{select, Either};
 use embassy_time::{with_timeout, Duration, Instant, Timer};
 use embedded_io_async::BufRead;
-use reqwless::{request::Method, response::Status};
+use firmware_image::{
+    app::{CompatibilityCheck, Incompatibility},
+    Verifier, VerifyError,
+};
+use reqwless::{
+    request::Method,
+    response::{Response, Status},
+};
+use sha2::{Digest, Sha256};
 use ufmt::uwrite;
 
 use crate::{
     board::{
-        initialized::{Context, StaMode},
-        ota::{Ota0Partition, Ota1Partition, OtaClient, OtaDataPartition},
+        device_key::AuthHeaders,
+        initialized::{Context, InnerContext, StaMode},
+        ota::{
+            download::{ImageTag, PartialDownload},
+            Ota0Partition, Ota1Partition, OtaClient, OtaDataPartition, FIRMWARE_PUBLIC_KEY,
+            RUNNING_IMAGE,
+        },
+        storage::FileSystem,
+        wifi::tls::TlsFailure,
     },
     human_readable::{BinarySize, Throughput},
-    states::menu::AppMenu,
+    states::{
+        menu::AppMenu,
+        registration::{self, RegistrationError},
+    },
     AppState, SerialNumber,
 };
 
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
 const READ_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// How often the download progress is saved, so that it can be resumed after a power loss. A
+/// multiple of the flash sector size.
+const PROGRESS_SAVE_INTERVAL: usize = 64 * 1024;
+
 #[derive(Clone, Copy, PartialEq)]
 enum UpdateError {
+    SigningKeyNotSet,
     WifiNotEnabled,
     WifiNotConnected,
     InternalError,
     HttpConnectionFailed,
     HttpConnectionTimeout,
+    Tls(TlsFailure),
+    Registration(RegistrationError),
     HttpRequestTimeout,
     HttpRequestFailed,
     WriteError,
     DownloadFailed,
     DownloadTimeout,
     EraseFailed,
+    InvalidImage(VerifyError),
+    Incompatible(Incompatibility),
     ActivateFailed,
 }
 
@@ -49,10 +76,13 @@ pub async fn firmware_update(context: &mut Context) -> AppState {
         UpdateResult::Success => "Update complete",
         UpdateResult::AlreadyUpToDate => "Already up to date",
         UpdateResult::Failed(e) => match e {
+            UpdateError::SigningKeyNotSet => "Update signing key not set",
             UpdateError::WifiNotEnabled => "WiFi not enabled",
             UpdateError::WifiNotConnected => "Could not connect to WiFi",
             UpdateError::InternalError => "Update failed: internal error",
             UpdateError::HttpConnectionFailed => "Failed to connect to update server",
+            UpdateError::Tls(failure) => failure.message(),
+            UpdateError::Registration(error) => error.message(),
             UpdateError::HttpConnectionTimeout => "Connection to update server timed out",
             UpdateError::HttpRequestTimeout => "Update request timed out",
             UpdateError::HttpRequestFailed => "Failed to check for update",
@@ -60,6 +90,8 @@ pub async fn firmware_update(context: &mut Context) -> AppState {
             UpdateError::WriteError => "Failed to write update",
             UpdateError::DownloadFailed => "Failed to download update",
             UpdateError::DownloadTimeout => "Download timed out",
+            UpdateError::InvalidImage(error) => error.message(),
+            UpdateError::Incompatible(error) => error.message(),
             UpdateError::ActivateFailed => "Failed to finalize update",
         },
     };
@@ -74,6 +106,11 @@ pub async fn firmware_update(context: &mut Context) -> AppState {
 }
 
 async fn do_update(context: &mut Context) -> UpdateResult {
+    // Without a key, no update could be installed.
+    let Some(public_key) = FIRMWARE_PUBLIC_KEY else {
+        return UpdateResult::Failed(UpdateError::SigningKeyNotSet);
+    };
+
     let sta = if let Some(sta) = context.enable_wifi_sta(StaMode::Enable).await {
         if sta.wait_for_connection(context).await {
             sta
@@ -86,33 +123,110 @@ async fn do_update(context: &mut Context) -> UpdateResult {
 
     context.display_message("Looking for updates").await;
 
-    let Ok(mut client_resources) = sta.https_client_resources() else {
+    let Ok(mut client_resources) = sta.https_client_resources(context) else {
         return UpdateResult::Failed(UpdateError::InternalError);
     };
     let mut client = client_resources.client();
 
-    let mut url = heapless::String::<128>::new();
+    let key = match registration::device_key(context, &mut client).await {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("Not registered: {:?}", e);
+            let error = client_resources
+                .tls_failure()
+                .map_or(UpdateError::Registration(e), UpdateError::Tls);
+            return UpdateResult::Failed(error);
+        }
+    };
+
+    let mut path = heapless::String::<64>::new();
     if uwrite!(
-        &mut url,
-        "{}/firmware/{}/{}/{}",
-        context.config.backend_url.as_str(),
+        &mut path,
+        "/firmware/{}/{}/{}",
         env!("HW_VERSION"),
         SerialNumber,
         env!("COMMIT_HASH")
     )
     .is_err()
+    {
+        error!("Path too long");
+        return UpdateResult::Failed(UpdateError::InternalError);
+    }
+
+    let mut url = heapless::String::<128>::new();
+    if uwrite!(
+        &mut url,
+        "{}{}",
+        context.config.backend_url.as_str(),
+        path.as_str()
+    )
+    .is_err()
     {
         error!("URL too long");
         return UpdateResult::Failed(UpdateError::InternalError);
     }
 
+    let mut ota = match OtaClient::initialize(OtaDataPartition, Ota0Partition, Ota1Partition).await
+    {
+        Ok(ota) => ota,
+        Err(e) => {
+            warn!("Failed to initialize OTA: {:?}", e);
+            return UpdateResult::Failed(UpdateError::InternalError);
+        }
+    };
+
+    // Verification continues where the interrupted download stopped.
+    let download = match context.storage.as_mut() {
+        Some(storage) => PartialDownload::load(&mut **storage).await,
+        None => None,
+    };
+    let mut resume = None;
+    if let Some(download) = download {
+        context.display_message("Resuming download").await;
+        match hash_written(&mut ota, &download).await {
+            Some((verifier, hasher)) => resume = Some((download, verifier, hasher)),
+            None => {
+                if let Some(storage) = context.storage.as_mut() {
+                    PartialDownload::clear(&mut **storage).await;
+                }
+            }
+        }
+    }
+
+    let Some(auth) = AuthHeaders::new(&key, &context.clock, "GET", &path, &[], &[]) else {
+        return UpdateResult::Failed(UpdateError::Registration(RegistrationError::ClockNotSet));
+    };
+    let auth_headers = auth.headers();
+
+    // If-Range makes the backend send the whole image if it changed since the download started.
+    let mut range = heapless::String::<24>::new();
+    let mut if_range = ImageTag::new();
+    let resume_headers;
+    let headers: &[(&str, &str)] = match &resume {
+        Some((download, _, _)) => {
+            unwrap!(uwrite!(range, "bytes={}-", download.written));
+            if_range.clone_from(&download.etag);
+            resume_headers = [
+                auth_headers[0],
+                auth_headers[1],
+                ("Range", range.as_str()),
+                ("If-Range", if_range.as_str()),
+            ];
+            &resume_headers
+        }
+        None => &auth_headers,
+    };
+
     debug!("Looking for update at {}", url.as_str());
 
     let mut request = match with_timeout(CONNECT_TIMEOUT, client.request(Method::GET, &url)).await {
-        Ok(Ok(request)) => request,
+        Ok(Ok(request)) => request.headers(headers),
         Ok(Err(e)) => {
             warn!("HTTP connect error: {:?}", e);
-            return UpdateResult::Failed(UpdateError::HttpConnectionFailed);
+            let error = client_resources
+                .tls_failure()
+                .map_or(UpdateError::HttpConnectionFailed, UpdateError::Tls);
+            return UpdateResult::Failed(error);
         }
         Err(_) => return UpdateResult::Failed(UpdateError::HttpConnectionTimeout),
     };
@@ -124,35 +238,69 @@ async fn do_update(context: &mut Context) -> UpdateResult {
     };
 
     let response = match result {
-        Ok(response) => match response.status.into() {
-            Status::Ok => response,
-            Status::NotModified => return UpdateResult::AlreadyUpToDate,
-            _ => {
-                warn!("HTTP response error: {:?}", response.status);
-                return UpdateResult::Failed(UpdateError::HttpRequestFailed);
-            }
-        },
+        Ok(response) => response,
         Err(e) => {
             warn!("HTTP response error: {:?}", e);
             return UpdateResult::Failed(UpdateError::HttpRequestFailed);
         }
     };
 
-    let mut ota = match OtaClient::initialize(OtaDataPartition, Ota0Partition, Ota1Partition).await
-    {
-        Ok(ota) => ota,
-        Err(e) => {
-            warn!("Failed to initialize OTA: {:?}", e);
-            return UpdateResult::Failed(UpdateError::InternalError);
+    let status: Status = response.status.into();
+    let resumed = match (status, resume) {
+        (Status::PartialContent, Some((download, verifier, hasher))) => {
+            match header(&response, "Content-Range").and_then(content_range) {
+                Some((start, total)) if start == download.written as usize => {
+                    Some((download.etag, start, total, verifier, hasher))
+                }
+                _ => {
+                    warn!("Unexpected range");
+                    None
+                }
+            }
         }
+        _ => None,
     };
 
-    let size = response.content_length;
-    print_progress(context, 0, size, None).await;
+    let (etag, offset, size, mut verifier, mut hasher) = match resumed {
+        Some((etag, offset, total, verifier, hasher)) => {
+            info!("Resuming download at {}", offset);
+            ota.resume_at(offset);
+
+            let size = total.or(response.content_length.map(|len| offset + len));
+            (Some(etag), offset, size, verifier, hasher)
+        }
+        None => {
+            // Whatever was downloaded before will be overwritten.
+            if let Some(storage) = context.storage.as_mut() {
+                PartialDownload::clear(&mut **storage).await;
+            }
+
+            match status {
+                Status::Ok => {}
+                Status::NotModified => return UpdateResult::AlreadyUpToDate,
+                _ => {
+                    warn!("HTTP response error: {:?}", response.status);
+                    return UpdateResult::Failed(UpdateError::HttpRequestFailed);
+                }
+            }
+
+            let etag = header(&response, "ETag").and_then(|etag| ImageTag::try_from(etag).ok());
+
+            print_progress(context, 0, response.content_length, None).await;
 
-    if let Err(e) = ota.erase().await {
-        warn!("Failed to erase OTA: {:?}", e);
-        return UpdateResult::Failed(UpdateError::EraseFailed);
+            if let Err(e) = ota.erase().await {
+                warn!("Failed to erase OTA: {:?}", e);
+                return UpdateResult::Failed(UpdateError::EraseFailed);
+            };
+
+            (
+                etag,
+                0,
+                response.content_length,
+                Verifier::new(),
+                Sha256::new(),
+            )
+        }
     };
 
     let mut reader = response.body().reader();
@@ -160,12 +308,22 @@ async fn do_update(context: &mut Context) -> UpdateResult {
     let started = Instant::now();
     let received_since = Cell::new(0);
     let mut received_total = 0;
+    let mut written = offset;
+    // Nothing is written before the start of the image is checked, so a resumed download has
+    // already been checked.
+    let mut check = (offset == 0).then(|| CompatibilityCheck::new(RUNNING_IMAGE, false));
+    let mut saved = offset;
+    let storage = &mut context.storage;
+    let display = &mut context.inner;
     let result = select(
         async {
-            loop {
+            'download: loop {
                 let received_buffer = match with_timeout(READ_TIMEOUT, reader.fill_buf()).await {
                     Ok(result) => match result {
-                        Ok(&[]) => break None,
+                        Ok(&[]) => {
+                            let too_short = check.as_ref().and_then(|check| check.finish().err());
+                            break too_short.map(UpdateError::Incompatible);
+                        }
                         Ok(read) => read,
                         Err(e) => {
                             warn!("HTTP read error: {:?}", e);
@@ -175,9 +333,32 @@ async fn do_update(context: &mut Context) -> UpdateResult {
                     _ => break Some(UpdateError::DownloadTimeout),
                 };
 
-                if let Err(e) = ota.write(received_buffer).await {
-                    warn!("Failed to write OTA: {:?}", e);
-                    break Some(UpdateError::WriteError);
+                let parts = match check.as_mut() {
+                    Some(check) => match check.update(received_buffer) {
+                        Ok(parts) => parts,
+                        Err(e) => {
+                            warn!("Incompatible update: {:?}", e);
+                            break Some(UpdateError::Incompatible(e));
+                        }
+                    },
+                    None => [&[][..], received_buffer],
+                };
+
+                for part in parts.into_iter().filter(|part| !part.is_empty()) {
+                    verifier.update(part);
+                    if let Err(e) = ota.write(part).await {
+                        warn!("Failed to write OTA: {:?}", e);
+                        break 'download Some(UpdateError::WriteError);
+                    }
+                    hasher.update(part);
+                    written += part.len();
+                }
+
+                if let Some(etag) = etag.as_ref() {
+                    if written / PROGRESS_SAVE_INTERVAL > saved / PROGRESS_SAVE_INTERVAL {
+                        save_progress(storage, etag, written, &hasher).await;
+                        saved = written;
+                    }
                 }
 
                 let received_len = received_buffer.len();
@@ -193,28 +374,121 @@ async fn do_update(context: &mut Context) -> UpdateResult {
 
                 let avg_speed = Throughput(received_total, started.elapsed());
 
-                print_progress(context, received_total, size, Some(avg_speed)).await;
+                print_progress(display, offset + received_total, size, Some(avg_speed)).await;
             }
         },
     )
     .await;
 
-    match result {
-        Either::First(Some(error)) => UpdateResult::Failed(error),
-        Either::First(None) => {
-            if let Err(e) = ota.activate().await {
-                warn!("Failed to activate OTA: {:?}", e);
-                UpdateResult::Failed(UpdateError::ActivateFailed)
-            } else {
-                UpdateResult::Success
+    let error = match result {
+        Either::First(error) => error,
+        Either::Second(_) => unreachable!(),
+    };
+
+    match (error, etag) {
+        // The connection was lost, what's written so far can be kept.
+        (Some(UpdateError::DownloadFailed | UpdateError::DownloadTimeout), Some(etag)) => {
+            save_progress(&mut context.storage, &etag, written, &hasher).await;
+        }
+        _ => {
+            if let Some(storage) = context.storage.as_mut() {
+                PartialDownload::clear(&mut **storage).await;
             }
         }
-        Either::Second(_) => unreachable!(),
+    }
+
+    if let Some(error) = error {
+        return UpdateResult::Failed(error);
+    }
+
+    // The image is only activated if it was signed by us.
+    if let Err(e) = verifier.verify(&public_key) {
+        warn!("Invalid firmware image: {:?}", e);
+        return UpdateResult::Failed(UpdateError::InvalidImage(e));
+    }
+
+    if let Err(e) = ota.activate().await {
+        warn!("Failed to activate OTA: {:?}", e);
+        UpdateResult::Failed(UpdateError::ActivateFailed)
+    } else {
+        UpdateResult::Success
+    }
+}
+
+/// Hashes the part of the image an interrupted download wrote, to continue verifying it.
+///
+/// Returns `None` if the update partition no longer holds what the download wrote.
+async fn hash_written(
+    ota: &mut OtaClient<OtaDataPartition, Ota0Partition, Ota1Partition>,
+    download: &PartialDownload,
+) -> Option<(Verifier, Sha256)> {
+    let mut verifier = Verifier::new();
+    let mut hasher = Sha256::new();
+
+    let written = download.written as usize;
+    let mut buffer = [0; 1024];
+    let mut offset = 0;
+    while offset < written {
+        let chunk = &mut buffer[..(written - offset).min(1024)];
+        if let Err(e) = ota.read(offset, chunk).await {
+            warn!("Failed to read OTA: {:?}", e);
+            return None;
+        }
+
+        verifier.update(chunk);
+        hasher.update(&*chunk);
+        offset += chunk.len();
+    }
+
+    if hasher.clone().finalize()[..] != download.digest {
+        warn!("Update partition changed since the download was interrupted");
+        return None;
+    }
+
+    Some((verifier, hasher))
+}
+
+fn header<'r, C>(response: &'r Response<'_, '_, C>, name: &str) -> Option<&'r str>
+where
+    C: embedded_io_async::Read,
+{
+    response
+        .headers()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| core::str::from_utf8(value).ok())
+}
+
+/// Parses `bytes {first}-{last}/{total}` into the first byte and the total length, if known.
+fn content_range(value: &str) -> Option<(usize, Option<usize>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, _) = range.split_once('-')?;
+
+    Some((first.parse().ok()?, total.parse().ok()))
+}
+
+/// Records how much of the image is written, see [`PartialDownload`].
+async fn save_progress(
+    storage: &mut Option<FileSystem>,
+    etag: &ImageTag,
+    written: usize,
+    hasher: &Sha256,
+) {
+    let Some(storage) = storage.as_mut() else {
+        return;
+    };
+
+    let download = PartialDownload {
+        etag: etag.clone(),
+        written: written as u32,
+        digest: hasher.clone().finalize().into(),
+    };
+    if let Err(e) = download.save(&mut **storage).await {
+        warn!("Failed to save download progress: {:?}", e);
     }
 }
 
 async fn print_progress(
-    context: &mut Context,
+    context: &mut InnerContext,
     current: usize,
     size: Option<usize>,
     speed: Option<Throughput>,
@@ -0,0 +1,232 @@
+//! Receives firmware updates uploaded through the config site.
+//!
+//! The image is written to the update partition as it arrives, once its start shows that it was
+//! built for this device. It is only activated if its signature is valid.
+
+use core::cell::Cell;
+
+use bad_server::{
+    handler::{BodySink, SinkError},
+    response::ResponseStatus,
+};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+use firmware_image::{
+    app::{CompatibilityCheck, Incompatibility},
+    Verifier,
+};
+use ufmt::uwrite;
+
+use crate::{
+    board::ota::{
+        Ota0Partition, Ota1Partition, OtaClient, OtaDataPartition, FIRMWARE_PUBLIC_KEY,
+        RUNNING_IMAGE,
+    },
+    human_readable::BinarySize,
+};
+
+type Ota = OtaClient<OtaDataPartition, Ota0Partition, Ota1Partition>;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum UploadState {
+    Idle,
+    Preparing,
+    Receiving {
+        received: usize,
+        size: Option<usize>,
+    },
+    /// The update is activated, and starts after a restart.
+    Installed,
+}
+
+impl UploadState {
+    pub fn message(self) -> heapless::String<32> {
+        let mut message = heapless::String::new();
+        match self {
+            UploadState::Idle => {}
+            UploadState::Preparing => unwrap!(message.push_str("Preparing update")),
+            UploadState::Receiving {
+                received,
+                size: Some(size),
+            } => unwrap!(uwrite!(
+                message,
+                "Receiving update: {}%",
+                received * 100 / size.max(1)
+            )),
+            UploadState::Receiving { received, .. } => unwrap!(uwrite!(
+                message,
+                "Receiving update: {}",
+                BinarySize(received)
+            )),
+            UploadState::Installed => unwrap!(message.push_str("Update complete")),
+        }
+        message
+    }
+}
+
+struct Upload {
+    ota: Ota,
+    check: CompatibilityCheck<'static>,
+    verifier: Verifier,
+}
+
+pub struct FirmwareUpload {
+    state: Cell<UploadState>,
+    upload: Mutex<NoopRawMutex, Option<Upload>>,
+}
+
+impl FirmwareUpload {
+    pub fn new() -> Self {
+        Self {
+            state: Cell::new(UploadState::Idle),
+            upload: Mutex::new(None),
+        }
+    }
+
+    pub fn state(&self) -> UploadState {
+        self.state.get()
+    }
+
+    /// Returns the sink the config site writes the uploaded image to.
+    pub fn sink(&self, allow_downgrade: bool) -> UploadSink<'_> {
+        UploadSink {
+            firmware: self,
+            allow_downgrade,
+        }
+    }
+}
+
+pub struct UploadSink<'a> {
+    firmware: &'a FirmwareUpload,
+    /// Set when the user explicitly asked to install an older firmware.
+    allow_downgrade: bool,
+}
+
+impl BodySink for UploadSink<'_> {
+    async fn begin(&self, len: Option<usize>) -> Result<(), SinkError> {
+        if FIRMWARE_PUBLIC_KEY.is_none() {
+            return Err(SinkError::new(
+                ResponseStatus::InternalServerError,
+                "Update signing key not set",
+            ));
+        }
+
+        // Only one upload at a time, and none after an update is installed.
+        if self.firmware.state.get() != UploadState::Idle {
+            return Err(SinkError::new(
+                ResponseStatus::Conflict,
+                "An update is already in progress",
+            ));
+        }
+        self.firmware.state.set(UploadState::Preparing);
+
+        let mut ota =
+            match OtaClient::initialize(OtaDataPartition, Ota0Partition, Ota1Partition).await {
+                Ok(ota) => ota,
+                Err(e) => {
+                    warn!("Failed to initialize OTA: {:?}", e);
+                    self.firmware.state.set(UploadState::Idle);
+                    return Err(SinkError::new(
+                        ResponseStatus::InternalServerError,
+                        "Failed to start update",
+                    ));
+                }
+            };
+
+        if let Err(e) = ota.erase().await {
+            warn!("Failed to erase OTA: {:?}", e);
+            self.firmware.state.set(UploadState::Idle);
+            return Err(SinkError::new(
+                ResponseStatus::InternalServerError,
+                "Failed to erase update partition",
+            ));
+        }
+
+        *self.firmware.upload.lock().await = Some(Upload {
+            ota,
+            check: CompatibilityCheck::new(RUNNING_IMAGE, self.allow_downgrade),
+            verifier: Verifier::new(),
+        });
+        self.firmware.state.set(UploadState::Receiving {
+            received: 0,
+            size: len,
+        });
+
+        Ok(())
+    }
+
+    async fn write(&self, data: &[u8]) -> Result<(), SinkError> {
+        let mut upload = self.firmware.upload.lock().await;
+        let upload = unwrap!(upload.as_mut());
+
+        let parts = match upload.check.update(data) {
+            Ok(parts) => parts,
+            Err(e) => {
+                warn!("Incompatible update: {:?}", e);
+                return Err(incompatible(e));
+            }
+        };
+
+        for part in parts.into_iter().filter(|part| !part.is_empty()) {
+            upload.verifier.update(part);
+            if let Err(e) = upload.ota.write(part).await {
+                warn!("Failed to write OTA: {:?}", e);
+                return Err(SinkError::new(
+                    ResponseStatus::InternalServerError,
+                    "Failed to write update",
+                ));
+            }
+        }
+
+        if let UploadState::Receiving { received, size } = self.firmware.state.get() {
+            self.firmware.state.set(UploadState::Receiving {
+                received: received + data.len(),
+                size,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn finish(&self) -> Result<(), SinkError> {
+        let Some(mut upload) = self.firmware.upload.lock().await.take() else {
+            return Err(SinkError::new(
+                ResponseStatus::InternalServerError,
+                "No update in progress",
+            ));
+        };
+        self.firmware.state.set(UploadState::Idle);
+
+        if let Err(e) = upload.check.finish() {
+            warn!("Incompatible update: {:?}", e);
+            return Err(incompatible(e));
+        }
+
+        if let Err(e) = upload.verifier.verify(&unwrap!(FIRMWARE_PUBLIC_KEY)) {
+            warn!("Invalid firmware image: {:?}", e);
+            return Err(SinkError::new(ResponseStatus::BadRequest, e.message()));
+        }
+
+        if let Err(e) = upload.ota.activate().await {
+            warn!("Failed to activate OTA: {:?}", e);
+            return Err(SinkError::new(
+                ResponseStatus::InternalServerError,
+                "Failed to finalize update",
+            ));
+        }
+
+        info!("Firmware update installed");
+        self.firmware.state.set(UploadState::Installed);
+
+        Ok(())
+    }
+
+    async fn abort(&self) {
+        warn!("Firmware upload aborted");
+        self.firmware.upload.lock().await.take();
+        self.firmware.state.set(UploadState::Idle);
+    }
+}
+
+fn incompatible(error: Incompatibility) -> SinkError {
+    SinkError::new(ResponseStatus::BadRequest, error.message())
+}
@@ -0,0 +1,214 @@
+//! Holter mode: long recordings that are streamed to flash while measuring.
+//!
+//! The recording is split into segments. A full segment is stored as a separate measurement file
+//! while the next one is being recorded, so the recording is only limited by the free space.
+//! Every segment has its own header, linked to the others by a session id, which means segments
+//! can be uploaded and decoded on their own, and losing power only loses the last segment.
+
+use core::cell::Cell;
+
+use ads129x::Sample;
+use alloc::boxed::Box;
+use config_types::types::RetentionPolicy;
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+use esp_hal::rng::Rng;
+use signal_processing::compressing_buffer::{
+    header::{LeadOffEvent, MeasurementHeader, Session},
+    CompressingBuffer,
+};
+
+use crate::{
+    board::{catalog::RecordingInfo, clock::WallClock, storage::FileSystem},
+    states::{
+        measure::finish_recording,
+        upload_or_store_measurement::{store_measurement, EncodedHeader},
+    },
+};
+
+pub const SEGMENT_SIZE: usize = 32 * 1024;
+
+/// A frame holds at most two varint-encoded samples of up to 5 bytes each.
+const MAX_FRAME_LEN: usize = 2 * 5;
+
+type Segment = CompressingBuffer<SEGMENT_SIZE>;
+
+struct SealedSegment {
+    buffer: Box<Segment>,
+    header: MeasurementHeader,
+}
+
+/// Passes segments between the recorder and the writer.
+pub struct Segments {
+    /// Segments to be stored. `None` marks the end of the recording.
+    sealed: Channel<NoopRawMutex, Option<SealedSegment>, 1>,
+    /// Buffers the writer is done with.
+    free: Channel<NoopRawMutex, Box<Segment>, 2>,
+    stored: Cell<u32>,
+    failed: Cell<bool>,
+}
+
+impl Segments {
+    pub const fn new() -> Self {
+        Self {
+            sealed: Channel::new(),
+            free: Channel::new(),
+            stored: Cell::new(0),
+            failed: Cell::new(false),
+        }
+    }
+
+    /// Returns the number of segments stored so far.
+    pub fn stored(&self) -> u32 {
+        self.stored.get()
+    }
+
+    /// Returns whether storing a segment has failed.
+    pub fn failed(&self) -> bool {
+        self.failed.get()
+    }
+}
+
+/// Records samples into the active segment and hands full segments to [`write_segments`].
+pub struct HolterRecorder<'a> {
+    segments: &'a Segments,
+    active: Box<Segment>,
+    header: MeasurementHeader,
+    /// Frames pushed into the active segment, including the ones it has since overwritten.
+    frames_recorded: u32,
+    leads: Option<u8>,
+}
+
+impl<'a> HolterRecorder<'a> {
+    /// Allocates the two segment buffers. `header` describes the recording, its lead-off events
+    /// are managed by the recorder.
+    pub fn new(segments: &'a Segments, mut header: MeasurementHeader) -> Option<Self> {
+        let channel_count = header.channel_count as usize;
+        let mut allocate = || {
+            let mut buffer = Box::try_new(Segment::EMPTY).ok()?;
+            buffer.set_channel_count(channel_count);
+            Some(buffer)
+        };
+
+        let active = allocate()?;
+        unwrap!(segments.free.try_send(allocate()?).ok());
+
+        header.session = Some(Session {
+            id: Rng::new().random().max(1),
+            segment: 0,
+            dropped_frames: 0,
+        });
+
+        Some(Self {
+            segments,
+            active,
+            header,
+            frames_recorded: 0,
+            leads: None,
+        })
+    }
+
+    pub fn segments(&self) -> &'a Segments {
+        self.segments
+    }
+
+    pub fn push(&mut self, ch1: Sample, ch2: Option<Sample>, leads: u8, clock: &WallClock) {
+        // If the writer still has the other buffer, the oldest frames are overwritten.
+        if self.active.space() < MAX_FRAME_LEN {
+            if let Ok(buffer) = self.segments.free.try_receive() {
+                let header = self.seal(clock);
+                let buffer = core::mem::replace(&mut self.active, buffer);
+                unwrap!(self
+                    .segments
+                    .sealed
+                    .try_send(Some(SealedSegment { buffer, header }))
+                    .ok());
+            } else if self.frames_recorded as usize == self.active.frame_count() {
+                warn!("Holter writer is behind, overwriting the oldest frames");
+            }
+        }
+
+        if self.leads != Some(leads) {
+            self.leads = Some(leads);
+            self.header.push_lead_off_event(LeadOffEvent {
+                frame: self.frames_recorded,
+                status: leads,
+            });
+        }
+
+        self.active.push(ch1.raw());
+        if let Some(ch2) = ch2 {
+            self.active.push(ch2.raw());
+        }
+        self.frames_recorded += 1;
+    }
+
+    /// Discards the frames recorded so far.
+    pub fn restart(&mut self) {
+        self.active.clear();
+        self.header.drop_frames(self.frames_recorded);
+        self.frames_recorded = 0;
+    }
+
+    /// Returns the header of the active segment and prepares the header of the next one.
+    fn seal(&mut self, clock: &WallClock) -> MeasurementHeader {
+        let mut header = self.header.clone();
+        finish_recording(clock, &mut header, &self.active, self.frames_recorded);
+
+        // Overwritten frames are missing from the start of the segment.
+        let session = unwrap!(header.session.as_mut());
+        session.dropped_frames = self.frames_recorded - self.active.frame_count() as u32;
+        if session.dropped_frames != 0 {
+            warn!(
+                "Holter segment {} lost {} frames",
+                session.segment, session.dropped_frames
+            );
+        }
+
+        // The next segment starts with the current lead status.
+        let session = unwrap!(self.header.session.as_mut());
+        session.segment += 1;
+        self.header.drop_frames(self.frames_recorded);
+        self.frames_recorded = 0;
+
+        header
+    }
+
+    /// Stores the active segment if `keep` is set, and stops the writer.
+    pub async fn finish(mut self, clock: &WallClock, keep: bool) {
+        if keep && !self.active.is_empty() && !self.segments.failed() {
+            let header = self.seal(clock);
+            let segment = SealedSegment {
+                buffer: self.active,
+                header,
+            };
+            self.segments.sealed.send(Some(segment)).await;
+        }
+
+        self.segments.sealed.send(None).await;
+    }
+}
+
+/// Stores the sealed segments until the recording is finished.
+pub async fn write_segments(
+    segments: &Segments,
+    storage: &mut FileSystem,
+    policy: RetentionPolicy,
+) {
+    while let Some(mut segment) = segments.sealed.receive().await {
+        if !segments.failed() {
+            let info = RecordingInfo::new(&segment.header, segment.buffer.frame_count());
+            let header = EncodedHeader::new(&segment.header);
+            let samples = segment.buffer.make_contiguous();
+            match store_measurement(storage, &header, samples, info, policy).await {
+                Ok(()) => segments.stored.set(segments.stored.get() + 1),
+                Err(e) => {
+                    error!("Failed to store segment: {:?}", e);
+                    segments.failed.set(true);
+                }
+            }
+        }
+
+        segment.buffer.clear();
+        _ = segments.free.try_send(segment.buffer);
+    }
+}
@@ -1,18 +1,28 @@
 use crate::{
     board::{
+        clock::WallClock,
         initialized::{Context, InnerContext},
+        storage::FileSystem,
         AdcSpi, EcgFrontend, PoweredEcgFrontend,
     },
-    states::{menu::AppMenu, to_progress, INIT_MENU_THRESHOLD, INIT_TIME, MIN_FRAME_TIME},
+    states::{
+        holter::{write_segments, HolterRecorder, Segments},
+        menu::AppMenu,
+        to_progress, INIT_MENU_THRESHOLD, INIT_TIME, MIN_FRAME_TIME,
+    },
     task_control::{TaskControlToken, TaskController},
     timeout::Timeout,
-    AppState,
+    AppState, SerialNumber,
+};
+use ads129x::{
+    ll, AdsConfig, AdsData, ChannelConfig, Inputs, InvalidConfig, RespirationConfig, Sample,
 };
-use ads129x::{ll, ConfigRegisters, Sample};
 use alloc::{boxed::Box, sync::Arc};
 use config_types::types::{
-    FilterStrength, Gain, LeadOffCurrent, LeadOffFrequency, LeadOffThreshold,
+    ChannelMode, FilterStrength, Gain, LeadOffCurrent, LeadOffFrequency, LeadOffThreshold,
+    RespirationFrequency, RespirationPhase, SampleRate,
 };
+use embassy_futures::join::join;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
 use embassy_time::{Duration, Instant, Ticker};
 use embedded_graphics::Drawable;
@@ -22,12 +32,13 @@ use gui::screens::{init::StartupScreen, measure::EcgScreen};
 use macros as cardio;
 use object_chain::{chain, Chain, ChainElement, Link};
 use signal_processing::{
-    compressing_buffer::CompressingBuffer,
+    breathing_rate::BreathingRateCalculator,
+    compressing_buffer::{
+        header::{lead_status, ChannelKind, FilterSettings, LeadOffEvent, MeasurementHeader},
+        CompressingBuffer,
+    },
     filter::{
-        iir::{
-            precomputed::{ALL_PASS, HR_NOISE_FILTER, STRONG_EKG_1000HZ, WEAK_EKG_1000HZ},
-            HighPass, Iir, LowPass,
-        },
+        iir::{precomputed, HighPass, Iir, LowPass},
         pli::{adaptation_blocking::AdaptationBlocking, PowerLineFilter},
         Filter,
     },
@@ -35,13 +46,23 @@ use signal_processing::{
     moving::sum::EstimatedSum,
 };
 
-type MessageQueue = Channel<CriticalSectionRawMutex, Sample, 32>;
+/// Samples of a single conversion. `ch2` is only present if both channels are enabled.
+#[derive(Clone, Copy)]
+struct EcgSample {
+    ch1: Sample,
+    ch2: Option<Sample>,
+    /// Connected leads, see [`lead_status`].
+    leads: u8,
+}
+
+type MessageQueue = Channel<CriticalSectionRawMutex, EcgSample, 32>;
 
 unsafe impl Send for PoweredEcgFrontend {}
 
 struct EcgTaskParams {
     token: TaskControlToken<Result<(), <AdcSpi as ErrorType>::Error>, PoweredEcgFrontend>,
     sender: Arc<MessageQueue>,
+    both_channels: bool,
 }
 
 // PLI filtering algo is probably overkill for displaying, but it's fancy
@@ -56,23 +77,29 @@ cfg_if::cfg_if! {
 
         type EcgDownsampler = DownsamplerLight;
 
-        fn create_downsampler() -> EcgDownsampler {
-            DownsamplerLight::ECG_SR_1000HZ
+        fn create_downsampler(sample_rate: SampleRate) -> EcgDownsampler {
+            match sample_rate {
+                SampleRate::_250 => DownsamplerLight::ECG_SR_250HZ,
+                SampleRate::_500 => DownsamplerLight::ECG_SR_500HZ,
+                SampleRate::_1000 => DownsamplerLight::ECG_SR_1000HZ,
+                SampleRate::_2000 => DownsamplerLight::ECG_SR_2000HZ,
+            }
         }
     } else {
-        use signal_processing::filter::downsample::DownSampler;
+        use signal_processing::filter::downsample::MultiStageDownSampler;
 
-        // Downsample by 8 to display around 1 second
-        type EcgDownsampler = chain! {
-            DownSampler,
-            DownSampler,
-            DownSampler
-        };
+        // Downsample to 125Hz to display around 1 second
+        type EcgDownsampler = MultiStageDownSampler<4>;
 
-        fn create_downsampler() -> EcgDownsampler {
-            Chain::new(DownSampler::new())
-                .append(DownSampler::new())
-                .append(DownSampler::new())
+        fn create_downsampler(sample_rate: SampleRate) -> EcgDownsampler {
+            let stages = match sample_rate {
+                SampleRate::_250 => 1,
+                SampleRate::_500 => 2,
+                SampleRate::_1000 => 3,
+                SampleRate::_2000 => 4,
+            };
+
+            MultiStageDownSampler::new(stages)
         }
     }
 }
@@ -82,45 +109,138 @@ pub const ECG_BUFFER_SIZE: usize = 90_000;
 // Two filter chains:
 // - PLI -> IIR HPF -> FIR Downsample -> display
 // - PLI -> IIR HPF -> FIR LPF in HR calculator -> HR calculator
+// In dual channel mode, the second channel is only displayed:
+// - PLI -> IIR HPF -> FIR Downsample -> display
+// In respiration mode, ECG is measured on channel 2 and channel 1 is fed into the breathing rate
+// calculator.
 struct EcgObjects {
     pub filter: EcgFilter,
     pub downsampler: EcgDownsampler,
-    pub heart_rate_calculator: HeartRateCalculator<[f32; 300], [f32; 50]>,
+    pub heart_rate_calculator: HeartRateCalculator<Box<[f32]>, Box<[f32]>>,
     pub hr_noise_filter: Iir<'static, LowPass, 2>,
+    pub secondary: Option<SecondaryChannel>,
+    pub breathing_rate_calculator: Option<BreathingRateCalculator>,
+}
+
+struct SecondaryChannel {
+    pub filter: EcgFilter,
+    pub downsampler: EcgDownsampler,
+}
+
+fn create_filter(sample_rate: SampleRate, hpf: Iir<'static, HighPass, 2>) -> EcgFilter {
+    let pli = match sample_rate {
+        SampleRate::_250 => PowerLineFilter::new_250sps([50.0]),
+        SampleRate::_500 => PowerLineFilter::new_500sps([50.0]),
+        SampleRate::_1000 => PowerLineFilter::new_1ksps([50.0]),
+        SampleRate::_2000 => PowerLineFilter::new_2ksps([50.0]),
+    };
+
+    Chain::new(pli).append(hpf)
+}
+
+fn create_hpf(sample_rate: SampleRate, strength: FilterStrength) -> Iir<'static, HighPass, 2> {
+    match (strength, sample_rate) {
+        (FilterStrength::None, _) => precomputed::ALL_PASS,
+        (FilterStrength::Weak, SampleRate::_250) => precomputed::WEAK_EKG_250HZ,
+        (FilterStrength::Weak, SampleRate::_500) => precomputed::WEAK_EKG_500HZ,
+        (FilterStrength::Weak, SampleRate::_1000) => precomputed::WEAK_EKG_1000HZ,
+        (FilterStrength::Weak, SampleRate::_2000) => precomputed::WEAK_EKG_2000HZ,
+        (FilterStrength::Strong, SampleRate::_250) => precomputed::STRONG_EKG_250HZ,
+        (FilterStrength::Strong, SampleRate::_500) => precomputed::STRONG_EKG_500HZ,
+        (FilterStrength::Strong, SampleRate::_1000) => precomputed::STRONG_EKG_1000HZ,
+        (FilterStrength::Strong, SampleRate::_2000) => precomputed::STRONG_EKG_2000HZ,
+    }
+}
+
+fn create_hr_noise_filter(sample_rate: SampleRate) -> Iir<'static, LowPass, 2> {
+    match sample_rate {
+        SampleRate::_250 => precomputed::HR_NOISE_FILTER_250HZ,
+        SampleRate::_500 => precomputed::HR_NOISE_FILTER_500HZ,
+        SampleRate::_1000 => precomputed::HR_NOISE_FILTER_1000HZ,
+        SampleRate::_2000 => precomputed::HR_NOISE_FILTER_2000HZ,
+    }
 }
 
 impl EcgObjects {
     #[inline(always)]
-    fn new(hpf: Iir<'static, HighPass, 2>) -> Self {
+    fn new(sample_rate: SampleRate, strength: FilterStrength, channel_mode: ChannelMode) -> Self {
+        let fs = sample_rate.sps() as f32;
+
         Self {
-            filter: Chain::new(PowerLineFilter::new_1ksps([50.0])).append(hpf),
-            downsampler: create_downsampler(),
-            heart_rate_calculator: HeartRateCalculator::new(1000.0),
-            hr_noise_filter: HR_NOISE_FILTER,
+            filter: create_filter(sample_rate, create_hpf(sample_rate, strength)),
+            downsampler: create_downsampler(sample_rate),
+            heart_rate_calculator: HeartRateCalculator::new_alloc(fs),
+            hr_noise_filter: create_hr_noise_filter(sample_rate),
+            secondary: (channel_mode == ChannelMode::Dual).then(|| SecondaryChannel {
+                filter: create_filter(sample_rate, create_hpf(sample_rate, strength)),
+                downsampler: create_downsampler(sample_rate),
+            }),
+            breathing_rate_calculator: (channel_mode == ChannelMode::Respiration)
+                .then(|| BreathingRateCalculator::new(fs)),
         }
     }
 }
 
 pub async fn measure(context: &mut Context) -> AppState {
-    let filter = match context.config.filter_strength() {
-        FilterStrength::None => ALL_PASS,
-        FilterStrength::Weak => WEAK_EKG_1000HZ,
-        FilterStrength::Strong => STRONG_EKG_1000HZ,
+    // Leave enough charge to store the recording when the battery runs low.
+    if context.battery_monitor.is_below_measurement_reserve() {
+        context.display_message("Battery too low to measure").await;
+        return AppState::Menu(AppMenu::Main);
+    }
+
+    let sample_rate = context.config.sample_rate;
+    let channel_mode = context.config.channel_mode;
+
+    let segments = Segments::new();
+    let holter = if !context.config.holter_mode {
+        None
+    } else if context.storage.is_none() {
+        context.display_message("Storage not available").await;
+        None
+    } else {
+        let recorder = HolterRecorder::new(&segments, measurement_header(context));
+        if recorder.is_none() {
+            warn!("Failed to allocate Holter segments");
+        }
+        recorder
     };
 
     // We allocate two different objects because the filters don't need to outlive this app state.
-    let ecg_buffer = Box::try_new(CompressingBuffer::EMPTY).ok();
-    let mut ecg = Box::new(EcgObjects::new(filter));
-
-    if ecg_buffer.is_none() {
+    let mut ecg_buffer = if holter.is_none() {
+        Box::try_new(CompressingBuffer::EMPTY).ok()
+    } else {
+        None
+    };
+    let mut ecg = Box::new(EcgObjects::new(
+        sample_rate,
+        context.config.filter_strength(),
+        channel_mode,
+    ));
+    let header = Box::new(measurement_header(context));
+
+    if let Some(ecg_buffer) = ecg_buffer.as_deref_mut() {
+        // Both channels are stored in channel order, in respiration mode too.
+        ecg_buffer.set_channel_count(match channel_mode {
+            ChannelMode::Single => 1,
+            ChannelMode::Dual | ChannelMode::Respiration => 2,
+        });
+    } else if holter.is_none() {
         warn!("Failed to allocate ECG buffer");
     }
 
     unsafe {
         let frontend = core::ptr::read(&context.frontend);
 
-        let (next_state, frontend) =
-            measure_impl(&mut context.inner, frontend, &mut ecg, ecg_buffer).await;
+        let (next_state, frontend) = measure_impl(
+            &mut context.inner,
+            frontend,
+            &mut ecg,
+            ecg_buffer,
+            header,
+            holter,
+            context.storage.as_mut(),
+        )
+        .await;
 
         core::ptr::write(&mut context.frontend, frontend);
 
@@ -135,13 +255,90 @@ pub async fn measure(context: &mut Context) -> AppState {
     }
 }
 
+fn adc_gain(gain: Gain) -> ll::Gain {
+    match gain {
+        Gain::X1 => ll::Gain::X1,
+        Gain::X2 => ll::Gain::X2,
+        Gain::X3 => ll::Gain::X3,
+        Gain::X4 => ll::Gain::X4,
+        Gain::X6 => ll::Gain::X6,
+        Gain::X8 => ll::Gain::X8,
+        Gain::X12 => ll::Gain::X12,
+    }
+}
+
+fn respiration_config(
+    frequency: RespirationFrequency,
+    phase: RespirationPhase,
+) -> RespirationConfig {
+    let frequency = match frequency {
+        RespirationFrequency::_32kHz => ll::RespirationFrequency::_32kHz,
+        RespirationFrequency::_64kHz => ll::RespirationFrequency::_64kHz,
+    };
+    let phase = match phase {
+        RespirationPhase::_0deg => ll::Phase::_0deg,
+        RespirationPhase::_11deg => ll::Phase::_11deg,
+        RespirationPhase::_22deg => ll::Phase::_22deg,
+        RespirationPhase::_33deg => ll::Phase::_33deg,
+        RespirationPhase::_45deg => ll::Phase::_45deg,
+        RespirationPhase::_56deg => ll::Phase::_56deg,
+        RespirationPhase::_67deg => ll::Phase::_67deg,
+        RespirationPhase::_78deg => ll::Phase::_78deg,
+        RespirationPhase::_90deg => ll::Phase::_90deg,
+        RespirationPhase::_101deg => ll::Phase::_101deg,
+        RespirationPhase::_112deg => ll::Phase::_112deg,
+        RespirationPhase::_123deg => ll::Phase::_123deg,
+        RespirationPhase::_135deg => ll::Phase::_135deg,
+        RespirationPhase::_146deg => ll::Phase::_146deg,
+        RespirationPhase::_157deg => ll::Phase::_157deg,
+        RespirationPhase::_168deg => ll::Phase::_168deg,
+    };
+
+    RespirationConfig::new(frequency, phase)
+}
+
+/// Describes the recording. Lead-off events are added while measuring.
+fn measurement_header(context: &Context) -> MeasurementHeader {
+    let config = &context.config;
+
+    let channel_count = match config.channel_mode {
+        ChannelMode::Single => 1,
+        ChannelMode::Dual | ChannelMode::Respiration => 2,
+    };
+    let high_pass_cutoff_millihertz = match config.filter_strength() {
+        FilterStrength::None => 0,
+        FilterStrength::Weak => 750,
+        FilterStrength::Strong => 1500,
+    };
+
+    let mut header = MeasurementHeader::new(channel_count, config.sample_rate.sps());
+    if config.channel_mode == ChannelMode::Respiration {
+        header.channel_kinds = [ChannelKind::Impedance, ChannelKind::Ecg];
+    }
+    header.gain = adc_gain(config.gain).factor();
+    header.serial = Some(SerialNumber::bytes());
+    header.filters = FilterSettings {
+        high_pass_cutoff_millihertz,
+        power_line_frequency: 50,
+    };
+    header.set_commit(env!("COMMIT_HASH"));
+
+    header
+}
+
 async fn measure_impl(
     context: &mut InnerContext,
     frontend: EcgFrontend,
     ecg: &mut EcgObjects,
     mut ecg_buffer: Option<Box<CompressingBuffer<ECG_BUFFER_SIZE>>>,
+    mut header: Box<MeasurementHeader>,
+    mut holter: Option<HolterRecorder<'_>>,
+    storage: Option<&mut FileSystem>,
 ) -> (AppState, EcgFrontend) {
-    let apply_config = |config_regs: &mut ConfigRegisters| {
+    let dual_channel = ecg.secondary.is_some();
+    let respiration = ecg.breathing_rate_calculator.is_some();
+    let both_channels = dual_channel || respiration;
+    let apply_config = |config: &mut AdsConfig| {
         let loff_current_value = match context.config.lead_off_current {
             LeadOffCurrent::Weak => ll::LeadOffCurrent::_6nA,
             LeadOffCurrent::Normal => ll::LeadOffCurrent::_22nA,
@@ -162,26 +359,45 @@ async fn measure_impl(
             LeadOffFrequency::Dc => ll::LeadOffFrequency::Dc,
             LeadOffFrequency::Ac => ll::LeadOffFrequency::Ac,
         };
-        let gain_value = match context.config.gain {
-            Gain::X1 => ll::Gain::X1,
-            Gain::X2 => ll::Gain::X2,
-            Gain::X3 => ll::Gain::X3,
-            Gain::X4 => ll::Gain::X4,
-            Gain::X6 => ll::Gain::X6,
-            Gain::X8 => ll::Gain::X8,
-            Gain::X12 => ll::Gain::X12,
+        let data_rate_value = match context.config.sample_rate {
+            SampleRate::_250 => ll::DataRate::_250sps,
+            SampleRate::_500 => ll::DataRate::_500sps,
+            SampleRate::_1000 => ll::DataRate::_1ksps,
+            SampleRate::_2000 => ll::DataRate::_2ksps,
         };
+        let gain_value = adc_gain(context.config.gain);
+
+        config.data_rate = data_rate_value;
+        config.lead_off.current = loff_current_value;
+        config.lead_off.threshold = loff_threshold_value;
+        config.lead_off.frequency = loff_frequency_value;
+        config.channel1.gain = gain_value;
 
-        config_regs.loff.set_leadoff_current(loff_current_value);
-        config_regs.loff.set_comp_th(loff_threshold_value);
-        config_regs.loff.set_leadoff_frequency(loff_frequency_value);
-        config_regs.ch1set.set_gain(gain_value);
+        if both_channels {
+            config.channel2 =
+                ChannelConfig::new(gain_value, ll::Ch2mux::Normal).with_lead_off(Inputs::BOTH);
+        }
+
+        if respiration {
+            config.respiration = Some(respiration_config(
+                context.config.respiration_frequency,
+                context.config.respiration_phase,
+            ));
+        }
+    };
+    let respiration_supported = |device_id: Option<ll::DeviceId>| {
+        !respiration || device_id.is_some_and(ll::DeviceId::supports_respiration)
     };
     let mut frontend = match frontend.enable_async(apply_config).await {
         Ok(frontend) => frontend,
         Err((fe, err)) => {
             let err_str = match err {
+                _ if !respiration_supported(fe.device_id()) => "Respiration not supported",
+                ads129x::AdsConfigError::Invalid(InvalidConfig::Respiration(_)) => {
+                    "Respiration phase not available at 64kHz"
+                }
                 ads129x::AdsConfigError::ReadbackMismatch => "Failed to start ADC: config error",
+                ads129x::AdsConfigError::Invalid(_) => "Failed to start ADC: invalid config",
                 ads129x::AdsConfigError::Spi(_) => "Failed to start ADC: SPI error",
             };
             context.display_message(err_str).await;
@@ -191,6 +407,12 @@ async fn measure_impl(
         }
     };
 
+    if !respiration_supported(frontend.device_id()) {
+        context.display_message("Respiration not supported").await;
+
+        return (AppState::Menu(AppMenu::Main), frontend.shut_down().await);
+    }
+
     match frontend
         .set_clock_source(context.config.use_external_clock)
         .await
@@ -228,121 +450,247 @@ async fn measure_impl(
         .spawn(unwrap!(reader_task(EcgTaskParams {
             token: task_control.token(),
             sender: queue.clone(),
+            both_channels,
         })));
 
     ecg.heart_rate_calculator.clear();
+    if let Some(calculator) = ecg.breathing_rate_calculator.as_mut() {
+        calculator.clear();
+    }
 
-    let mut screen = EcgScreen::new();
+    let mut screen = if dual_channel {
+        EcgScreen::new_dual()
+    } else {
+        EcgScreen::new()
+    };
 
     let mut samples = 0; // Counter and 1s timer to debug perf issues
     let mut debug_print_timer = Timeout::new(Duration::from_secs(1));
 
     let mut ticker = Ticker::every(MIN_FRAME_TIME);
-    let mut drop_samples = 1500; // Slight delay for the input to settle
+    // Slight delay for the input to settle
+    let mut drop_samples = context.config.sample_rate.sps() as usize * 3 / 2;
     let mut entered = Instant::now();
     let exit_timer = Timeout::new_with_start(INIT_TIME, entered - INIT_MENU_THRESHOLD);
 
-    while !task_control.has_exited() && !context.battery_monitor.is_low() {
-        let display_full = screen.buffer_full();
-        while let Ok(sample) = queue.try_receive() {
-            samples += 1;
+    // Frames pushed into the ECG buffer, including the ones it has since overwritten.
+    let mut frames_recorded = 0;
+    let mut leads = None;
 
-            if drop_samples == 0 {
-                if let Some(ecg_buffer) = ecg_buffer.as_deref_mut() {
-                    ecg_buffer.push(sample.raw());
-                }
-                if let Some(filtered) = ecg.filter.update(sample.voltage()) {
-                    if let Some(filtered) = ecg.hr_noise_filter.update(filtered) {
-                        ecg.heart_rate_calculator.update(filtered);
+    // Full Holter segments are stored while the measurement is running.
+    let segments = holter.as_ref().map(HolterRecorder::segments);
+    let retention_policy = context.config.retention_policy;
+    let writer = async {
+        if let (Some(segments), Some(storage)) = (segments, storage) {
+            write_segments(segments, storage, retention_policy).await;
+        }
+    };
+    let recording = async {
+        while !task_control.has_exited()
+            && !context.battery_monitor.is_low()
+            && !segments.is_some_and(Segments::failed)
+        {
+            let display_full = screen.buffer_full();
+            while let Ok(sample) = queue.try_receive() {
+                samples += 1;
+
+                if drop_samples == 0 {
+                    if leads != Some(sample.leads) {
+                        leads = Some(sample.leads);
+                        header.push_lead_off_event(LeadOffEvent {
+                            frame: frames_recorded,
+                            status: sample.leads,
+                        });
                     }
-
-                    if let Some(downsampled) = ecg.downsampler.update(filtered) {
-                        screen.push(downsampled);
+                    if let Some(ecg_buffer) = ecg_buffer.as_deref_mut() {
+                        ecg_buffer.push(sample.ch1.raw());
+                        if let Some(ch2) = sample.ch2 {
+                            ecg_buffer.push(ch2.raw());
+                        }
+                        frames_recorded += 1;
                     }
+                    if let Some(holter) = holter.as_mut() {
+                        holter.push(sample.ch1, sample.ch2, sample.leads, &context.clock);
+                    }
+                    // In respiration mode, channel 1 carries the impedance signal.
+                    let ecg_sample = match (respiration, sample.ch2) {
+                        (true, Some(ch2)) => ch2,
+                        _ => sample.ch1,
+                    };
+                    if let Some(calculator) = ecg.breathing_rate_calculator.as_mut() {
+                        calculator.update(sample.ch1.voltage());
+                    }
+                    if let Some(filtered) = ecg.filter.update(ecg_sample.voltage()) {
+                        if let Some(filtered) = ecg.hr_noise_filter.update(filtered) {
+                            ecg.heart_rate_calculator.update(filtered);
+                        }
+
+                        if let Some(downsampled) = ecg.downsampler.update(filtered) {
+                            screen.push(downsampled);
+                        }
+                    }
+                    if let (Some(secondary), Some(ch2)) = (ecg.secondary.as_mut(), sample.ch2) {
+                        if let Some(filtered) = secondary.filter.update(ch2.voltage()) {
+                            if let Some(downsampled) = secondary.downsampler.update(filtered) {
+                                screen.push_secondary(downsampled);
+                            }
+                        }
+                    }
+                } else {
+                    drop_samples -= 1;
                 }
-            } else {
-                drop_samples -= 1;
             }
-        }
 
-        if !display_full {
-            if screen.buffer_full() {
-                entered = Instant::now();
+            if !display_full {
+                if screen.buffer_full() {
+                    entered = Instant::now();
+                }
+                if let Some(ecg_buffer) = ecg_buffer.as_deref_mut() {
+                    ecg_buffer.clear();
+                    header.drop_frames(frames_recorded);
+                    frames_recorded = 0;
+                }
+                if let Some(holter) = holter.as_mut() {
+                    holter.restart();
+                }
             }
-            if let Some(ecg_buffer) = ecg_buffer.as_deref_mut() {
-                ecg_buffer.clear();
+
+            if debug_print_timer.is_elapsed() {
+                debug!(
+                    "Collected {} samples in {}ms",
+                    samples,
+                    debug_print_timer.elapsed().as_millis()
+                );
+                samples = 0;
+                debug_print_timer.reset();
             }
-        }
 
-        if debug_print_timer.is_elapsed() {
-            debug!(
-                "Collected {} samples in {}ms",
-                samples,
-                debug_print_timer.elapsed().as_millis()
-            );
-            samples = 0;
-            debug_print_timer.reset();
+            context
+                .with_status_bar(|display| {
+                    if !exit_timer.is_elapsed() {
+                        StartupScreen {
+                            label: "Release to menu",
+                            progress: to_progress(exit_timer.elapsed(), INIT_TIME),
+                        }
+                        .draw(display)
+                    } else {
+                        screen.update_heart_rate(ecg.heart_rate_calculator.current_hr());
+                        screen.update_breathing_rate(
+                            ecg.breathing_rate_calculator
+                                .as_ref()
+                                .and_then(|calculator| calculator.current_rate()),
+                        );
+                        screen.elapsed_secs = entered.elapsed().as_secs() as usize;
+
+                        screen.draw(display)
+                    }
+                })
+                .await;
+
+            ticker.next().await;
         }
 
-        context
-            .with_status_bar(|display| {
-                if !exit_timer.is_elapsed() {
-                    StartupScreen {
-                        label: "Release to menu",
-                        progress: to_progress(exit_timer.elapsed(), INIT_TIME),
-                    }
-                    .draw(display)
+        let result = task_control.stop().await;
+        let next_state = match result {
+            Ok(result) => {
+                // task stopped itself
+                if let Err(_e) = result.as_ref() {
+                    warn!("Measurement task error"); // TODO: print error once supported
+                }
+                if result.is_ok() && !exit_timer.is_elapsed() {
+                    AppState::Menu(AppMenu::Main)
+                } else if let Some(ecg_buffer) = ecg_buffer {
+                    finish_recording(&context.clock, &mut header, &ecg_buffer, frames_recorded);
+                    AppState::UploadOrStore(ecg_buffer, header)
                 } else {
-                    screen.update_heart_rate(ecg.heart_rate_calculator.current_hr());
-                    screen.elapsed_secs = entered.elapsed().as_secs() as usize;
-
-                    screen.draw(display)
+                    AppState::Shutdown
                 }
-            })
-            .await;
-
-        ticker.next().await;
-    }
-
-    let result = task_control.stop().await;
-    let next_state = match result {
-        Ok(result) => {
-            // task stopped itself
-            if let Err(_e) = result.as_ref() {
-                warn!("Measurement task error"); // TODO: print error once supported
             }
-            if result.is_ok() && !exit_timer.is_elapsed() {
-                AppState::Menu(AppMenu::Main)
-            } else if let Some(ecg_buffer) = ecg_buffer {
-                AppState::UploadOrStore(ecg_buffer)
-            } else {
-                AppState::Shutdown
+            Err(_) => {
+                // task was aborted - battery low
+                if let Some(ecg_buffer) = ecg_buffer {
+                    finish_recording(&context.clock, &mut header, &ecg_buffer, frames_recorded);
+                    AppState::StoreOnLowBattery(ecg_buffer, header)
+                } else {
+                    AppState::Shutdown
+                }
             }
+        };
+
+        if let Some(holter) = holter {
+            // Releasing to the menu discards the recording, like in the normal mode.
+            let keep = !matches!(next_state, AppState::Menu(_));
+            holter.finish(&context.clock, keep).await;
         }
-        Err(_) => {
-            // task was aborted - battery low
-            AppState::Shutdown
-        }
+
+        next_state
     };
+    let (next_state, _) = join(recording, writer).await;
+
+    if let Some(segments) = segments {
+        if segments.failed() {
+            context.display_message("Could not store recording").await;
+        }
+        if segments.stored() > 0 {
+            context.signal_sta_work_available(true);
+        }
+    }
 
     let frontend = task_control.unwrap();
 
     (next_state, frontend.shut_down().await)
 }
 
+/// Updates the header to describe the frames left in the buffer.
+pub fn finish_recording<const N: usize>(
+    clock: &WallClock,
+    header: &mut MeasurementHeader,
+    ecg_buffer: &CompressingBuffer<N>,
+    frames_recorded: u32,
+) {
+    // The buffer may have overwritten the oldest frames.
+    header.drop_frames(frames_recorded - ecg_buffer.frame_count() as u32);
+    header.start_time = clock.now_us().map(|now| {
+        let duration_us = ecg_buffer.frame_count() as u64 * 1_000_000 / header.sample_rate as u64;
+        now.saturating_sub(duration_us) / 1_000_000
+    });
+}
+
 #[cardio::task]
 async fn reader_task(params: EcgTaskParams) {
-    let EcgTaskParams { mut token, sender } = params;
+    let EcgTaskParams {
+        mut token,
+        sender,
+        both_channels,
+    } = params;
 
     token
-        .run_cancellable(|frontend| read_ecg(sender.as_ref(), frontend))
+        .run_cancellable(|frontend| read_ecg(sender.as_ref(), frontend, both_channels))
         .await;
     info!("Measurement task stopped");
 }
 
+fn connected_leads(sample: &AdsData) -> u8 {
+    let mut leads = 0;
+    if sample.ch1_positive_lead_connected() {
+        leads |= lead_status::CH1_POSITIVE;
+    }
+    if sample.ch1_negative_lead_connected() {
+        leads |= lead_status::CH1_NEGATIVE;
+    }
+    if sample.ch2_positive_lead_connected() {
+        leads |= lead_status::CH2_POSITIVE;
+    }
+    if sample.ch2_negative_lead_connected() {
+        leads |= lead_status::CH2_NEGATIVE;
+    }
+    leads
+}
+
 async fn read_ecg(
     queue: &MessageQueue,
     frontend: &mut PoweredEcgFrontend,
+    both_channels: bool,
 ) -> Result<(), <AdcSpi as ErrorType>::Error> {
     loop {
         let sample = frontend.read().await?;
@@ -352,7 +700,13 @@ async fn read_ecg(
             return Ok(());
         }
 
-        if queue.try_send(sample.ch1_sample()).is_err() {
+        let sample = EcgSample {
+            ch1: sample.ch1_sample(),
+            ch2: both_channels.then(|| sample.ch2_sample()),
+            leads: connected_leads(&sample),
+        };
+
+        if queue.try_send(sample).is_err() {
             warn!("Sample lost");
         }
     }
@@ -1,5 +1,5 @@
 use crate::{
-    board::initialized::Context,
+    board::{device_key::DeviceKey, initialized::Context},
     states::menu::{AppMenu, MenuBuilder, MenuScreen},
     uformat, AppState, SerialNumber,
 };
@@ -20,6 +20,7 @@ pub enum AboutMenuEvents {
     None,
     ToBatteryInfo,
     ToSerial,
+    Register,
     Back,
 }
 
@@ -55,12 +56,13 @@ type AboutMenuBuilder = MenuBuilder<
         AboutMenuItem<MenuString<12>>,
         AboutMenuItem<&'static str>,
         AboutMenuItem<&'static str>,
+        AboutMenuItem<&'static str>,
         AboutMenuItem<&'static str>
     ),
     AboutMenuEvents,
 >;
 
-fn about_menu_builder(context: &mut Context) -> AboutMenuBuilder {
+fn about_menu_builder(context: &mut Context, registered: bool) -> AboutMenuBuilder {
     let adc_model = match context.frontend.device_id() {
         Some(id) => match id {
             ll::DeviceId::Ads1191 => "ADS1191",
@@ -72,6 +74,8 @@ fn about_menu_builder(context: &mut Context) -> AboutMenuBuilder {
         None => "Unknown",
     };
 
+    let registered = if registered { "Yes" } else { "No" };
+
     create_menu("Device info")
         .add_item("FW", env!("FW_VERSION"), |_| AboutMenuEvents::None)
         .add_item("HW", env!("COMPLETE_HW_VERSION"), |_| AboutMenuEvents::None)
@@ -79,6 +83,7 @@ fn about_menu_builder(context: &mut Context) -> AboutMenuBuilder {
             AboutMenuEvents::ToSerial
         })
         .add_item("ADC", adc_model, |_| AboutMenuEvents::None)
+        .add_item("Registered", registered, |_| AboutMenuEvents::Register)
         .add_item("Fuel gauge", "MAX17055", |_| AboutMenuEvents::ToBatteryInfo)
         .add_item("Back", "<-", |_| AboutMenuEvents::Back)
 }
@@ -89,7 +94,8 @@ impl MenuScreen for AboutAppMenu {
     type MenuBuilder = AboutMenuBuilder;
 
     async fn menu(&mut self, context: &mut Context) -> Self::MenuBuilder {
-        about_menu_builder(context)
+        let registered = DeviceKey::load().await.is_some();
+        about_menu_builder(context, registered)
     }
 
     async fn handle_event(
@@ -101,6 +107,10 @@ impl MenuScreen for AboutAppMenu {
             AboutMenuEvents::None => None,
             AboutMenuEvents::ToBatteryInfo => Some(AppState::Menu(AppMenu::BatteryInfo)),
             AboutMenuEvents::ToSerial => Some(AppState::DisplaySerial),
+            #[cfg(feature = "wifi")]
+            AboutMenuEvents::Register => Some(AppState::Register),
+            #[cfg(not(feature = "wifi"))]
+            AboutMenuEvents::Register => None,
             AboutMenuEvents::Back => Some(AppState::Menu(AppMenu::Main)),
         }
     }
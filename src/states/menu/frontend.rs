@@ -3,7 +3,10 @@ use crate::{
     states::menu::{AppMenu, MenuBuilder, MenuScreen},
     AppState,
 };
-use config_types::types::{Gain, LeadOffCurrent, LeadOffFrequency, LeadOffThreshold};
+use config_types::types::{
+    ChannelMode, Gain, LeadOffCurrent, LeadOffFrequency, LeadOffThreshold, RespirationFrequency,
+    RespirationPhase, SampleRate,
+};
 use embedded_menu::items::MenuItem;
 use gui::{
     embedded_layout::{
@@ -31,6 +34,12 @@ pub enum FrontendMenuEvents {
     ChangeLeadOffThreshold(LeadOffThreshold),
     ChangeLeadOffFrequency(LeadOffFrequency),
     ChangeGain(Gain),
+    ChangeSampleRate(SampleRate),
+    ChangeChannelMode(ChannelMode),
+    ChangeRespirationFrequency(RespirationFrequency),
+    ChangeRespirationPhase(RespirationPhase),
+    ChangeHolterMode(bool),
+    SelfTest,
     Back,
 }
 
@@ -44,6 +53,12 @@ type FrontendMenuBuilder = MenuBuilder<
         FrontendMenuItem<LeadOffThreshold>,
         FrontendMenuItem<LeadOffFrequency>,
         FrontendMenuItem<Gain>,
+        FrontendMenuItem<SampleRate>,
+        FrontendMenuItem<ChannelMode>,
+        FrontendMenuItem<RespirationFrequency>,
+        FrontendMenuItem<RespirationPhase>,
+        FrontendMenuItem<bool>,
+        FrontendMenuItem<&'static str>,
         FrontendMenuItem<&'static str>
     ),
     FrontendMenuEvents,
@@ -72,6 +87,32 @@ fn frontend_menu_builder(context: &mut Context) -> FrontendMenuBuilder {
             FrontendMenuEvents::ChangeLeadOffFrequency,
         )
         .add_item("Gain", context.config.gain, FrontendMenuEvents::ChangeGain)
+        .add_item(
+            "Sample rate",
+            context.config.sample_rate,
+            FrontendMenuEvents::ChangeSampleRate,
+        )
+        .add_item(
+            "Channels",
+            context.config.channel_mode,
+            FrontendMenuEvents::ChangeChannelMode,
+        )
+        .add_item(
+            "Resp. frequency",
+            context.config.respiration_frequency,
+            FrontendMenuEvents::ChangeRespirationFrequency,
+        )
+        .add_item(
+            "Resp. phase",
+            context.config.respiration_phase,
+            FrontendMenuEvents::ChangeRespirationPhase,
+        )
+        .add_item(
+            "Holter mode",
+            context.config.holter_mode,
+            FrontendMenuEvents::ChangeHolterMode,
+        )
+        .add_item("Self test", "->", |_| FrontendMenuEvents::SelfTest)
         .add_item("Back", "<-", |_| FrontendMenuEvents::Back)
 }
 
@@ -105,6 +146,22 @@ impl MenuScreen for FrontendMenu {
             FrontendMenuEvents::ChangeGain(gain) => {
                 context.update_config(|config| config.gain = gain);
             }
+            FrontendMenuEvents::ChangeSampleRate(sample_rate) => {
+                context.update_config(|config| config.sample_rate = sample_rate);
+            }
+            FrontendMenuEvents::ChangeChannelMode(mode) => {
+                context.update_config(|config| config.channel_mode = mode);
+            }
+            FrontendMenuEvents::ChangeRespirationFrequency(frequency) => {
+                context.update_config(|config| config.respiration_frequency = frequency);
+            }
+            FrontendMenuEvents::ChangeRespirationPhase(phase) => {
+                context.update_config(|config| config.respiration_phase = phase);
+            }
+            FrontendMenuEvents::ChangeHolterMode(enabled) => {
+                context.update_config(|config| config.holter_mode = enabled);
+            }
+            FrontendMenuEvents::SelfTest => return Some(AppState::Menu(AppMenu::SelfTest)),
             FrontendMenuEvents::Back => return Some(AppState::Menu(AppMenu::Main)),
         }
 
@@ -21,6 +21,7 @@ pub mod battery_info;
 pub mod display;
 pub mod frontend;
 pub mod main;
+pub mod self_test;
 pub mod storage;
 #[cfg(feature = "wifi")]
 pub mod wifi_ap;
@@ -36,6 +37,7 @@ pub enum AppMenu {
     Storage,
     DeviceInfo,
     BatteryInfo,
+    SelfTest,
     #[cfg(feature = "wifi")]
     WifiAP,
     #[cfg(feature = "wifi")]
@@ -184,6 +186,7 @@ pub async fn display_menu_screen(mut menu: AppMenu, board: &mut Context) -> AppS
             #[cfg(feature = "wifi")]
             AppMenu::WifiListVisible => wifi_sta::wifi_sta(board).await,
             AppMenu::BatteryInfo => battery_info::battery_info_menu(board).await,
+            AppMenu::SelfTest => self_test::self_test_menu(board).await,
         };
 
         match next {
@@ -0,0 +1,166 @@
+use crate::{
+    board::initialized::Context,
+    human_readable::LeftPad,
+    states::menu::{AppMenu, MenuBuilder, MenuItems, MenuScreen},
+    uformat, AppState,
+};
+use ads129x::{AdsConfigError, Measurement, SelfTestReport};
+use embedded_menu::items::menu_item::MenuItem;
+use esp_hal::time::Rate;
+use gui::{
+    embedded_layout::{
+        chain,
+        object_chain::{Chain, Link},
+    },
+    screens::create_menu,
+};
+
+#[derive(Clone, Copy)]
+pub enum SelfTestEvents {
+    None,
+    Back,
+}
+
+pub async fn self_test_menu(context: &mut Context) -> AppState {
+    context.display_message("Running self test...").await;
+
+    let report = match run_self_test(context).await {
+        Ok(report) => report,
+        Err(message) => {
+            context.display_message(message).await;
+            return AppState::Menu(AppMenu::Frontend);
+        }
+    };
+
+    if report.passed() {
+        info!("Self test passed: {:?}", report);
+    } else {
+        warn!("Self test failed: {:?}", report);
+    }
+
+    SelfTestMenu { report }
+        .display(context)
+        .await
+        .unwrap_or(AppState::Shutdown)
+}
+
+async fn run_self_test(context: &mut Context) -> Result<SelfTestReport, &'static str> {
+    unsafe {
+        let frontend = core::ptr::read(&context.frontend);
+
+        let (result, frontend) = match frontend.enable_async(|_| {}).await {
+            Ok(mut frontend) => {
+                let result = frontend.self_test().await;
+                (result, frontend.shut_down().await)
+            }
+            Err((frontend, err)) => (Err(err), frontend),
+        };
+
+        core::ptr::write(&mut context.frontend, frontend);
+
+        // Reset SPI bus configuration
+        unwrap!(context.frontend.spi_mut().bus_mut().apply_config(
+            &esp_hal::spi::master::Config::default()
+                .with_frequency(Rate::from_mhz(1))
+                .with_mode(esp_hal::spi::Mode::_1)
+        ));
+
+        result.map_err(|err| match err {
+            AdsConfigError::ReadbackMismatch => "Self test failed: config error",
+            AdsConfigError::Invalid(_) => "Self test failed: invalid config",
+            AdsConfigError::Spi(_) => "Self test failed: SPI error",
+        })
+    }
+}
+
+type SelfTestMenuItem<S> = MenuItem<S, SelfTestEvents, &'static str, true>;
+
+struct SelfTestMenu {
+    report: SelfTestReport,
+}
+
+type SelfTestMenuBuilder = MenuBuilder<
+    chain!(
+        SelfTestMenuItem<&'static str>,
+        MenuItems<SelfTestMenuItem<heapless::String<24>>, SelfTestEvents, 11>,
+        SelfTestMenuItem<&'static str>
+    ),
+    SelfTestEvents,
+>;
+
+fn result_str(passed: bool) -> &'static str {
+    if passed {
+        "OK"
+    } else {
+        "FAIL"
+    }
+}
+
+fn self_test_menu_builder(report: &SelfTestReport) -> SelfTestMenuBuilder {
+    let mut items = heapless::Vec::<_, 11>::new();
+
+    let mut list_item = |name, measurement: Measurement, scale: f32, unit| {
+        let value = (measurement.value * scale).clamp(-99999.0, 99999.0) as i32;
+        let label = uformat!(24, "{} {}{}", name, LeftPad(6, value), unit);
+
+        unwrap!(items
+            .push(
+                MenuItem::new(label, result_str(measurement.passed()))
+                    .with_value_converter(|_| SelfTestEvents::None)
+            )
+            .ok())
+    };
+
+    for (labels, channel) in [
+        ["CH1 noise", "CH1 offset", "CH1 test", "CH1 freq"],
+        ["CH2 noise", "CH2 offset", "CH2 test", "CH2 freq"],
+    ]
+    .into_iter()
+    .zip(report.channels)
+    {
+        let Some(channel) = channel else {
+            continue;
+        };
+
+        list_item(labels[0], channel.noise, 1.0, "uV");
+        list_item(labels[1], channel.offset, 1.0, "uV");
+        list_item(labels[2], channel.test_amplitude, 1.0, "uV");
+        list_item(labels[3], channel.test_frequency, 1000.0, "mHz");
+    }
+
+    list_item("Temp", report.temperature, 1.0, "C");
+    list_item("AVDD", report.analog_supply, 1000.0, "mV");
+    if let Some(digital_supply) = report.digital_supply {
+        list_item("DVDD", digital_supply, 1000.0, "mV");
+    }
+
+    create_menu("Self test")
+        .add_item(
+            "Result",
+            if report.passed() { "PASS" } else { "FAIL" },
+            |_| SelfTestEvents::None,
+        )
+        .add_menu_items(items)
+        .add_item("Back", "<-", |_| SelfTestEvents::Back)
+}
+
+impl MenuScreen for SelfTestMenu {
+    type Event = SelfTestEvents;
+    type Result = AppState;
+    type MenuBuilder = SelfTestMenuBuilder;
+
+    async fn menu(&mut self, _context: &mut Context) -> Self::MenuBuilder {
+        self_test_menu_builder(&self.report)
+    }
+
+    async fn handle_event(
+        &mut self,
+        event: Self::Event,
+        _context: &mut Context,
+    ) -> Option<Self::Result> {
+        match event {
+            SelfTestEvents::None => None,
+            SelfTestEvents::Back => Some(AppState::Menu(AppMenu::Frontend)),
+        }
+    }
+}
@@ -4,7 +4,10 @@ use crate::{
     states::menu::{AppMenu, MenuBuilder, MenuItems, MenuScreen},
     uformat, AppState,
 };
-use config_types::{types::MeasurementAction, Config};
+use config_types::{
+    types::{MeasurementAction, RetentionPolicy},
+    Config,
+};
 use embedded_menu::items::menu_item::{MenuItem, SelectValue};
 use gui::{
     embedded_layout::{
@@ -17,6 +20,7 @@ use gui::{
 #[derive(Clone, Copy)]
 pub enum StorageMenuEvents {
     ChangeMeasurementAction(MeasurementAction),
+    ChangeRetentionPolicy(RetentionPolicy),
     Format,
     #[cfg(feature = "wifi")]
     Upload,
@@ -50,6 +54,7 @@ struct StorageMenu;
 type StorageMenuBuilder = MenuBuilder<
     chain!(
         StorageMenuItem<MeasurementAction>,
+        StorageMenuItem<RetentionPolicy>,
         MenuItems<StorageMenuItem<UsedStorage>, StorageMenuEvents, 2>,
         MenuItems<StorageMenuItem<&'static str>, StorageMenuEvents, 2>,
         StorageMenuItem<&'static str>,
@@ -80,6 +85,20 @@ async fn storage_menu_builder(context: &mut Context) -> StorageMenuBuilder {
                 )
                 .ok());
         }
+
+        let catalog = storage.catalog();
+        let recordings_str = UsedStorage(uformat!(
+            32,
+            "{}, {} new",
+            catalog.entries().len(),
+            catalog.pending().count()
+        ));
+        unwrap!(used_item
+            .push(
+                MenuItem::new("EKGs", recordings_str)
+                    .with_value_converter(|_| StorageMenuEvents::Nothing)
+            )
+            .ok());
     }
 
     #[cfg(feature = "wifi")]
@@ -102,6 +121,11 @@ async fn storage_menu_builder(context: &mut Context) -> StorageMenuBuilder {
             context.config.measurement_action,
             StorageMenuEvents::ChangeMeasurementAction,
         )
+        .add_item(
+            "Overwrite",
+            context.config.retention_policy,
+            StorageMenuEvents::ChangeRetentionPolicy,
+        )
         .add_menu_items(used_item)
         .add_menu_items(items)
         .add_item("Format storage", "->", |_| StorageMenuEvents::Format)
@@ -131,6 +155,11 @@ impl MenuScreen for StorageMenu {
 
                 context.update_config(|config| config.measurement_action = action);
             }
+            StorageMenuEvents::ChangeRetentionPolicy(policy) => {
+                debug!("Settings changed");
+
+                context.update_config(|config| config.retention_policy = policy);
+            }
             StorageMenuEvents::Format => {
                 info!("Format requested");
                 context.display_message("Formatting storage...").await;
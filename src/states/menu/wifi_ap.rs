@@ -1,18 +1,25 @@
 use alloc::{boxed::Box, rc::Rc};
 use bad_server::{
-    connector::Connection, handler::RequestHandler, request::Request, response::ResponseStatus,
+    connector::Connection,
+    handler::{RequestHandler, StreamingHandler},
+    request::Request,
+    response::ResponseStatus,
     HandleError,
 };
 use config_site::{
     self,
     data::{SharedWebContext, WebContext},
+    handlers::captive_portal::CaptivePortal,
 };
 use embassy_executor::Spawner;
 use embassy_net::tcp::TcpSocket;
 use embassy_time::{Duration, Ticker, Timer};
 use embedded_graphics::Drawable;
 use gui::{
-    screens::wifi_ap::{ApMenuEvents, WifiApScreen},
+    screens::{
+        message::MessageScreen,
+        wifi_ap::{ApMenuEvents, WifiApScreen},
+    },
     widgets::wifi_access_point::WifiAccessPointState,
 };
 use macros as cardio;
@@ -20,10 +27,16 @@ use macros as cardio;
 use crate::{
     board::{
         initialized::Context,
-        wifi::{ap::Ap, sta::Sta},
+        wifi::{
+            ap::Ap,
+            captive_portal::{self, PORTAL_URL},
+            sta::Sta,
+        },
     },
     states::{
-        menu::AppMenu, TouchInputShaper, MENU_IDLE_DURATION, MIN_FRAME_TIME, WEBSERVER_TASKS,
+        firmware_upload::{FirmwareUpload, UploadState},
+        menu::AppMenu,
+        TouchInputShaper, MENU_IDLE_DURATION, MIN_FRAME_TIME, WEBSERVER_TASKS,
     },
     task_control::{TaskControlToken, TaskController},
     timeout::Timeout,
@@ -31,7 +44,9 @@ use crate::{
 };
 
 pub async fn wifi_ap(context: &mut Context) -> AppState {
-    let Some((ap, sta)) = context.enable_wifi_ap_sta().await else {
+    let credentials = context.ap_credentials().await;
+
+    let Some((ap, sta)) = context.enable_wifi_ap_sta(credentials.clone()).await else {
         // FIXME: Show error screen
         return AppState::Menu(AppMenu::Main);
     };
@@ -41,7 +56,9 @@ pub async fn wifi_ap(context: &mut Context) -> AppState {
     let web_context = Rc::new(SharedWebContext::new(WebContext {
         known_networks: context.config.known_networks.clone(),
         backend_url: context.config.backend_url.clone(),
+        server_ca: context.config.server_ca.clone(),
     }));
+    let firmware_upload = Rc::new(FirmwareUpload::new());
 
     let webserver_task_control = [(); WEBSERVER_TASKS].map(|_| TaskController::new());
     for control in webserver_task_control.iter() {
@@ -49,19 +66,56 @@ pub async fn wifi_ap(context: &mut Context) -> AppState {
             ap.clone(),
             sta.clone(),
             web_context.clone(),
+            firmware_upload.clone(),
             control.token(),
         )));
     }
 
-    let mut screen = WifiApScreen::new();
+    let captive_portal_task_control = TaskController::new();
+    spawner.spawn(unwrap!(captive_portal_task(
+        ap.clone(),
+        captive_portal_task_control.token(),
+    )));
+
+    let mut screen = WifiApScreen::new(&credentials.ssid, &credentials.passphrase);
 
     let mut ticker = Ticker::every(MIN_FRAME_TIME);
     let mut exit_timer = Timeout::new(MENU_IDLE_DURATION);
     let mut input = TouchInputShaper::new();
+    let mut was_touched = false;
+    let mut update_installed = false;
 
     loop {
+        match firmware_upload.state() {
+            UploadState::Idle => {}
+            UploadState::Installed => {
+                // Give the webserver time to respond before shutting it down.
+                Timer::after(Duration::from_secs(1)).await;
+                update_installed = true;
+                break;
+            }
+            state => {
+                exit_timer.reset();
+
+                let message = state.message();
+                context
+                    .with_status_bar(|display| {
+                        MessageScreen {
+                            message: message.as_str(),
+                        }
+                        .draw(display)
+                    })
+                    .await;
+
+                ticker.next().await;
+                continue;
+            }
+        }
+
         input.update(&mut context.frontend);
         let is_touched = input.is_touched();
+        let touch_started = is_touched && !was_touched;
+        was_touched = is_touched;
 
         // We only enable this check for fuel gauges because enabling wifi modifies ADC readings
         // and the board would shut down immediately.
@@ -86,9 +140,17 @@ pub async fn wifi_ap(context: &mut Context) -> AppState {
 
         screen.state = connection_state;
 
-        #[allow(irrefutable_let_patterns)]
-        if let Some(ApMenuEvents::Exit) = screen.menu.interact(is_touched) {
-            break;
+        if screen.show_qr_code {
+            // Any new touch goes back to the menu.
+            if touch_started {
+                screen.show_qr_code = false;
+            }
+        } else {
+            match screen.menu.interact(is_touched) {
+                Some(ApMenuEvents::Exit) => break,
+                Some(ApMenuEvents::ShowQrCode) => screen.show_qr_code = true,
+                None => {}
+            }
         }
 
         context
@@ -101,6 +163,7 @@ pub async fn wifi_ap(context: &mut Context) -> AppState {
     for control in webserver_task_control {
         let _ = control.stop().await;
     }
+    let _ = captive_portal_task_control.stop().await;
 
     context.disable_wifi().await;
 
@@ -115,11 +178,19 @@ pub async fn wifi_ap(context: &mut Context) -> AppState {
             if web_context.backend_url != config.backend_url {
                 config.backend_url.clone_from(&web_context.backend_url);
             }
+            if web_context.server_ca != config.server_ca {
+                config.server_ca.clone_from(&web_context.server_ca);
+            }
         });
     }
 
     context.save_config().await;
 
+    if update_installed {
+        context.display_message("Update complete").await;
+        return AppState::Shutdown;
+    }
+
     AppState::Menu(AppMenu::Main)
 }
 
@@ -135,6 +206,7 @@ async fn webserver_task(
     ap: Ap,
     sta: Sta,
     context: Rc<SharedWebContext>,
+    firmware_upload: Rc<FirmwareUpload>,
     mut task_control: TaskControlToken<()>,
 ) {
     info!("Started webserver task");
@@ -159,15 +231,39 @@ async fn webserver_task(
 
             config_site::create(&context, env!("FW_VERSION"))
                 .with_handler(RequestHandler::get("/vn", VisibleNetworks { sta }))
+                .with_handler(RequestHandler::post(
+                    "/fw",
+                    StreamingHandler::<_, 2048>::new(firmware_upload.sink(false)),
+                ))
+                .with_handler(RequestHandler::post(
+                    "/fw/downgrade",
+                    StreamingHandler::<_, 2048>::new(firmware_upload.sink(true)),
+                ))
+                .with_handler(CaptivePortal::new(PORTAL_URL))
                 .with_request_buffer(&mut resources.request_buffer[..])
                 .with_header_count::<24>()
-                .listen(&mut socket, 8080)
+                .listen(&mut socket, 80)
                 .await;
         })
         .await;
     info!("Stopped webserver task");
 }
 
+#[cardio::task]
+async fn captive_portal_task(ap: Ap, mut task_control: TaskControlToken<()>) {
+    info!("Started captive portal task");
+    task_control
+        .run_cancellable(|_| async {
+            while !ap.is_active() {
+                Timer::after(Duration::from_millis(500)).await;
+            }
+
+            captive_portal::serve(ap.stack()).await;
+        })
+        .await;
+    info!("Stopped captive portal task");
+}
+
 struct VisibleNetworks {
     sta: Sta,
 }
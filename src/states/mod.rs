@@ -2,10 +2,15 @@ pub mod charging;
 pub mod display_serial;
 #[cfg(feature = "wifi")]
 pub mod firmware_update;
+#[cfg(feature = "wifi")]
+pub mod firmware_upload;
+pub mod holter;
 pub mod init;
 pub mod measure;
 pub mod menu;
 #[cfg(feature = "wifi")]
+pub mod registration;
+#[cfg(feature = "wifi")]
 pub mod throughput;
 pub mod upload_or_store_measurement;
 
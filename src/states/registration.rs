@@ -0,0 +1,231 @@
+//! Registers the device with the backend.
+//!
+//! The device sends `POST /register/{serial}`, and the backend answers with a hex encoded
+//! [`DeviceKey`] that is used to sign every later request. The key is kept in its own partition,
+//! so it survives formatting the storage.
+//!
+//! The backend only issues a new key for a registered serial if the request is signed with the
+//! current key. Requests can't be signed before the clock is set.
+
+use embassy_time::{with_timeout, Duration};
+use embedded_nal_async::{Dns, TcpConnect};
+use reqwless::{client::HttpClient, request::Method, response::Status};
+use ufmt::uwrite;
+
+use crate::{
+    board::{
+        clock::WallClock,
+        device_key::{AuthHeaders, DeviceKey},
+        initialized::{Context, StaMode},
+        wifi::tls::TlsFailure,
+    },
+    states::menu::AppMenu,
+    AppState, SerialNumber,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistrationError {
+    ClockNotSet,
+    Refused,
+    Failed,
+}
+
+impl RegistrationError {
+    pub fn message(self) -> &'static str {
+        match self {
+            RegistrationError::ClockNotSet => "Clock not set",
+            RegistrationError::Refused => "Registration refused",
+            RegistrationError::Failed => "Registration failed",
+        }
+    }
+}
+
+/// Returns the device key, registering the device first if needed.
+pub async fn device_key<T, DNS>(
+    context: &mut Context,
+    client: &mut HttpClient<'_, T, DNS>,
+) -> Result<DeviceKey, RegistrationError>
+where
+    T: TcpConnect,
+    DNS: Dns,
+{
+    // Nothing can be signed without the time, not even a request for a new key.
+    if context.clock.now().is_none() {
+        return Err(RegistrationError::ClockNotSet);
+    }
+
+    if let Some(key) = DeviceKey::load().await {
+        return Ok(key);
+    }
+
+    register_and_store(context, client, None).await
+}
+
+async fn register_and_store<T, DNS>(
+    context: &mut Context,
+    client: &mut HttpClient<'_, T, DNS>,
+    current: Option<&DeviceKey>,
+) -> Result<DeviceKey, RegistrationError>
+where
+    T: TcpConnect,
+    DNS: Dns,
+{
+    context.display_message("Registering device").await;
+
+    let key = register(client, &context.config.backend_url, current, &context.clock).await?;
+
+    if let Err(e) = key.save().await {
+        // The backend has already replaced the key, it has to reset the registration.
+        warn!("Failed to store device key: {:?}", e);
+        return Err(RegistrationError::Failed);
+    }
+
+    info!("Device registered");
+    context.display_message("Device registered").await;
+
+    Ok(key)
+}
+
+async fn register<T, DNS>(
+    client: &mut HttpClient<'_, T, DNS>,
+    backend_url: &str,
+    current: Option<&DeviceKey>,
+    clock: &WallClock,
+) -> Result<DeviceKey, RegistrationError>
+where
+    T: TcpConnect,
+    DNS: Dns,
+{
+    let mut path = heapless::String::<32>::new();
+    if uwrite!(&mut path, "/register/{}", SerialNumber).is_err() {
+        warn!("Path too long");
+        return Err(RegistrationError::Failed);
+    }
+
+    let mut url = heapless::String::<128>::new();
+    if uwrite!(&mut url, "{}{}", backend_url, path.as_str()).is_err() {
+        warn!("URL too long");
+        return Err(RegistrationError::Failed);
+    }
+
+    // A registered device proves that it holds the current key.
+    let auth = match current {
+        Some(key) => Some(
+            AuthHeaders::new(key, clock, "POST", &path, &[], &[])
+                .ok_or(RegistrationError::ClockNotSet)?,
+        ),
+        None => None,
+    };
+    let auth_headers = auth.as_ref().map(AuthHeaders::headers);
+
+    debug!("Registering at {}", url.as_str());
+
+    let mut request = match with_timeout(TIMEOUT, client.request(Method::POST, &url)).await {
+        Ok(Ok(request)) => match &auth_headers {
+            Some(headers) => request.headers(headers),
+            None => request,
+        },
+        Ok(Err(e)) => {
+            warn!("HTTP connect error: {:?}", e);
+            return Err(RegistrationError::Failed);
+        }
+        Err(_) => {
+            warn!("Connect timeout");
+            return Err(RegistrationError::Failed);
+        }
+    };
+
+    let mut rx_buffer = [0; 512];
+    let response = match with_timeout(TIMEOUT, request.send(&mut rx_buffer)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            warn!("HTTP request error: {:?}", e);
+            return Err(RegistrationError::Failed);
+        }
+        Err(_) => {
+            warn!("Timeout");
+            return Err(RegistrationError::Failed);
+        }
+    };
+
+    match response.status.into() {
+        Status::Ok | Status::Created => {}
+        Status::Unauthorized | Status::Forbidden => return Err(RegistrationError::Refused),
+        _ => {
+            warn!("HTTP registration failed: {:?}", response.status);
+            return Err(RegistrationError::Failed);
+        }
+    }
+
+    let body = match with_timeout(TIMEOUT, response.body().read_to_end()).await {
+        Ok(Ok(body)) => body,
+        _ => {
+            warn!("Failed to read device key");
+            return Err(RegistrationError::Failed);
+        }
+    };
+
+    DeviceKey::from_hex(body.trim_ascii()).ok_or_else(|| {
+        warn!("Invalid device key");
+        RegistrationError::Failed
+    })
+}
+
+/// Requests a new key from the backend, signed with the current one if the device is registered.
+pub async fn register_device(context: &mut Context) -> AppState {
+    let message = match register_again(context).await {
+        Ok(()) => "Device registered",
+        Err(RegistrationFailure::WifiNotEnabled) => "WiFi not enabled",
+        Err(RegistrationFailure::WifiNotConnected) => "Could not connect to WiFi",
+        Err(RegistrationFailure::InternalError) => "Registration failed: internal error",
+        Err(RegistrationFailure::Tls(failure)) => failure.message(),
+        Err(RegistrationFailure::Registration(error)) => error.message(),
+    };
+
+    context.display_message(message).await;
+
+    AppState::Menu(AppMenu::DeviceInfo)
+}
+
+enum RegistrationFailure {
+    WifiNotEnabled,
+    WifiNotConnected,
+    InternalError,
+    Tls(TlsFailure),
+    Registration(RegistrationError),
+}
+
+async fn register_again(context: &mut Context) -> Result<(), RegistrationFailure> {
+    let Some(sta) = context.enable_wifi_sta(StaMode::Enable).await else {
+        return Err(RegistrationFailure::WifiNotEnabled);
+    };
+    if !sta.wait_for_connection(context).await {
+        return Err(RegistrationFailure::WifiNotConnected);
+    }
+
+    let Ok(mut client_resources) = sta.https_client_resources(context) else {
+        return Err(RegistrationFailure::InternalError);
+    };
+    let mut client = client_resources.client();
+
+    if context.clock.now().is_none() {
+        return Err(RegistrationFailure::Registration(
+            RegistrationError::ClockNotSet,
+        ));
+    }
+
+    let current = DeviceKey::load().await;
+    match register_and_store(context, &mut client, current.as_ref()).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("Registration failed: {:?}", e);
+            Err(client_resources.tls_failure().map_or(
+                RegistrationFailure::Registration(e),
+                RegistrationFailure::Tls,
+            ))
+        }
+    }
+}
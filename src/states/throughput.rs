@@ -7,9 +7,16 @@ use reqwless::{request::Method, response::Status};
 use ufmt::{uwrite, uwriteln};
 
 use crate::{
-    board::initialized::{Context, StaMode},
+    board::{
+        device_key::AuthHeaders,
+        initialized::{Context, StaMode},
+        wifi::tls::TlsFailure,
+    },
     human_readable::{BinarySize, Throughput},
-    states::menu::AppMenu,
+    states::{
+        menu::AppMenu,
+        registration::{self, RegistrationError},
+    },
     AppState, SerialNumber,
 };
 
@@ -23,6 +30,8 @@ enum TestError {
     InternalError,
     HttpConnectionFailed,
     HttpConnectionTimeout,
+    Tls(TlsFailure),
+    Registration(RegistrationError),
     HttpRequestTimeout,
     HttpRequestFailed,
     DownloadFailed,
@@ -53,6 +62,8 @@ pub async fn throughput(context: &mut Context) -> AppState {
             TestError::WifiNotConnected => "Could not connect to WiFi",
             TestError::InternalError => "Test failed: internal error",
             TestError::HttpConnectionFailed => "Failed to connect to server",
+            TestError::Tls(failure) => failure.message(),
+            TestError::Registration(error) => error.message(),
             TestError::HttpConnectionTimeout => "Connection to server timed out",
             TestError::HttpRequestTimeout => "Test request timed out",
             TestError::HttpRequestFailed => "Failed to access test data",
@@ -77,18 +88,41 @@ async fn run_test(context: &mut Context) -> TestResult {
         return TestResult::Failed(TestError::WifiNotEnabled);
     };
 
-    let Ok(mut client_resources) = sta.https_client_resources() else {
+    let Ok(mut client_resources) = sta.https_client_resources(context) else {
         return TestResult::Failed(TestError::InternalError);
     };
     let mut client = client_resources.client();
 
+    let key = match registration::device_key(context, &mut client).await {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("Not registered: {:?}", e);
+            let error = client_resources
+                .tls_failure()
+                .map_or(TestError::Registration(e), TestError::Tls);
+            return TestResult::Failed(error);
+        }
+    };
+
+    let mut path = heapless::String::<64>::new();
+    if uwrite!(
+        &mut path,
+        "/firmware/{}/{}/0000000",
+        env!("HW_VERSION"),
+        SerialNumber
+    )
+    .is_err()
+    {
+        error!("Path too long");
+        return TestResult::Failed(TestError::InternalError);
+    }
+
     let mut url = heapless::String::<128>::new();
     if uwrite!(
         &mut url,
-        "{}/firmware/{}/{}/0000000",
+        "{}{}",
         context.config.backend_url.as_str(),
-        env!("HW_VERSION"),
-        SerialNumber
+        path.as_str()
     )
     .is_err()
     {
@@ -96,6 +130,11 @@ async fn run_test(context: &mut Context) -> TestResult {
         return TestResult::Failed(TestError::InternalError);
     }
 
+    let Some(auth) = AuthHeaders::new(&key, &context.clock, "GET", &path, &[], &[]) else {
+        return TestResult::Failed(TestError::Registration(RegistrationError::ClockNotSet));
+    };
+    let auth_headers = auth.headers();
+
     debug!("Testing throughput using {}", url.as_str());
 
     let connect = with_timeout(CONNECT_TIMEOUT, async {
@@ -112,10 +151,13 @@ async fn run_test(context: &mut Context) -> TestResult {
     });
 
     let mut request = match connect.await {
-        Ok(Ok(request)) => request,
+        Ok(Ok(request)) => request.headers(&auth_headers),
         Ok(Err(e)) => {
             warn!("HTTP connect error: {:?}", e);
-            return TestResult::Failed(TestError::HttpConnectionFailed);
+            let error = client_resources
+                .tls_failure()
+                .map_or(TestError::HttpConnectionFailed, TestError::Tls);
+            return TestResult::Failed(error);
         }
         _ => return TestResult::Failed(TestError::HttpConnectionTimeout),
     };
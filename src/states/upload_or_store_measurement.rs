@@ -1,6 +1,7 @@
 use core::str;
 
 use alloc::boxed::Box;
+use embassy_futures::yield_now;
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_menu::{
     builder::MenuBuilder,
@@ -11,14 +12,16 @@ use embedded_menu::{
 };
 use gui::{embedded_layout::object_chain, screens::create_menu};
 use norfs::{medium::StorageMedium, writer::FileDataWriter, OnCollision, Storage, StorageError};
-use signal_processing::compressing_buffer::{CompressingBuffer, EkgFormat};
+use signal_processing::compressing_buffer::{header::MeasurementHeader, CompressingBuffer};
 use ufmt::uwrite;
 
 use crate::{
-    board::initialized::Context, human_readable::BinarySize, states::menu::MenuScreen, uformat,
-    AppState,
+    board::{catalog::RecordingInfo, initialized::Context, storage::FileSystem},
+    human_readable::BinarySize,
+    states::menu::MenuScreen,
+    uformat, AppState,
 };
-use config_types::types::MeasurementAction;
+use config_types::types::{MeasurementAction, RetentionPolicy};
 
 #[cfg(feature = "wifi")]
 pub async fn upload_stored_measurements(context: &mut Context, next_state: AppState) -> AppState {
@@ -30,26 +33,17 @@ pub async fn upload_stored_measurements(context: &mut Context, next_state: AppSt
 pub async fn upload_or_store_measurement<const SIZE: usize>(
     context: &mut Context,
     mut buffer: Box<CompressingBuffer<SIZE>>,
+    header: Box<MeasurementHeader>,
     next_state: AppState,
 ) -> AppState {
-    let sample_count = buffer.len();
-    let samples = buffer.make_contiguous();
-
-    const SAMPLE_RATE: usize = 1000; // samples/sec
-
-    debug!("Measurement length: {} samples", sample_count);
-
-    if sample_count < 20 * SAMPLE_RATE {
-        if context.config.measurement_action != MeasurementAction::Discard {
-            // We don't want to store too-short measurements.
-            debug!("Measurement is too short to upload or store.");
-            context
-                .display_message("Measurement too short, discarding")
-                .await;
-        }
+    if is_too_short(context, &buffer, &header).await {
         return next_state;
     }
 
+    let info = RecordingInfo::new(&header, buffer.frame_count());
+    let header = EncodedHeader::new(&header);
+    let samples = buffer.make_contiguous();
+
     let (can_upload, can_store) = match context.config.measurement_action {
         MeasurementAction::Ask => ask_for_measurement_action(context).await,
         MeasurementAction::Auto => (true, true),
@@ -61,7 +55,7 @@ pub async fn upload_or_store_measurement<const SIZE: usize>(
     let store_after_upload = if can_upload {
         cfg_if::cfg_if! {
             if #[cfg(feature = "wifi")] {
-                let upload_result = try_to_upload(context, samples).await;
+                let upload_result = try_to_upload(context, &header, samples).await;
                 debug!("Upload result: {:?}", upload_result);
                 upload_result == StoreMeasurement::Store
             } else {
@@ -73,10 +67,10 @@ pub async fn upload_or_store_measurement<const SIZE: usize>(
     };
 
     if can_store && store_after_upload {
-        let store_result = try_store_measurement(context, samples).await;
+        let store_result = try_store_measurement(context, &header, samples, info).await;
 
         if let Err(e) = store_result {
-            context.display_message("Could not store measurement").await;
+            context.display_message(store_error_message(&e)).await;
             error!("Failed to store measurement: {:?}", e);
         }
     }
@@ -95,6 +89,56 @@ pub async fn upload_or_store_measurement<const SIZE: usize>(
     next_state
 }
 
+/// Stores the measurement without trying to upload it, so that the remaining charge is enough.
+pub async fn store_measurement_on_low_battery<const SIZE: usize>(
+    context: &mut Context,
+    mut buffer: Box<CompressingBuffer<SIZE>>,
+    header: Box<MeasurementHeader>,
+) -> AppState {
+    context.display_message("Battery low").await;
+
+    if context.config.measurement_action == MeasurementAction::Discard
+        || is_too_short(context, &buffer, &header).await
+    {
+        return AppState::Shutdown;
+    }
+
+    let info = RecordingInfo::new(&header, buffer.frame_count());
+    let header = EncodedHeader::new(&header);
+    let samples = buffer.make_contiguous();
+
+    if let Err(e) = try_store_measurement(context, &header, samples, info).await {
+        context.display_message(store_error_message(&e)).await;
+        error!("Failed to store measurement: {:?}", e);
+    }
+
+    AppState::Shutdown
+}
+
+async fn is_too_short<const SIZE: usize>(
+    context: &mut Context,
+    buffer: &CompressingBuffer<SIZE>,
+    header: &MeasurementHeader,
+) -> bool {
+    let sample_count = buffer.frame_count();
+
+    debug!("Measurement length: {} samples", sample_count);
+
+    if sample_count >= 20 * header.sample_rate as usize {
+        return false;
+    }
+
+    if context.config.measurement_action != MeasurementAction::Discard {
+        // We don't want to store too-short measurements.
+        debug!("Measurement is too short to upload or store.");
+        context
+            .display_message("Measurement too short, discarding")
+            .await;
+    }
+
+    true
+}
+
 async fn ask_for_measurement_action(context: &mut Context) -> (bool, bool) {
     let network_configured =
         !context.config.backend_url.is_empty() && !context.config.known_networks.is_empty();
@@ -196,7 +240,9 @@ impl MenuScreen for AskForMeasurementActionMenu {
 
 async fn try_store_measurement(
     context: &mut Context,
+    header: &EncodedHeader,
     measurement: &[u8],
+    info: RecordingInfo,
 ) -> Result<(), StorageError> {
     debug!("Trying to store measurement");
 
@@ -206,70 +252,83 @@ async fn try_store_measurement(
         return Ok(());
     };
 
-    let meas_idx = find_measurement_index(storage).await?;
+    let policy = context.inner.config.retention_policy;
+    store_measurement(storage, header, measurement, info, policy).await?;
 
-    let mut filename = heapless::String::<16>::new();
-    unwrap!(uwrite!(&mut filename, "meas.{}", meas_idx));
+    context.signal_sta_work_available(true);
 
-    storage
-        .store_writer(
-            &filename,
-            &MeasurementWriter(measurement),
-            OnCollision::Fail,
-        )
+    Ok(())
+}
+
+fn store_error_message(error: &StorageError) -> &'static str {
+    match error {
+        StorageError::InsufficientSpace => "Storage full",
+        _ => "Could not store measurement",
+    }
+}
+
+/// Stores the measurement in a new file and adds it to the catalog.
+pub async fn store_measurement(
+    storage: &mut FileSystem,
+    header: &EncodedHeader,
+    measurement: &[u8],
+    info: RecordingInfo,
+    policy: RetentionPolicy,
+) -> Result<(), StorageError> {
+    let writer = MeasurementWriter {
+        header,
+        samples: measurement,
+    };
+
+    let (catalog, storage) = storage.split_catalog();
+    let entry = catalog
+        .begin_store(storage, info, writer.estimate_length(), policy)
         .await?;
 
-    info!("Measurement saved to {}", filename);
+    let filename = entry.file_name();
+    let result = storage
+        .store_writer(&filename, &writer, OnCollision::Fail)
+        .await;
 
-    context.signal_sta_work_available(true);
+    catalog
+        .finish_store(storage, &entry, result.is_ok())
+        .await?;
+    result?;
+
+    info!("Measurement saved to {}", filename);
 
     Ok(())
 }
 
-async fn find_measurement_index<M>(storage: &mut Storage<M>) -> Result<u32, StorageError>
-where
-    M: StorageMedium,
-    [(); M::BLOCK_COUNT]:,
-{
-    let mut max_index = None;
-    let mut dir = storage.read_dir().await?;
-    let mut buffer = [0; 64];
-    while let Some(file) = dir.next(storage).await? {
-        match file.name(storage, &mut buffer).await {
-            Ok(name) => {
-                if let Some(idx) = name
-                    .strip_prefix("meas.")
-                    .and_then(|s| s.parse::<u32>().ok())
-                {
-                    let update_max = if let Some(max) = max_index {
-                        idx > max
-                    } else {
-                        true
-                    };
-
-                    if update_max {
-                        max_index = Some(idx);
-                    }
-                }
-            }
-            Err(StorageError::InsufficientBuffer) => {
-                // not a measurement file, ignore
-            }
-            Err(e) => {
-                warn!("Failed to read file name: {:?}", e);
-                return Err(e);
-            }
-        }
+/// Format version and the format-specific bytes that precede the samples.
+pub struct EncodedHeader {
+    bytes: [u8; MeasurementHeader::MAX_ENCODED_LEN],
+    len: usize,
+}
+
+impl EncodedHeader {
+    pub fn new(header: &MeasurementHeader) -> Self {
+        let mut bytes = [0; MeasurementHeader::MAX_ENCODED_LEN];
+        let len = header.encoded_len();
+        unwrap!(header.write(&mut &mut bytes[..]).ok());
+
+        Self { bytes, len }
+    }
+
+    fn version(&self) -> u8 {
+        self.bytes[0]
     }
 
-    Ok(max_index.map(|idx| idx + 1).unwrap_or(0))
+    fn format_bytes(&self) -> &[u8] {
+        &self.bytes[1..self.len]
+    }
 }
 
-struct MeasurementWriter<'a>(&'a [u8]);
+const WRITE_CHUNK_SIZE: usize = 1024;
 
-impl<'a> MeasurementWriter<'a> {
-    // We're good with a straight u8 until 127 samples, then we can consider switching to varint.
-    const FORMAT_VERSION: u8 = EkgFormat::VERSION;
+struct MeasurementWriter<'a> {
+    header: &'a EncodedHeader,
+    samples: &'a [u8],
 }
 
 impl FileDataWriter for MeasurementWriter<'_> {
@@ -287,16 +346,19 @@ impl FileDataWriter for MeasurementWriter<'_> {
 
         let mut writer = writer.bind(storage);
 
-        writer
-            .write_all(&Self::FORMAT_VERSION.to_le_bytes())
-            .await?;
-        writer.write_all(self.0).await?;
+        writer.write_all(&[self.header.version()]).await?;
+        writer.write_all(self.header.format_bytes()).await?;
+        // Writing in chunks lets a measurement that is still running process its samples.
+        for chunk in self.samples.chunks(WRITE_CHUNK_SIZE) {
+            writer.write_all(chunk).await?;
+            yield_now().await;
+        }
 
         Ok(())
     }
 
     fn estimate_length(&self) -> usize {
-        Self::FORMAT_VERSION.to_le_bytes().len() + self.0.len()
+        1 + self.header.format_bytes().len() + self.samples.len()
     }
 }
 
@@ -304,17 +366,24 @@ impl FileDataWriter for MeasurementWriter<'_> {
 mod wifi {
     use super::*;
     use crate::{
-        board::initialized::{InnerContext, StaMode},
+        board::{
+            catalog::{CatalogEntry, MAX_ENTRIES},
+            clock::WallClock,
+            device_key::{AuthHeaders, DeviceKey},
+            initialized::{InnerContext, StaMode},
+            wifi::tls::TlsFailure,
+        },
+        states::registration,
         SerialNumber,
     };
-    use embassy_time::{with_timeout, Duration};
+    use embassy_time::{with_timeout, Duration, Timer};
     use embedded_nal_async::{Dns, TcpConnect};
-    use norfs::read_dir::DirEntry;
     use reqwless::{
         client::HttpClient,
         request::{Method, RequestBody, RequestBuilder},
-        response::Status,
+        response::{Response, Status},
     };
+    use sha2::{Digest, Sha256};
 
     /// Whether to store the measurement or not. Used instead of a bool to reduce confusion.
     #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -324,7 +393,11 @@ mod wifi {
         DontStore,
     }
 
-    pub async fn try_to_upload(context: &mut Context, buffer: &[u8]) -> StoreMeasurement {
+    pub async fn try_to_upload(
+        context: &mut Context,
+        header: &EncodedHeader,
+        buffer: &[u8],
+    ) -> StoreMeasurement {
         if context.config.backend_url.is_empty() {
             debug!("No backend URL configured, not uploading.");
             return StoreMeasurement::Store;
@@ -342,19 +415,35 @@ mod wifi {
         };
 
         // If we found a network, attempt to upload.
-        // TODO: only try to upload if we are registered.
         debug!("Trying to upload measurement");
 
-        let Ok(mut client_resources) = sta.https_client_resources() else {
+        let Ok(mut client_resources) = sta.https_client_resources(context) else {
             context.display_message("Out of memory").await;
             return StoreMeasurement::Store;
         };
         let mut client = client_resources.client();
 
+        let key = match registration::device_key(context, &mut client).await {
+            Ok(key) => key,
+            Err(e) => {
+                warn!("Not registered: {:?}", e);
+                let message = client_resources
+                    .tls_failure()
+                    .map_or(e.message(), TlsFailure::message);
+                context.display_message(message).await;
+                return StoreMeasurement::Store;
+            }
+        };
+
         match upload_measurement(
             &mut client,
-            0,
-            MeasurementRef { version: 0, buffer },
+            MeasurementRef {
+                version: header.version() as u32,
+                header: header.format_bytes(),
+                buffer,
+            },
+            None,
+            &key,
             &mut context.inner,
         )
         .await
@@ -366,7 +455,10 @@ mod wifi {
             }
             Err(_) => {
                 warn!("Failed to upload measurement");
-                context.display_message("Upload failed").await;
+                let message = client_resources
+                    .tls_failure()
+                    .map_or("Upload failed", TlsFailure::message);
+                context.display_message(message).await;
                 StoreMeasurement::Store
             }
         }
@@ -389,80 +481,73 @@ mod wifi {
             .display_message("Uploading stored measurements...")
             .await;
 
-        let Some(storage) = context.storage.as_mut() else {
-            context.display_message("Storage not available").await;
+        let Ok(mut client_resources) = sta.https_client_resources(context) else {
+            context.display_message("Out of memory").await;
             return;
         };
+        let mut client = client_resources.client();
 
-        let Ok(mut dir) = storage.read_dir().await else {
-            context.display_message("Could not read storage").await;
-            return;
+        let key = match registration::device_key(context, &mut client).await {
+            Ok(key) => key,
+            Err(e) => {
+                warn!("Not registered: {:?}", e);
+                let message = client_resources
+                    .tls_failure()
+                    .map_or(e.message(), TlsFailure::message);
+                context.display_message(message).await;
+                context.signal_sta_work_available(true);
+                return;
+            }
         };
 
-        let mut fn_buffer = [0; 64];
-
-        let Ok(mut client_resources) = sta.https_client_resources() else {
-            context.display_message("Out of memory").await;
+        let Some(storage) = context.storage.as_mut() else {
+            context.display_message("Storage not available").await;
             return;
         };
-        let mut client = client_resources.client();
+
+        // Copied, because the catalog is updated while uploading.
+        let mut pending = heapless::Vec::<CatalogEntry, MAX_ENTRIES>::new();
+        pending.extend(storage.catalog().pending().copied());
 
         let mut success = true;
-        loop {
-            match dir.next(storage).await {
-                Ok(file) => {
-                    let Some(file) = file else {
-                        debug!("File is None");
-                        break;
-                    };
-
-                    match file.name(storage, &mut fn_buffer).await {
-                        Ok(name) if name.starts_with("meas.") => {
-                            let Ok((file, buffer)) = load_measurement(file, storage).await else {
-                                warn!("Failed to load {}", name);
-                                continue;
-                            };
-
-                            if let Err(e) = upload_measurement(
-                                &mut client,
-                                0,
-                                buffer.as_ref(),
-                                &mut context.inner,
-                            )
-                            .await
-                            {
-                                warn!("Failed to upload {}: {:?}", name, e);
-                                success = false;
-                                break;
-                            }
-
-                            info!("Uploaded {}", name);
-                            if let Err(e) = file.delete(storage).await {
-                                warn!("Failed to delete file: {:?}", e);
-                            }
-                        }
-                        Ok(_) | Err(StorageError::InsufficientBuffer) => {
-                            // not a measurement file, ignore
-                        }
-                        Err(e) => {
-                            warn!("Failed to read file name: {:?}", e);
-                            success = false;
-                            break;
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to read directory: {:?}", e);
-                    success = false;
-                    break;
-                }
+        let count = pending.len();
+        for (index, entry) in pending.into_iter().enumerate() {
+            let name = entry.file_name();
+
+            let Ok(measurement) = load_measurement(storage, &entry).await else {
+                warn!("Failed to load {}", name);
+                continue;
+            };
+
+            if let Err(e) = upload_measurement(
+                &mut client,
+                measurement.as_ref(),
+                Some(FileProgress { index, count }),
+                &key,
+                &mut context.inner,
+            )
+            .await
+            {
+                warn!("Failed to upload {}: {:?}", name, e);
+                success = false;
+                break;
+            }
+
+            info!("Uploaded {}", name);
+
+            // The file is kept until the space is needed.
+            let (catalog, storage) = storage.split_catalog();
+            if let Err(e) = catalog.mark_uploaded(storage, entry.index).await {
+                warn!("Failed to update catalog: {:?}", e);
             }
         }
 
         let message = if success {
             "Upload successful"
         } else {
-            "Failed to upload measurements"
+            client_resources
+                .tls_failure()
+                .map_or("Failed to upload measurements", TlsFailure::message)
         };
         context.display_message(message).await;
 
@@ -478,6 +563,8 @@ mod wifi {
         fn as_ref(&self) -> MeasurementRef<'_> {
             MeasurementRef {
                 version: self.version,
+                // Stored measurements contain their format-specific header.
+                header: &[],
                 buffer: &self.buffer,
             }
         }
@@ -485,41 +572,46 @@ mod wifi {
 
     pub struct MeasurementRef<'a> {
         version: u32,
+        header: &'a [u8],
         buffer: &'a [u8],
     }
 
-    impl RequestBody for MeasurementRef<'_> {
-        fn len(&self) -> Option<usize> {
-            Some(self.buffer.len() + 4)
-        }
-
-        async fn write<W: embedded_io_async::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
-            writer.write_all(&self.version.to_le_bytes()).await?;
-            writer.write_all(self.buffer).await?;
+    impl MeasurementRef<'_> {
+        /// Returns the start of the measurement in seconds since the Unix epoch, or 0 if unknown.
+        fn timestamp(&self) -> u64 {
+            let mut reader = if self.header.is_empty() {
+                self.buffer
+            } else {
+                self.header
+            };
 
-            Ok(())
+            u8::try_from(self.version)
+                .ok()
+                .and_then(|version| MeasurementHeader::read_with_version(version, &mut reader).ok())
+                .and_then(|header| header.start_time)
+                .unwrap_or(0)
         }
     }
 
-    pub async fn load_measurement<M>(
-        file: DirEntry<M>,
-        storage: &mut Storage<M>,
-    ) -> Result<(DirEntry<M>, Measurement), ()>
-    where
-        M: StorageMedium,
-        [(); M::BLOCK_COUNT]:,
-    {
-        let Ok(size) = file.size(storage).await else {
-            warn!("Failed to read size");
-            return Err(());
+    pub async fn load_measurement(
+        storage: &mut FileSystem,
+        entry: &CatalogEntry,
+    ) -> Result<Measurement, ()> {
+        let mut reader = match storage.read(&entry.file_name()).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("Failed to open file: {:?}", e);
+                return Err(());
+            }
         };
 
+        // The version is not part of the buffer.
+        let size = (entry.size as usize).saturating_sub(1);
         let Ok(mut buffer) = buffer_with_capacity(size, 0) else {
             warn!("Failed to allocate {} bytes", size);
             return Err(());
         };
 
-        let mut reader = file.open();
         let version = reader.read_loadable::<u8>(storage).await;
         let version = match version {
             Ok(version) => version,
@@ -534,13 +626,10 @@ mod wifi {
             return Err(());
         };
 
-        Ok((
-            DirEntry::from_reader(reader),
-            Measurement {
-                version: version as u32,
-                buffer,
-            },
-        ))
+        Ok(Measurement {
+            version: version as u32,
+            buffer,
+        })
     }
 
     fn buffer_with_capacity<T: Copy>(size: usize, init_val: T) -> Result<Box<[T]>, ()> {
@@ -562,32 +651,66 @@ mod wifi {
         Ok(buffer.into_boxed_slice())
     }
 
+    /// Every request opens a new connection, so this is a trade-off between the handshake
+    /// overhead and the data that is sent again when a connection drops.
+    const CHUNK_SIZE: usize = 16 * 1024;
+    const MAX_ATTEMPTS: u32 = 5;
+    const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(16);
+
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+    const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Which of the files being uploaded is the current one.
+    #[derive(Clone, Copy)]
+    pub struct FileProgress {
+        pub index: usize,
+        pub count: usize,
+    }
+
+    enum ChunkError {
+        /// The request may succeed if it is sent again.
+        Retry,
+        /// The backend refused the upload.
+        Rejected,
+    }
+
+    /// Uploads a measurement in chunks. The backend keeps track of how much of an upload it has
+    /// received, so an interrupted upload continues where it stopped, even after a restart.
+    ///
+    /// Uploads are identified by the hash of their contents. `GET /upload_data/{serial}/{id}`
+    /// returns the number of bytes the backend has, `POST` with an `X-Upload-Offset` header
+    /// appends a chunk and returns the new offset. If the offset doesn't match, the backend
+    /// answers `409 Conflict` with the offset it expects.
     pub async fn upload_measurement<T, DNS>(
         client: &mut HttpClient<'_, T, DNS>,
-        meas_timestamp: u64,
         samples: MeasurementRef<'_>,
+        progress: Option<FileProgress>,
+        key: &DeviceKey,
         context: &mut InnerContext,
     ) -> Result<(), ()>
     where
         T: TcpConnect,
         DNS: Dns,
     {
-        let uploading_msg = uformat!(
-            32,
-            "Uploading measurement: {}",
-            BinarySize(samples.buffer.len())
-        );
-        context.display_message(uploading_msg.as_str()).await;
-
-        const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
-        const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
-
-        let mut upload_url = heapless::String::<128>::new();
+        let version = samples.version.to_le_bytes();
+        let body = [&version[..], samples.header, samples.buffer];
+        let total = body.iter().map(|part| part.len()).sum::<usize>();
+
+        let mut path = heapless::String::<64>::new();
+        unwrap!(uwrite!(
+            &mut path,
+            "/upload_data/{}/{}",
+            SerialNumber,
+            upload_id(&body).as_str()
+        ));
+
+        let mut url = heapless::String::<128>::new();
         if uwrite!(
-            &mut upload_url,
-            "{}/upload_data/{}",
+            &mut url,
+            "{}{}",
             context.config.backend_url.as_str(),
-            SerialNumber
+            path.as_str()
         )
         .is_err()
         {
@@ -595,48 +718,300 @@ mod wifi {
             return Err(());
         }
 
-        let mut timestamp = heapless::String::<32>::new();
-        unwrap!(uwrite!(&mut timestamp, "{}", meas_timestamp));
+        let mut timestamp = heapless::String::<20>::new();
+        unwrap!(uwrite!(&mut timestamp, "{}", samples.timestamp()));
 
-        debug!("Uploading measurement to {}", upload_url);
+        debug!("Uploading measurement to {}", url);
 
-        let headers = [("X-Timestamp", timestamp.as_str())];
+        let upload = Upload {
+            url: &url,
+            path: &path,
+            key,
+            timestamp: &timestamp,
+            body: &body,
+            total,
+        };
 
-        let mut request =
-            match with_timeout(CONNECT_TIMEOUT, client.request(Method::POST, &upload_url)).await {
-                Ok(Ok(request)) => request.headers(&headers).body(samples),
-                Ok(Err(e)) => {
-                    warn!("HTTP connect error: {:?}", e);
-                    return Err(());
+        // Unknown until the backend is asked, and after a failed request.
+        let mut offset = None;
+        // The most the backend has confirmed. Only getting past it resets the retries, so failing
+        // chunks can't be retried forever by asking for the offset in between.
+        let mut confirmed = 0;
+        let mut attempts = 0;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let result = match offset {
+                None => upload.query_offset(client, &context.clock).await,
+                Some(offset) => {
+                    show_progress(context, progress, offset, total).await;
+                    upload.send_chunk(client, &context.clock, offset).await
                 }
-                _ => {
-                    warn!("Conect timeout");
-                    return Err(());
+            };
+
+            match result {
+                Ok(received) if received >= total => return Ok(()),
+                Ok(received) if received > confirmed => {
+                    offset = Some(received);
+                    confirmed = received;
+                    attempts = 0;
+                    backoff = INITIAL_BACKOFF;
                 }
+                // Learning the offset is not a failure, but not progress either.
+                Ok(received) if offset.is_none() => offset = Some(received),
+                Err(ChunkError::Rejected) => return Err(()),
+                // A chunk that was not accepted is retried like a failed request.
+                Ok(_) | Err(ChunkError::Retry) => {
+                    attempts += 1;
+                    if attempts == MAX_ATTEMPTS {
+                        warn!("Giving up after {} attempts", attempts);
+                        return Err(());
+                    }
+
+                    // The chunk may have arrived even if the response didn't.
+                    offset = None;
+
+                    let message =
+                        uformat!(40, "Upload interrupted, retrying in {}s", backoff.as_secs());
+                    context.display_message(message.as_str()).await;
+                    Timer::after(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+
+    async fn show_progress(
+        context: &mut InnerContext,
+        progress: Option<FileProgress>,
+        offset: usize,
+        total: usize,
+    ) {
+        let percent = offset * 100 / total;
+        let message = match progress {
+            Some(progress) => uformat!(
+                32,
+                "Uploading {}/{}: {}%",
+                progress.index + 1,
+                progress.count,
+                percent
+            ),
+            None => uformat!(32, "Uploading measurement: {}%", percent),
+        };
+        context.display_message(message.as_str()).await;
+    }
+
+    /// Identifies the upload of a measurement, so that it can be resumed.
+    fn upload_id(body: &[&[u8]; 3]) -> heapless::String<16> {
+        let mut hasher = Sha256::new();
+        for part in body {
+            hasher.update(part);
+        }
+        let hash = hasher.finalize();
+
+        let mut id = heapless::String::new();
+        unwrap!(uwrite!(
+            &mut id,
+            "{:x}",
+            u64::from_be_bytes(unwrap!(hash[..8].try_into().ok()))
+        ));
+        id
+    }
+
+    struct Upload<'a> {
+        url: &'a str,
+        /// Relative to the backend URL, for signing.
+        path: &'a str,
+        key: &'a DeviceKey,
+        timestamp: &'a str,
+        body: &'a [&'a [u8]; 3],
+        total: usize,
+    }
+
+    impl Upload<'_> {
+        async fn query_offset<T, DNS>(
+            &self,
+            client: &mut HttpClient<'_, T, DNS>,
+            clock: &WallClock,
+        ) -> Result<usize, ChunkError>
+        where
+            T: TcpConnect,
+            DNS: Dns,
+        {
+            // The clock was set when the key was loaded, see `registration::device_key`.
+            let Some(auth) = AuthHeaders::new(self.key, clock, "GET", self.path, &[], &[]) else {
+                return Err(ChunkError::Rejected);
             };
+            let headers = auth.headers();
+
+            let mut request =
+                match with_timeout(CONNECT_TIMEOUT, client.request(Method::GET, self.url)).await {
+                    Ok(Ok(request)) => request.headers(&headers),
+                    Ok(Err(e)) => {
+                        warn!("HTTP connect error: {:?}", e);
+                        return Err(ChunkError::Retry);
+                    }
+                    Err(_) => {
+                        warn!("Connect timeout");
+                        return Err(ChunkError::Retry);
+                    }
+                };
 
-        let mut rx_buffer = [0; 512];
-        match with_timeout(UPLOAD_TIMEOUT, request.send(&mut rx_buffer)).await {
-            Ok(Ok(response)) => {
-                if [Status::Ok, Status::Created].contains(&response.status.into()) {
-                    return Ok(());
+            let mut rx_buffer = [0; 512];
+            let response = match with_timeout(UPLOAD_TIMEOUT, request.send(&mut rx_buffer)).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => {
+                    warn!("HTTP request error: {:?}", e);
+                    return Err(ChunkError::Retry);
                 }
+                Err(_) => {
+                    warn!("Timeout");
+                    return Err(ChunkError::Retry);
+                }
+            };
 
-                warn!("HTTP upload failed: {:?}", response.status);
-                for header in response.headers() {
-                    if !header.0.is_empty() {
-                        debug!(
-                            "Header {}: {}",
-                            header.0,
-                            str::from_utf8(header.1).unwrap_or("not a string")
-                        );
+            match response.status.into() {
+                Status::Ok => read_offset(response).await,
+                // The backend has not seen this upload.
+                Status::NotFound => Ok(0),
+                status => Err(status_error(status)),
+            }
+        }
+
+        async fn send_chunk<T, DNS>(
+            &self,
+            client: &mut HttpClient<'_, T, DNS>,
+            clock: &WallClock,
+            offset: usize,
+        ) -> Result<usize, ChunkError>
+        where
+            T: TcpConnect,
+            DNS: Dns,
+        {
+            let chunk = Chunk::new(self.body, offset, CHUNK_SIZE);
+
+            let mut offset_header = heapless::String::<20>::new();
+            unwrap!(uwrite!(&mut offset_header, "{}", offset));
+            let mut length_header = heapless::String::<20>::new();
+            unwrap!(uwrite!(&mut length_header, "{}", self.total));
+
+            let upload_headers = [
+                ("X-Timestamp", self.timestamp),
+                ("X-Upload-Offset", offset_header.as_str()),
+                ("X-Upload-Length", length_header.as_str()),
+            ];
+
+            let Some(auth) = AuthHeaders::new(
+                self.key,
+                clock,
+                "POST",
+                self.path,
+                &upload_headers,
+                &chunk.0,
+            ) else {
+                return Err(ChunkError::Rejected);
+            };
+            let [auth_time, auth_signature] = auth.headers();
+
+            let [timestamp, upload_offset, upload_length] = upload_headers;
+            let headers = [
+                timestamp,
+                upload_offset,
+                upload_length,
+                auth_time,
+                auth_signature,
+            ];
+
+            let mut request =
+                match with_timeout(CONNECT_TIMEOUT, client.request(Method::POST, self.url)).await {
+                    Ok(Ok(request)) => request.headers(&headers).body(chunk),
+                    Ok(Err(e)) => {
+                        warn!("HTTP connect error: {:?}", e);
+                        return Err(ChunkError::Retry);
+                    }
+                    Err(_) => {
+                        warn!("Connect timeout");
+                        return Err(ChunkError::Retry);
                     }
+                };
+
+            let mut rx_buffer = [0; 512];
+            let response = match with_timeout(UPLOAD_TIMEOUT, request.send(&mut rx_buffer)).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => {
+                    warn!("HTTP upload error: {:?}", e);
+                    return Err(ChunkError::Retry);
+                }
+                Err(_) => {
+                    warn!("Timeout");
+                    return Err(ChunkError::Retry);
                 }
+            };
+
+            match response.status.into() {
+                Status::Ok | Status::Created | Status::Conflict => read_offset(response).await,
+                status => Err(status_error(status)),
             }
-            Ok(Err(e)) => warn!("HTTP upload error: {:?}", e),
-            _ => warn!("Timeout"),
         }
-        Err(())
+    }
+
+    async fn read_offset<C>(response: Response<'_, '_, C>) -> Result<usize, ChunkError>
+    where
+        C: embedded_io_async::Read,
+    {
+        let body = match with_timeout(UPLOAD_TIMEOUT, response.body().read_to_end()).await {
+            Ok(Ok(body)) => body,
+            _ => {
+                warn!("Failed to read response");
+                return Err(ChunkError::Retry);
+            }
+        };
+
+        str::from_utf8(body)
+            .ok()
+            .and_then(|body| body.trim().parse().ok())
+            .ok_or_else(|| {
+                warn!("Invalid upload offset");
+                ChunkError::Rejected
+            })
+    }
+
+    fn status_error(status: Status) -> ChunkError {
+        warn!("HTTP upload failed: {:?}", status);
+        match status {
+            Status::BadRequest | Status::Unauthorized | Status::Forbidden => ChunkError::Rejected,
+            _ => ChunkError::Retry,
+        }
+    }
+
+    /// A range of the upload body.
+    struct Chunk<'a>([&'a [u8]; 3]);
+
+    impl<'a> Chunk<'a> {
+        fn new(body: &[&'a [u8]; 3], offset: usize, len: usize) -> Self {
+            let mut skip = offset;
+            let mut remaining = len;
+            Self(body.map(|part| {
+                let start = skip.min(part.len());
+                let end = (start + remaining).min(part.len());
+                skip -= start;
+                remaining -= end - start;
+                &part[start..end]
+            }))
+        }
+    }
+
+    impl RequestBody for Chunk<'_> {
+        fn len(&self) -> Option<usize> {
+            Some(self.0.iter().map(|part| part.len()).sum())
+        }
+
+        async fn write<W: embedded_io_async::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+            for part in self.0 {
+                writer.write_all(part).await?;
+            }
+
+            Ok(())
+        }
     }
 }
 
@@ -0,0 +1,928 @@
+//! Host-side decoder for stored and uploaded measurements.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result as AnyResult};
+use clap::ValueEnum;
+use signal_processing::compressing_buffer::{
+    header::{lead_status, ChannelKind, MeasurementHeader},
+    InterleavedFormat,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Comma separated values, one row per frame, in microvolts.
+    Csv,
+    /// EDF+ with lead status changes as annotations.
+    Edf,
+    /// WFDB (PhysioNet) record in format 212.
+    Wfdb212,
+    /// WFDB (PhysioNet) record in format 16.
+    Wfdb16,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Edf => "edf",
+            OutputFormat::Wfdb212 | OutputFormat::Wfdb16 => "hea",
+        }
+    }
+}
+
+pub struct DecodeOptions {
+    pub input: PathBuf,
+    pub format: Option<OutputFormat>,
+    pub output: Option<PathBuf>,
+    pub upload_body: bool,
+    pub reference: f64,
+}
+
+pub fn decode(options: DecodeOptions) -> AnyResult<()> {
+    let data = fs::read(&options.input)
+        .with_context(|| format!("Failed to read {}", options.input.display()))?;
+
+    let recording = Recording::parse(&data, options.upload_body, options.reference)?;
+
+    print!("{}", recording.info());
+
+    let Some(format) = options.format else {
+        return Ok(());
+    };
+
+    let output = options
+        .output
+        .unwrap_or_else(|| options.input.with_extension(format.extension()));
+
+    match format {
+        OutputFormat::Csv => {
+            let mut file = io::BufWriter::new(fs::File::create(&output)?);
+            recording.write_csv(&mut file)?;
+            file.flush()?;
+        }
+        OutputFormat::Edf => {
+            let mut file = io::BufWriter::new(fs::File::create(&output)?);
+            recording.write_edf(&mut file)?;
+            file.flush()?;
+        }
+        OutputFormat::Wfdb212 => recording.write_wfdb(&output, WfdbFormat::F212)?,
+        OutputFormat::Wfdb16 => recording.write_wfdb(&output, WfdbFormat::F16)?,
+    }
+
+    println!("Written {}", output.display());
+
+    Ok(())
+}
+
+pub struct Recording {
+    version: u32,
+    header: MeasurementHeader,
+    /// Raw ADC codes, one vector per channel.
+    channels: Vec<Vec<i32>>,
+    /// Input-referred voltage of one LSB, in microvolts.
+    microvolts_per_lsb: f64,
+}
+
+impl Recording {
+    /// Parses a `meas.N` file or, if `upload_body` is set, a request body sent to the backend.
+    pub fn parse(data: &[u8], upload_body: bool, reference: f64) -> AnyResult<Self> {
+        let mut reader = data;
+
+        let version = if upload_body {
+            let Some((version, rest)) = reader.split_first_chunk::<4>() else {
+                bail!("Upload body is too short");
+            };
+            reader = rest;
+            u32::from_le_bytes(*version)
+        } else {
+            let Some((&version, rest)) = reader.split_first() else {
+                bail!("Measurement is empty");
+            };
+            reader = rest;
+            version as u32
+        };
+
+        let Ok(header_version) = u8::try_from(version) else {
+            bail!("Unknown format version {version}");
+        };
+        let header = MeasurementHeader::read_with_version(header_version, &mut reader)
+            .map_err(|err| anyhow::anyhow!("Failed to read header: {err:?}"))?;
+
+        let channel_count = header.channel_count as usize;
+        let mut format = InterleavedFormat::new(channel_count);
+        let mut channels = vec![Vec::new(); channel_count];
+        let mut channel = 0;
+        while let Ok(Some(sample)) = format.read(&mut reader) {
+            channels[channel].push(sample);
+            channel = (channel + 1) % channel_count;
+        }
+
+        // Drop an incomplete last frame.
+        let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+        for channel in channels.iter_mut() {
+            channel.truncate(frames);
+        }
+
+        // Full scale is ±VREF / gain, represented in 24 bits.
+        let gain = header.gain.max(1) as f64;
+        let microvolts_per_lsb = reference * 1_000_000.0 / (gain * (1 << 23) as f64);
+
+        Ok(Self {
+            version,
+            header,
+            channels,
+            microvolts_per_lsb,
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    fn duration(&self) -> f64 {
+        self.frame_count() as f64 / self.header.sample_rate as f64
+    }
+
+    fn serial(&self) -> Option<String> {
+        self.header
+            .serial
+            .map(|serial| serial.iter().map(|b| format!("{b:02X}")).collect())
+    }
+
+    /// Returns the signal name of a channel, numbered from 1.
+    fn channel_label(&self, channel: usize) -> String {
+        let kind = match self.header.channel_kinds()[channel - 1] {
+            ChannelKind::Ecg => "ECG",
+            ChannelKind::Impedance => "Resp",
+        };
+        format!("{kind} CH{channel}")
+    }
+
+    fn microvolts(&self, raw: i32) -> f64 {
+        raw as f64 * self.microvolts_per_lsb
+    }
+
+    pub fn info(&self) -> String {
+        let header = &self.header;
+        let mut info = String::new();
+
+        let unknown = || String::from("unknown");
+
+        _ = writeln!(info, "Format version:  {}", self.version);
+        _ = writeln!(
+            info,
+            "Channels:        {}",
+            (1..=self.channels.len())
+                .map(|channel| self.channel_label(channel))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        _ = writeln!(info, "Sample rate:     {} sps", header.sample_rate);
+        _ = writeln!(
+            info,
+            "Gain:            {}",
+            match header.gain {
+                0 => String::from("unknown, assuming 1x"),
+                gain => format!("{gain}x"),
+            }
+        );
+        _ = writeln!(
+            info,
+            "Start time:      {}",
+            header.start_time.map_or_else(unknown, |time| {
+                let (date, time) = civil_time(time);
+                format!(
+                    "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                    date.0, date.1, date.2, time.0, time.1, time.2
+                )
+            })
+        );
+        _ = writeln!(
+            info,
+            "Serial:          {}",
+            self.serial().unwrap_or_else(unknown)
+        );
+        _ = writeln!(
+            info,
+            "Firmware:        {}",
+            match header.commit() {
+                "" => unknown(),
+                commit => commit.to_string(),
+            }
+        );
+        _ = writeln!(
+            info,
+            "Filters:         {}",
+            self.filter_description().unwrap_or_else(unknown)
+        );
+        _ = writeln!(
+            info,
+            "Length:          {} frames, {:.3} s",
+            self.frame_count(),
+            self.duration()
+        );
+        if let Some(session) = header.session {
+            _ = writeln!(
+                info,
+                "Session:         {:08x}, segment {}",
+                session.id, session.segment
+            );
+            if session.dropped_frames != 0 {
+                _ = writeln!(
+                    info,
+                    "Gap:             {} frames, {:.3} s lost before this segment",
+                    session.dropped_frames,
+                    session.dropped_frames as f64 / header.sample_rate as f64
+                );
+            }
+        }
+        for event in header.lead_off_events() {
+            _ = writeln!(
+                info,
+                "Lead status:     {:.3} s: {}",
+                event.frame as f64 / header.sample_rate as f64,
+                lead_status_text(event.status, header.channel_count)
+            );
+        }
+        if header.lead_off_events_truncated() {
+            _ = writeln!(
+                info,
+                "Lead status:     some changes before the last one were not recorded"
+            );
+        }
+
+        info
+    }
+
+    fn filter_description(&self) -> Option<String> {
+        let filters = self.header.filters;
+        let mut parts = Vec::new();
+
+        if filters.high_pass_cutoff_millihertz != 0 {
+            parts.push(format!(
+                "HP:{}Hz",
+                filters.high_pass_cutoff_millihertz as f64 / 1000.0
+            ));
+        }
+        if filters.power_line_frequency != 0 {
+            parts.push(format!("N:{}Hz", filters.power_line_frequency));
+        }
+
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "time")?;
+        for channel in 1..=self.channels.len() {
+            write!(out, ",ch{channel}_uV")?;
+        }
+        writeln!(out)?;
+
+        for frame in 0..self.frame_count() {
+            write!(out, "{:.4}", frame as f64 / self.header.sample_rate as f64)?;
+            for channel in self.channels.iter() {
+                write!(out, ",{:.3}", self.microvolts(channel[frame]))?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes an EDF+ file with one-second data records. The last record is padded by repeating
+    /// the last frame.
+    pub fn write_edf(&self, out: &mut impl Write) -> io::Result<()> {
+        let samples_per_record = self.header.sample_rate as usize;
+        let frames = self.frame_count();
+        let record_count = frames.div_ceil(samples_per_record).max(1);
+
+        let quantizers = self
+            .channels
+            .iter()
+            .map(|samples| Quantizer::new(samples, 1 << 16))
+            .collect::<Vec<_>>();
+
+        let annotations = (0..record_count)
+            .map(|record| self.edf_annotations(record, samples_per_record))
+            .collect::<Vec<_>>();
+        let annotation_samples = annotations
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+            .div_ceil(2);
+
+        let signal_count = self.channels.len() + 1;
+
+        // Header
+        // EDF uses 1985-01-01 as a placeholder for unknown dates.
+        const UNKNOWN_START: u64 = 473_385_600;
+        let (start_date, start_time) = civil_time(self.header.start_time.unwrap_or(UNKNOWN_START));
+        let recording_date = match self.header.start_time {
+            Some(_) => format!(
+                "{:02}-{}-{}",
+                start_date.2,
+                MONTHS[start_date.1 as usize - 1],
+                start_date.0
+            ),
+            None => String::from("X"),
+        };
+        let equipment = match self.serial() {
+            Some(serial) => format!("Card/IO_{serial}"),
+            None => String::from("Card/IO"),
+        };
+
+        let mut header = String::new();
+        edf_field(&mut header, "0", 8);
+        edf_field(&mut header, "X X X X", 80);
+        edf_field(
+            &mut header,
+            &format!("Startdate {recording_date} X X {equipment}"),
+            80,
+        );
+        edf_field(
+            &mut header,
+            &format!(
+                "{:02}.{:02}.{:02}",
+                start_date.2,
+                start_date.1,
+                start_date.0 % 100
+            ),
+            8,
+        );
+        edf_field(
+            &mut header,
+            &format!(
+                "{:02}.{:02}.{:02}",
+                start_time.0, start_time.1, start_time.2
+            ),
+            8,
+        );
+        edf_field(&mut header, &(256 * (signal_count + 1)).to_string(), 8);
+        edf_field(&mut header, "EDF+C", 44);
+        edf_field(&mut header, &record_count.to_string(), 8);
+        edf_field(&mut header, "1", 8);
+        edf_field(&mut header, &signal_count.to_string(), 4);
+
+        // Signal headers are stored field by field.
+        let prefilter = self
+            .filter_description()
+            .map(|filters| format!("Displayed with {filters}; stored unfiltered"))
+            .unwrap_or_default();
+
+        for channel in 1..=self.channels.len() {
+            edf_field(&mut header, &self.channel_label(channel), 16);
+        }
+        edf_field(&mut header, "EDF Annotations", 16);
+
+        for _ in self.channels.iter() {
+            edf_field(&mut header, "AgAgCl electrode", 80);
+        }
+        edf_field(&mut header, "", 80);
+
+        for _ in self.channels.iter() {
+            edf_field(&mut header, "uV", 8);
+        }
+        edf_field(&mut header, "", 8);
+
+        for quantizer in quantizers.iter() {
+            let physical = self.microvolts(quantizer.raw(i16::MIN as i32));
+            edf_field(&mut header, &edf_number(physical), 8);
+        }
+        edf_field(&mut header, "-1", 8);
+
+        for quantizer in quantizers.iter() {
+            let physical = self.microvolts(quantizer.raw(i16::MAX as i32));
+            edf_field(&mut header, &edf_number(physical), 8);
+        }
+        edf_field(&mut header, "1", 8);
+
+        for _ in 0..signal_count {
+            edf_field(&mut header, &i16::MIN.to_string(), 8);
+        }
+        for _ in 0..signal_count {
+            edf_field(&mut header, &i16::MAX.to_string(), 8);
+        }
+
+        for _ in self.channels.iter() {
+            edf_field(&mut header, &prefilter, 80);
+        }
+        edf_field(&mut header, "", 80);
+
+        for _ in self.channels.iter() {
+            edf_field(&mut header, &samples_per_record.to_string(), 8);
+        }
+        edf_field(&mut header, &annotation_samples.to_string(), 8);
+
+        for _ in 0..signal_count {
+            edf_field(&mut header, "", 32);
+        }
+
+        debug_assert_eq!(header.len(), 256 * (signal_count + 1));
+        out.write_all(header.as_bytes())?;
+
+        // Data records
+        for (record, annotation) in annotations.iter().enumerate() {
+            let first = record * samples_per_record;
+            for (samples, quantizer) in self.channels.iter().zip(quantizers.iter()) {
+                for frame in first..first + samples_per_record {
+                    let raw = samples
+                        .get(frame.min(frames.saturating_sub(1)))
+                        .copied()
+                        .unwrap_or(0);
+                    let digital = quantizer.digital(raw) as i16;
+                    out.write_all(&digital.to_le_bytes())?;
+                }
+            }
+
+            out.write_all(annotation)?;
+            let padding = annotation_samples * 2 - annotation.len();
+            out.write_all(&vec![0; padding])?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the time-keeping TAL and the lead status changes of a data record.
+    fn edf_annotations(&self, record: usize, samples_per_record: usize) -> Vec<u8> {
+        let mut tal = format!("+{record}\x14\x14\x00");
+
+        let frames = record * samples_per_record..(record + 1) * samples_per_record;
+        for event in self.header.lead_off_events() {
+            if frames.contains(&(event.frame as usize)) {
+                _ = write!(
+                    tal,
+                    "+{}\x14{}\x14\x00",
+                    event.frame as f64 / self.header.sample_rate as f64,
+                    lead_status_text(event.status, self.header.channel_count)
+                );
+            }
+        }
+
+        tal.into_bytes()
+    }
+
+    /// Writes a WFDB record. `header_path` is the path of the `.hea` file, the signal file is
+    /// placed next to it.
+    pub fn write_wfdb(&self, header_path: &Path, format: WfdbFormat) -> AnyResult<()> {
+        let Some(record_name) = header_path.file_stem().and_then(|stem| stem.to_str()) else {
+            bail!("Invalid output path: {}", header_path.display());
+        };
+        let dat_path = header_path.with_extension("dat");
+
+        let quantizers = self
+            .channels
+            .iter()
+            .map(|samples| Quantizer::new(samples, format.levels()))
+            .collect::<Vec<_>>();
+
+        let mut data = Vec::new();
+        self.write_wfdb_data(&quantizers, format, &mut data)?;
+        fs::write(&dat_path, data)?;
+
+        let mut header = io::BufWriter::new(fs::File::create(header_path)?);
+        self.write_wfdb_header(record_name, &quantizers, format, &mut header)?;
+        header.flush()?;
+
+        Ok(())
+    }
+
+    fn write_wfdb_header(
+        &self,
+        record_name: &str,
+        quantizers: &[Quantizer],
+        format: WfdbFormat,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        write!(
+            out,
+            "{record_name} {} {} {}",
+            self.channels.len(),
+            self.header.sample_rate,
+            self.frame_count()
+        )?;
+        if let Some(start_time) = self.header.start_time {
+            let (date, time) = civil_time(start_time);
+            write!(
+                out,
+                " {:02}:{:02}:{:02} {:02}/{:02}/{:04}",
+                time.0, time.1, time.2, date.2, date.1, date.0
+            )?;
+        }
+        writeln!(out)?;
+
+        for (channel, (samples, quantizer)) in self.channels.iter().zip(quantizers).enumerate() {
+            let adc_gain = 1.0 / (quantizer.step as f64 * self.microvolts_per_lsb);
+            let baseline = -quantizer.center / quantizer.step;
+            let initial = samples.first().map_or(0, |raw| quantizer.digital(*raw));
+            let checksum = samples.iter().fold(0i16, |sum, raw| {
+                sum.wrapping_add(quantizer.digital(*raw) as i16)
+            });
+
+            writeln!(
+                out,
+                "{record_name}.dat {} {adc_gain}({baseline})/uV {} 0 {initial} {checksum} 0 {}",
+                format.code(),
+                format.bits(),
+                self.channel_label(channel + 1)
+            )?;
+        }
+
+        writeln!(out, "# Card/IO measurement")?;
+        if let Some(serial) = self.serial() {
+            writeln!(out, "# Serial: {serial}")?;
+        }
+        if !self.header.commit().is_empty() {
+            writeln!(out, "# Firmware: {}", self.header.commit())?;
+        }
+        if let Some(filters) = self.filter_description() {
+            writeln!(out, "# Display filters (not applied): {filters}")?;
+        }
+        for event in self.header.lead_off_events() {
+            writeln!(
+                out,
+                "# Lead status at sample {}: {}",
+                event.frame,
+                lead_status_text(event.status, self.header.channel_count)
+            )?;
+        }
+        if self.header.lead_off_events_truncated() {
+            writeln!(
+                out,
+                "# Some lead status changes before the last one were not recorded"
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn write_wfdb_data(
+        &self,
+        quantizers: &[Quantizer],
+        format: WfdbFormat,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let digital = (0..self.frame_count()).flat_map(|frame| {
+            self.channels
+                .iter()
+                .zip(quantizers)
+                .map(move |(samples, quantizer)| quantizer.digital(samples[frame]))
+        });
+
+        match format {
+            WfdbFormat::F16 => {
+                for sample in digital {
+                    out.write_all(&(sample as i16).to_le_bytes())?;
+                }
+            }
+            WfdbFormat::F212 => {
+                // Pairs of 12-bit samples are packed into 3 bytes.
+                let samples = digital.collect::<Vec<_>>();
+                for pair in samples.chunks(2) {
+                    let first = pair[0] as u16 & 0xFFF;
+                    out.write_all(&[first as u8])?;
+                    match pair.get(1) {
+                        Some(&second) => {
+                            let second = second as u16 & 0xFFF;
+                            out.write_all(&[
+                                ((first >> 8) | ((second >> 4) & 0xF0)) as u8,
+                                second as u8,
+                            ])?;
+                        }
+                        None => out.write_all(&[(first >> 8) as u8])?,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WfdbFormat {
+    F212,
+    F16,
+}
+
+impl WfdbFormat {
+    fn code(self) -> u32 {
+        match self {
+            WfdbFormat::F212 => 212,
+            WfdbFormat::F16 => 16,
+        }
+    }
+
+    /// Resolution of the stored samples. Raw ADC codes are requantized to fit.
+    fn bits(self) -> u32 {
+        match self {
+            WfdbFormat::F212 => 12,
+            WfdbFormat::F16 => 16,
+        }
+    }
+
+    fn levels(self) -> i64 {
+        1 << self.bits()
+    }
+}
+
+/// Maps 24-bit ADC codes onto a narrower digital range: `raw ≈ digital * step + center`.
+///
+/// `center` is a multiple of `step`, so the physical zero maps to an integer digital value.
+struct Quantizer {
+    step: i64,
+    center: i64,
+}
+
+impl Quantizer {
+    fn new(samples: &[i32], levels: i64) -> Self {
+        let min = samples.iter().copied().min().unwrap_or(0) as i64;
+        let max = samples.iter().copied().max().unwrap_or(0) as i64;
+
+        // Leave room for rounding, and keep the lowest value free as WFDB reserves it.
+        let step = ((max - min) / (levels - 4) + 1).max(1);
+        let center = ((min + max) / 2).div_euclid(step) * step;
+
+        Self { step, center }
+    }
+
+    fn digital(&self, raw: i32) -> i32 {
+        let offset = raw as i64 - self.center;
+        ((offset + self.step / 2).div_euclid(self.step)) as i32
+    }
+
+    fn raw(&self, digital: i32) -> i32 {
+        (digital as i64 * self.step + self.center) as i32
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// Appends an ASCII field, space padded or truncated to `len` bytes.
+fn edf_field(header: &mut String, value: &str, len: usize) {
+    let value = &value[..value.len().min(len)];
+    _ = write!(header, "{value:<len$}");
+}
+
+/// Formats a number to fit into an 8 character EDF header field.
+fn edf_number(value: f64) -> String {
+    for decimals in (0..=3).rev() {
+        let formatted = format!("{value:.decimals$}");
+        if formatted.len() <= 8 {
+            return formatted;
+        }
+    }
+    format!("{}", value.round() as i64)
+}
+
+fn lead_status_text(status: u8, channel_count: u8) -> String {
+    let leads = [
+        ("CH1+", lead_status::CH1_POSITIVE),
+        ("CH1-", lead_status::CH1_NEGATIVE),
+        ("CH2+", lead_status::CH2_POSITIVE),
+        ("CH2-", lead_status::CH2_NEGATIVE),
+    ];
+
+    let disconnected = leads[..2 * channel_count as usize]
+        .iter()
+        .filter(|(_, bit)| status & bit == 0)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    if disconnected.is_empty() {
+        String::from("all leads connected")
+    } else {
+        format!("lead off {}", disconnected.join(" "))
+    }
+}
+
+/// Converts seconds since the Unix epoch to UTC `(year, month, day)` and `(hour, minute, second)`.
+fn civil_time(timestamp: u64) -> ((i64, u32, u32), (u32, u32, u32)) {
+    let days = (timestamp / 86400) as i64;
+    let seconds = (timestamp % 86400) as u32;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (
+        (year, month, day),
+        (seconds / 3600, seconds / 60 % 60, seconds % 60),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use signal_processing::compressing_buffer::header::{LeadOffEvent, Session};
+
+    fn encode(header: &MeasurementHeader, samples: &[i32]) -> Vec<u8> {
+        let mut buffer = vec![0; header.encoded_len()];
+        header.write(&mut &mut buffer[..]).unwrap();
+
+        let mut format = InterleavedFormat::new(header.channel_count as usize);
+        for sample in samples {
+            let mut bytes = [0; 8];
+            let mut writer = &mut bytes[..];
+            let len = format.write(*sample, &mut writer).unwrap();
+            buffer.extend_from_slice(&bytes[..len]);
+        }
+
+        buffer
+    }
+
+    fn recording() -> Recording {
+        let mut header = MeasurementHeader::new(2, 4);
+        header.gain = 1;
+        header.start_time = Some(1_700_000_000);
+        header.push_lead_off_event(LeadOffEvent {
+            frame: 5,
+            status: lead_status::CH1_POSITIVE | lead_status::CH1_NEGATIVE,
+        });
+
+        let samples = (0..6)
+            .flat_map(|i| [i * 1000, -i * 1000])
+            .collect::<Vec<_>>();
+        Recording::parse(&encode(&header, &samples), false, 2.42).unwrap()
+    }
+
+    #[test]
+    fn decodes_stored_measurement() {
+        let recording = recording();
+
+        assert_eq!(recording.version, MeasurementHeader::VERSION as u32);
+        assert_eq!(recording.frame_count(), 6);
+        assert_eq!(recording.channels[0], [0, 1000, 2000, 3000, 4000, 5000]);
+        assert_eq!(
+            recording.channels[1],
+            [0, -1000, -2000, -3000, -4000, -5000]
+        );
+    }
+
+    #[test]
+    fn decodes_upload_body() {
+        let header = MeasurementHeader::new(1, 500);
+
+        let mut body = (MeasurementHeader::VERSION as u32).to_le_bytes().to_vec();
+        body.extend_from_slice(&encode(&header, &[1, 2, 3])[1..]);
+
+        let recording = Recording::parse(&body, true, 2.42).unwrap();
+        assert_eq!(recording.header, header);
+        assert_eq!(recording.channels, [[1, 2, 3]]);
+    }
+
+    #[test]
+    fn info_shows_segment_gap() {
+        let mut header = MeasurementHeader::new(1, 500);
+        header.session = Some(Session {
+            id: 0xC0FFEE,
+            segment: 2,
+            dropped_frames: 250,
+        });
+        let recording = Recording::parse(&encode(&header, &[1, 2, 3]), false, 2.42).unwrap();
+
+        let info = recording.info();
+        assert!(info.contains("Session:         00c0ffee, segment 2\n"));
+        assert!(info.contains("Gap:             250 frames, 0.500 s lost before this segment\n"));
+    }
+
+    #[test]
+    fn channel_kinds_name_the_signals() {
+        let mut header = MeasurementHeader::new(2, 4);
+        header.channel_kinds = [ChannelKind::Impedance, ChannelKind::Ecg];
+        let recording = Recording::parse(&encode(&header, &[1, 2, 3, 4]), false, 2.42).unwrap();
+
+        let mut edf = Vec::new();
+        recording.write_edf(&mut edf).unwrap();
+        let labels = std::str::from_utf8(&edf[256..256 + 32]).unwrap();
+        assert_eq!(labels, "Resp CH1        ECG CH2         ");
+    }
+
+    #[test]
+    fn wfdb_header_describes_signals() {
+        let mut header = MeasurementHeader::new(2, 4);
+        header.channel_kinds = [ChannelKind::Impedance, ChannelKind::Ecg];
+        let recording = Recording::parse(&encode(&header, &[1, 2, 3, 4]), false, 2.42).unwrap();
+
+        for format in [WfdbFormat::F212, WfdbFormat::F16] {
+            let quantizers = recording
+                .channels
+                .iter()
+                .map(|samples| Quantizer::new(samples, format.levels()))
+                .collect::<Vec<_>>();
+
+            let mut out = Vec::new();
+            recording
+                .write_wfdb_header("rec", &quantizers, format, &mut out)
+                .unwrap();
+
+            let out = String::from_utf8(out).unwrap();
+            let signals = out.lines().skip(1).take(2).collect::<Vec<_>>();
+            for (signal, label) in signals.iter().zip(["Resp CH1", "ECG CH2"]) {
+                let fields = signal.split_whitespace().collect::<Vec<_>>();
+                assert_eq!(fields[1], format.code().to_string());
+                assert_eq!(fields[3], format.bits().to_string());
+                assert_eq!(fields[8..].join(" "), label);
+            }
+        }
+    }
+
+    #[test]
+    fn csv_contains_scaled_samples() {
+        let mut csv = Vec::new();
+        recording().write_csv(&mut csv).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("time,ch1_uV,ch2_uV"));
+        assert_eq!(lines.next(), Some("0.0000,0.000,0.000"));
+        assert_eq!(lines.next(), Some("0.2500,288.486,-288.486"));
+    }
+
+    #[test]
+    fn edf_records_are_complete() {
+        let mut edf = Vec::new();
+        recording().write_edf(&mut edf).unwrap();
+
+        let header = std::str::from_utf8(&edf[..256]).unwrap();
+        assert_eq!(&header[168..184], "14.11.2322.13.20");
+        assert_eq!(header[236..244].trim(), "2");
+        assert_eq!(header[252..256].trim(), "3");
+
+        let signals = &edf[256..4 * 256];
+        let annotation_samples = std::str::from_utf8(&signals[216 * 3..216 * 3 + 24])
+            .unwrap()
+            .split_whitespace()
+            .map(|s| s.parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(annotation_samples[..2], [4, 4]);
+
+        let record_len = 2 * (4 + 4 + annotation_samples[2]);
+        assert_eq!(edf.len(), 4 * 256 + 2 * record_len);
+    }
+
+    #[test]
+    fn wfdb_212_packs_sample_pairs() {
+        let recording = recording();
+        let quantizers = recording
+            .channels
+            .iter()
+            .map(|samples| Quantizer::new(samples, WfdbFormat::F212.levels()))
+            .collect::<Vec<_>>();
+
+        let mut data = Vec::new();
+        recording
+            .write_wfdb_data(&quantizers, WfdbFormat::F212, &mut data)
+            .unwrap();
+
+        // 12 samples, 3 bytes per pair
+        assert_eq!(data.len(), 18);
+
+        let unpack = |bytes: &[u8]| {
+            let first = bytes[0] as u16 | ((bytes[1] as u16 & 0x0F) << 8);
+            let second = bytes[2] as u16 | ((bytes[1] as u16 & 0xF0) << 4);
+            let sign_extend = |v: u16| ((v << 4) as i16 >> 4) as i32;
+            (sign_extend(first), sign_extend(second))
+        };
+        for (frame, bytes) in data.chunks(3).enumerate() {
+            let (ch1, ch2) = unpack(bytes);
+            assert_eq!(quantizers[0].raw(ch1), recording.channels[0][frame]);
+            assert_eq!(quantizers[1].raw(ch2), recording.channels[1][frame]);
+        }
+    }
+
+    #[test]
+    fn quantizer_fits_full_scale() {
+        let samples = [-(1 << 23), (1 << 23) - 1];
+        let quantizer = Quantizer::new(&samples, 1 << 16);
+
+        for sample in samples {
+            let digital = quantizer.digital(sample);
+            assert!(digital > i16::MIN as i32 && digital <= i16::MAX as i32);
+            assert!((quantizer.raw(digital) - sample).abs() <= quantizer.step as i32 / 2);
+        }
+    }
+
+    #[test]
+    fn unix_time_is_converted_to_utc() {
+        assert_eq!(civil_time(0), ((1970, 1, 1), (0, 0, 0)));
+        assert_eq!(civil_time(1_700_000_000), ((2023, 11, 14), (22, 13, 20)));
+        assert_eq!(civil_time(951_782_400), ((2000, 2, 29), (0, 0, 0)));
+        assert_eq!(civil_time(473_385_600), ((1985, 1, 1), (0, 0, 0)));
+    }
+}
@@ -1,8 +1,22 @@
+use std::path::PathBuf;
+
 use anyhow::Result as AnyResult;
 use clap::{Parser, Subcommand, ValueEnum};
 
 use duct::{cmd, Expression};
 
+use crate::{
+    decode::{DecodeOptions, OutputFormat},
+    mock_backend::{BackendOptions, Failure},
+    sign::SignOptions,
+};
+
+mod decode;
+mod mock_backend;
+mod ntp_server;
+mod sign;
+mod test_ca;
+
 #[derive(Debug, Subcommand)]
 pub enum Subcommands {
     /// Builds the firmware.
@@ -70,6 +84,102 @@ pub enum Subcommands {
         #[clap(long)]
         watch: bool,
     },
+
+    /// Prints the metadata of a recorded measurement and optionally converts it.
+    Decode {
+        /// A `meas.N` file read from the device, or an upload body.
+        input: PathBuf,
+
+        /// Which format to convert to. Only prints metadata if omitted.
+        #[arg(long, short)]
+        format: Option<OutputFormat>,
+
+        /// Output file. Defaults to the input path with the format's extension.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Whether the input is an upload body, which starts with a 32-bit version.
+        #[arg(long)]
+        upload_body: bool,
+
+        /// ADC reference voltage, in volts.
+        #[arg(long, default_value_t = 2.42)]
+        reference: f64,
+    },
+
+    /// Runs a local SNTP server to test time synchronization against.
+    NtpServer {
+        /// Which UDP port to listen on.
+        #[arg(long, default_value_t = 123)]
+        port: u16,
+
+        /// Seconds to add to the host's time, to make the synchronized time easy to recognize.
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        offset: i64,
+    },
+
+    /// Generates a test CA and a server certificate for a local HTTPS backend.
+    TestCa {
+        /// The host name or IP address the device reaches the backend at.
+        host: String,
+
+        /// Where to write the keys and certificates.
+        #[arg(long, default_value = "target/test-ca")]
+        out: PathBuf,
+    },
+
+    /// Runs a local implementation of the backend API.
+    MockBackend {
+        /// Which TCP port to listen on.
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Where to store device keys and uploaded measurements.
+        #[arg(long, default_value = "target/mock-backend")]
+        out: PathBuf,
+
+        /// The signed firmware image to offer to devices.
+        #[arg(long, default_value = "target/card_io_fw.signed.bin")]
+        firmware: PathBuf,
+
+        /// The commit the firmware image was built from. Defaults to the current commit.
+        #[arg(long)]
+        firmware_version: Option<String>,
+
+        /// Whether to refuse to register devices.
+        #[arg(long)]
+        refuse_registration: bool,
+
+        /// Which failure to inject.
+        #[arg(long)]
+        fail: Option<Failure>,
+
+        /// Inject the failure into every Nth request.
+        #[arg(long, default_value_t = 3)]
+        fail_every: usize,
+    },
+
+    /// Generates the key firmware updates are signed with.
+    GenerateKey {
+        /// Where to write the secret key. The public key is written to `firmware-key.pub`.
+        #[arg(long, default_value = "firmware.key")]
+        out: PathBuf,
+    },
+
+    /// Signs a firmware image for over-the-air updates, and writes its manifest next to it.
+    Sign {
+        /// The firmware image to sign.
+        #[arg(default_value = "target/card_io_fw.bin")]
+        input: PathBuf,
+
+        /// The secret key generated by `generate-key`.
+        #[arg(long, default_value = "firmware.key")]
+        key: PathBuf,
+
+        /// Where to write the signed image. Defaults to the input path with `.signed.bin`.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
@@ -220,9 +330,20 @@ fn extra_checks(config: BuildConfig) -> AnyResult<()> {
 }
 
 fn test() -> AnyResult<()> {
-    let packages = ["signal-processing"];
+    let packages = [
+        "signal-processing",
+        "firmware-image",
+        "ads129x",
+        "max17055",
+        "xtask",
+        "config-site",
+    ];
 
-    let mut args = vec!["test", "--features=dyn_filter"];
+    let mut args = vec![
+        "test",
+        "--features=signal-processing/dyn_filter",
+        "--features=max17055/norfs",
+    ];
 
     for p in packages {
         args.push("-p");
@@ -278,6 +399,40 @@ fn main() -> AnyResult<()> {
             name,
             watch,
         } => example(package, name, watch),
+        Subcommands::Decode {
+            input,
+            format,
+            output,
+            upload_body,
+            reference,
+        } => decode::decode(DecodeOptions {
+            input,
+            format,
+            output,
+            upload_body,
+            reference,
+        }),
+        Subcommands::NtpServer { port, offset } => ntp_server::serve(port, offset),
+        Subcommands::TestCa { host, out } => test_ca::generate(&host, &out),
+        Subcommands::MockBackend {
+            port,
+            out,
+            firmware,
+            firmware_version,
+            refuse_registration,
+            fail,
+            fail_every,
+        } => mock_backend::serve(BackendOptions {
+            port,
+            out,
+            firmware,
+            firmware_version,
+            refuse_registration,
+            fail,
+            fail_every,
+        }),
+        Subcommands::GenerateKey { out } => sign::generate_key(&out),
+        Subcommands::Sign { input, key, output } => sign::sign(SignOptions { input, key, output }),
     }
 }
 
@@ -0,0 +1,632 @@
+//! A local implementation of the backend API, to test uploads and firmware updates against.
+//!
+//! Device keys and uploads are kept in the output directory, so the server can be restarted
+//! without registering the devices again. Completed uploads are decoded and written next to the
+//! raw upload body as CSV.
+
+use std::{
+    fs,
+    io::Write as _,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context as _, Result as AnyResult};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use tiny_http::{Header, Response, Server};
+
+use crate::decode::Recording;
+
+/// ADC reference voltage used to decode uploads, in volts.
+const REFERENCE: f64 = 2.42;
+
+/// Requests signed further from the server's time than this are logged.
+const MAX_CLOCK_SKEW: u64 = 300;
+
+/// Which failure to inject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Failure {
+    /// Answer with 503 Service Unavailable.
+    Error,
+    /// Answer after a minute, when the device has given up waiting.
+    Timeout,
+    /// Store half of an upload chunk, then answer with 503. Other requests fail like `error`.
+    Partial,
+}
+
+pub struct BackendOptions {
+    pub port: u16,
+    pub out: PathBuf,
+    pub firmware: PathBuf,
+    pub firmware_version: Option<String>,
+    pub refuse_registration: bool,
+    pub fail: Option<Failure>,
+    pub fail_every: usize,
+}
+
+pub fn serve(options: BackendOptions) -> AnyResult<()> {
+    let firmware_version = match options.firmware_version {
+        Some(version) => version,
+        // The firmware reports the commit it was built from.
+        None => duct::cmd!("git", "rev-parse", "--short", "HEAD")
+            .read()
+            .context("Failed to read the current commit, set --firmware-version")?,
+    };
+
+    let backend = Backend {
+        out: options.out,
+        firmware: options.firmware,
+        firmware_version,
+        refuse_registration: options.refuse_registration,
+    };
+
+    let server = Server::http(("0.0.0.0", options.port))
+        .map_err(|e| anyhow!("Failed to listen on port {}: {e}", options.port))?;
+
+    println!(
+        "🌐  Serving the backend API on port {}, set the backend URL to http://<this host>:{}",
+        options.port, options.port
+    );
+    println!("Data is stored in {}", backend.out.display());
+
+    for (index, mut incoming) in server.incoming_requests().enumerate() {
+        let failure = options
+            .fail
+            .filter(|_| options.fail_every != 0 && (index + 1) % options.fail_every == 0);
+
+        let mut body = Vec::new();
+        if let Err(e) = incoming.as_reader().read_to_end(&mut body) {
+            println!("Failed to read request body: {e}");
+            continue;
+        }
+
+        let request = Request {
+            method: incoming.method().as_str().to_string(),
+            path: incoming
+                .url()
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            headers: incoming
+                .headers()
+                .iter()
+                .map(|header| (header.field.to_string(), header.value.to_string()))
+                .collect(),
+            body,
+        };
+
+        let reply = backend.handle(&request, failure);
+        println!(
+            "{} {} ({} bytes) -> {}{}",
+            request.method,
+            request.path,
+            request.body.len(),
+            reply.status,
+            match failure {
+                Some(failure) => format!(" (injected {failure:?})"),
+                None => String::new(),
+            }
+        );
+
+        let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+        for (name, value) in reply.headers {
+            response.add_header(Header::from_bytes(name, value).unwrap());
+        }
+
+        if failure == Some(Failure::Timeout) {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_secs(60));
+                _ = incoming.respond(response);
+            });
+        } else if let Err(e) = incoming.respond(response) {
+            println!("Failed to respond: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    /// Without the query string.
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Reply {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+}
+
+struct Backend {
+    out: PathBuf,
+    firmware: PathBuf,
+    firmware_version: String,
+    refuse_registration: bool,
+}
+
+impl Backend {
+    fn handle(&self, request: &Request, failure: Option<Failure>) -> Reply {
+        let segments = request.path.split('/').skip(1).collect::<Vec<_>>();
+
+        // Only upload chunks can fail partially.
+        let partial = match failure {
+            Some(Failure::Partial) => true,
+            Some(_) => return Reply::new(503, "Injected failure"),
+            None => false,
+        };
+        if partial && !matches!(segments.as_slice(), ["upload_data", ..]) {
+            return Reply::new(503, "Injected failure");
+        }
+
+        if !segments.iter().skip(1).all(|segment| is_name(segment)) {
+            return Reply::new(400, "Invalid path");
+        }
+
+        let result = match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["register", serial]) => Ok(self.register(serial, request)),
+            ("GET", ["upload_data", serial, id]) => {
+                self.authorized(serial, request, || self.upload_offset(serial, id))
+            }
+            ("POST", ["upload_data", serial, id]) => self.authorized(serial, request, || {
+                self.append(serial, id, request, partial)
+            }),
+            ("GET", ["firmware", _hw, serial, commit]) => {
+                self.authorized(serial, request, || Ok(self.firmware(request, commit)))
+            }
+            _ => Ok(Reply::new(404, "Not found")),
+        };
+
+        result.unwrap_or_else(|e| {
+            println!("Error: {e:#}");
+            Reply::new(500, "Internal error")
+        })
+    }
+
+    fn key_file(&self, serial: &str) -> PathBuf {
+        self.out.join("devices").join(format!("{serial}.key"))
+    }
+
+    /// Issues a new key. A registered device has to sign the request with its current key.
+    fn register(&self, serial: &str, request: &Request) -> Reply {
+        if self.refuse_registration {
+            return Reply::new(403, "Registration refused");
+        }
+
+        if self.key_file(serial).exists() {
+            if let Err(e) = self.verify(serial, request) {
+                println!("Unauthorized: {e:#}");
+                return Reply::new(401, "Unauthorized");
+            }
+        }
+
+        let key = hex(&random_key(serial));
+        let file = self.key_file(serial);
+        if let Err(e) = write_file(&file, key.as_bytes()) {
+            println!("Error: {e:#}");
+            return Reply::new(500, "Failed to store key");
+        }
+
+        println!("Registered {serial}");
+        Reply::new(201, key)
+    }
+
+    fn authorized(
+        &self,
+        serial: &str,
+        request: &Request,
+        handler: impl FnOnce() -> AnyResult<Reply>,
+    ) -> AnyResult<Reply> {
+        if let Err(e) = self.verify(serial, request) {
+            println!("Unauthorized: {e:#}");
+            return Ok(Reply::new(401, "Unauthorized"));
+        }
+
+        handler()
+    }
+
+    /// Checks that the request is signed with the device's key.
+    fn verify(&self, serial: &str, request: &Request) -> AnyResult<()> {
+        let Ok(key) = fs::read_to_string(self.key_file(serial)) else {
+            return Err(anyhow!("{serial} is not registered"));
+        };
+        let key = unhex(key.trim()).context("Invalid key file")?;
+
+        let (Some(time), Some(signature)) = (
+            request.header("X-Auth-Time"),
+            request.header("X-Auth-Signature"),
+        ) else {
+            return Err(anyhow!("Request is not signed"));
+        };
+
+        let headers = SIGNED_HEADERS.map(|name| request.header(name).unwrap_or(""));
+        let expected = sign(
+            &key,
+            &request.method,
+            &request.path,
+            time,
+            &headers,
+            &request.body,
+        );
+        if signature != expected {
+            return Err(anyhow!("Invalid signature"));
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        match time.parse::<u64>() {
+            Ok(time) if time.abs_diff(now) <= MAX_CLOCK_SKEW => {}
+            _ => println!("Warning: request signed at {time}, the server's time is {now}"),
+        }
+
+        Ok(())
+    }
+
+    fn upload_files(&self, serial: &str, id: &str) -> (PathBuf, PathBuf) {
+        let dir = self.out.join("uploads").join(serial);
+        (
+            dir.join(format!("{id}.part")),
+            dir.join(format!("{id}.bin")),
+        )
+    }
+
+    fn upload_offset(&self, serial: &str, id: &str) -> AnyResult<Reply> {
+        let (partial, complete) = self.upload_files(serial, id);
+
+        Ok(match file_len(&complete).or_else(|| file_len(&partial)) {
+            Some(len) => Reply::new(200, len.to_string()),
+            None => Reply::new(404, "Unknown upload"),
+        })
+    }
+
+    fn append(&self, serial: &str, id: &str, request: &Request, partial: bool) -> AnyResult<Reply> {
+        let number = |name| request.header(name)?.parse::<u64>().ok();
+        let (Some(offset), Some(length)) = (number("X-Upload-Offset"), number("X-Upload-Length"))
+        else {
+            return Ok(Reply::new(400, "Missing upload offset or length"));
+        };
+
+        let (partial_file, complete_file) = self.upload_files(serial, id);
+
+        // The response to the last chunk may have been lost.
+        if let Some(len) = file_len(&complete_file) {
+            return Ok(Reply::new(200, len.to_string()));
+        }
+
+        let current = file_len(&partial_file).unwrap_or(0);
+        if offset != current {
+            return Ok(Reply::new(409, current.to_string()));
+        }
+
+        let chunk = if partial {
+            &request.body[..request.body.len() / 2]
+        } else {
+            &request.body[..]
+        };
+
+        let received = current + chunk.len() as u64;
+        if received > length {
+            return Ok(Reply::new(400, "Upload is longer than announced"));
+        }
+
+        fs::create_dir_all(partial_file.parent().unwrap())?;
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial_file)?
+            .write_all(chunk)?;
+
+        if partial {
+            return Ok(Reply::new(503, "Injected failure"));
+        }
+
+        if received < length {
+            return Ok(Reply::new(200, received.to_string()));
+        }
+
+        fs::rename(&partial_file, &complete_file)?;
+        println!("Upload {id} from {serial} complete");
+        store_decoded(&complete_file);
+
+        Ok(Reply::new(201, received.to_string()))
+    }
+
+    fn firmware(&self, request: &Request, commit: &str) -> Reply {
+        let etag = format!("\"{}\"", self.firmware_version);
+
+        if commit == self.firmware_version || request.header("If-None-Match") == Some(&etag) {
+            return Reply::new(304, "").with_header("ETag", etag);
+        }
+
+        let Ok(image) = fs::read(&self.firmware) else {
+            return Reply::new(404, "No firmware image");
+        };
+
+        // An interrupted download resumes, unless the image changed since it started.
+        let start = request
+            .header("Range")
+            .filter(|_| request.header("If-Range") == Some(&etag))
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.strip_suffix('-'))
+            .and_then(|start| start.parse::<usize>().ok());
+
+        match start {
+            Some(start) if start < image.len() => {
+                let range = format!("bytes {start}-{}/{}", image.len() - 1, image.len());
+                Reply::new(206, &image[start..])
+                    .with_header("ETag", etag)
+                    .with_header("Content-Range", range)
+            }
+            Some(_) => {
+                Reply::new(416, "").with_header("Content-Range", format!("bytes */{}", image.len()))
+            }
+            None => Reply::new(200, image).with_header("ETag", etag),
+        }
+    }
+}
+
+/// Decodes an upload body and writes it as CSV. Failures are logged, the raw data is kept.
+fn store_decoded(upload: &Path) {
+    let result = fs::read(upload)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| Recording::parse(&data, true, REFERENCE))
+        .and_then(|recording| {
+            print!("{}", recording.info());
+
+            let mut file = std::io::BufWriter::new(fs::File::create(upload.with_extension("csv"))?);
+            recording.write_csv(&mut file)?;
+            file.flush()?;
+            Ok(())
+        });
+
+    if let Err(e) = result {
+        println!("Failed to decode {}: {e:#}", upload.display());
+    }
+}
+
+/// Serials and upload ids are used as file names.
+fn is_name(segment: &str) -> bool {
+    !segment.is_empty() && segment.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn file_len(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.len())
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> AnyResult<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Good enough for a test server, not for anything else.
+fn random_key(serial: &str) -> [u8; 32] {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    let mut hasher = Sha256::new();
+    hasher.update(serial.as_bytes());
+    hasher.update(now.as_nanos().to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Request headers covered by the signature, in the order they are signed.
+const SIGNED_HEADERS: [&str; 3] = ["X-Timestamp", "X-Upload-Offset", "X-Upload-Length"];
+
+/// Signs a request the way the firmware does, see `board::device_key`. `headers` are the values
+/// of the [`SIGNED_HEADERS`], empty if the request doesn't have them.
+fn sign(key: &[u8], method: &str, path: &str, time: &str, headers: &[&str], body: &[u8]) -> String {
+    let body_hash = hex(&Sha256::digest(body));
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(format!("{method}\n{path}\n{time}\n").as_bytes());
+    for value in headers {
+        mac.update(format!("{value}\n").as_bytes());
+    }
+    mac.update(body_hash.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn unhex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn backend(name: &str) -> Backend {
+        let out = std::env::temp_dir().join(format!("mock-backend-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&out);
+
+        Backend {
+            out,
+            firmware: PathBuf::from("missing.bin"),
+            firmware_version: String::from("abcdef0"),
+            refuse_registration: false,
+        }
+    }
+
+    fn request(
+        key: &[u8],
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Request {
+        let mut request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_vec(),
+        };
+
+        let signed = SIGNED_HEADERS.map(|name| request.header(name).unwrap_or(""));
+        let signature = sign(key, method, path, "0", &signed, body);
+        request
+            .headers
+            .push((String::from("X-Auth-Time"), String::from("0")));
+        request
+            .headers
+            .push((String::from("X-Auth-Signature"), signature));
+
+        request
+    }
+
+    fn register(backend: &Backend) -> Vec<u8> {
+        let reply = backend.handle(&request(&[], "POST", "/register/ABC", &[], &[]), None);
+        assert_eq!(reply.status, 201);
+        unhex(std::str::from_utf8(&reply.body).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn interrupted_uploads_resume_at_the_stored_offset() {
+        let backend = backend("resume");
+        let key = register(&backend);
+
+        let path = "/upload_data/ABC/1234";
+        let chunk = |offset: &str, body: &[u8]| {
+            request(
+                &key,
+                "POST",
+                path,
+                &[("X-Upload-Offset", offset), ("X-Upload-Length", "8")],
+                body,
+            )
+        };
+
+        let reply = backend.handle(&request(&key, "GET", path, &[], &[]), None);
+        assert_eq!(reply.status, 404);
+
+        let reply = backend.handle(&chunk("0", b"0123"), Some(Failure::Partial));
+        assert_eq!(reply.status, 503);
+
+        let reply = backend.handle(&request(&key, "GET", path, &[], &[]), None);
+        assert_eq!((reply.status, reply.body.as_slice()), (200, &b"2"[..]));
+
+        let reply = backend.handle(&chunk("0", b"0123"), None);
+        assert_eq!((reply.status, reply.body.as_slice()), (409, &b"2"[..]));
+
+        let reply = backend.handle(&chunk("2", b"234567"), None);
+        assert_eq!((reply.status, reply.body.as_slice()), (201, &b"8"[..]));
+
+        let (_, complete) = backend.upload_files("ABC", "1234");
+        assert_eq!(fs::read(complete).unwrap(), b"01234567");
+
+        _ = fs::remove_dir_all(&backend.out);
+    }
+
+    #[test]
+    fn firmware_downloads_resume_if_the_image_is_unchanged() {
+        let mut backend = backend("firmware");
+        backend.firmware = backend.out.join("firmware.bin");
+        write_file(&backend.firmware, b"0123456789").unwrap();
+        let key = register(&backend);
+
+        let path = "/firmware/v6c6/ABC/0000000";
+        let download = |headers: &[(&str, &str)]| {
+            let reply = backend.handle(&request(&key, "GET", path, headers, &[]), None);
+            (reply.status, reply.body)
+        };
+
+        let resume = [("Range", "bytes=4-"), ("If-Range", "\"abcdef0\"")];
+        assert_eq!(download(&resume), (206, b"456789".to_vec()));
+
+        let changed = [("Range", "bytes=4-"), ("If-Range", "\"1234567\"")];
+        assert_eq!(download(&changed), (200, b"0123456789".to_vec()));
+
+        _ = fs::remove_dir_all(&backend.out);
+    }
+
+    #[test]
+    fn requests_must_be_signed_with_the_device_key() {
+        let backend = backend("sign");
+        let key = register(&backend);
+
+        let path = "/firmware/v6c6/ABC/abcdef0";
+        let reply = backend.handle(&request(&key, "GET", path, &[], &[]), None);
+        assert_eq!(reply.status, 304);
+
+        let reply = backend.handle(&request(&[0; 32], "GET", path, &[], &[]), None);
+        assert_eq!(reply.status, 401);
+
+        _ = fs::remove_dir_all(&backend.out);
+    }
+
+    #[test]
+    fn upload_headers_are_signed() {
+        let backend = backend("sign-headers");
+        let key = register(&backend);
+
+        let path = "/upload_data/ABC/1234";
+        let mut chunk = request(
+            &key,
+            "POST",
+            path,
+            &[("X-Upload-Offset", "0"), ("X-Upload-Length", "4")],
+            b"0123",
+        );
+        chunk.headers[1].1 = String::from("2");
+        let reply = backend.handle(&chunk, None);
+        assert_eq!(reply.status, 401);
+
+        _ = fs::remove_dir_all(&backend.out);
+    }
+
+    #[test]
+    fn registered_devices_must_sign_to_register_again() {
+        let backend = backend("register");
+        let key = register(&backend);
+
+        let reply = backend.handle(&request(&[], "POST", "/register/ABC", &[], &[]), None);
+        assert_eq!(reply.status, 401);
+
+        let reply = backend.handle(&request(&key, "POST", "/register/ABC", &[], &[]), None);
+        assert_eq!(reply.status, 201);
+        let new_key = unhex(std::str::from_utf8(&reply.body).unwrap()).unwrap();
+
+        let path = "/firmware/v6c6/ABC/abcdef0";
+        let reply = backend.handle(&request(&new_key, "GET", path, &[], &[]), None);
+        assert_eq!(reply.status, 304);
+
+        _ = fs::remove_dir_all(&backend.out);
+    }
+}
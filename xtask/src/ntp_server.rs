@@ -0,0 +1,111 @@
+//! A minimal SNTP server to test the firmware's time synchronization against.
+
+use std::{
+    net::UdpSocket,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context as _, Result as AnyResult};
+
+const PACKET_LEN: usize = 48;
+
+/// Seconds between the NTP epoch (1900-01-01) and the Unix epoch.
+const NTP_TO_UNIX_SECONDS: u64 = 2_208_988_800;
+
+pub fn serve(port: u16, offset: i64) -> AnyResult<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))
+        .with_context(|| format!("Failed to bind UDP port {port}"))?;
+
+    println!(
+        "🕒  Serving SNTP on port {port}, build the firmware with NTP_SERVER=<this host>:{port}"
+    );
+
+    let mut request = [0; 1024];
+    loop {
+        let (len, peer) = socket.recv_from(&mut request)?;
+        let received = now(offset);
+
+        let Some(response) = response(&request[..len], received, now(offset)) else {
+            println!("Ignoring invalid request from {peer}");
+            continue;
+        };
+
+        socket.send_to(&response, peer)?;
+        println!("Answered {peer}");
+    }
+}
+
+fn now(offset: i64) -> Duration {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    if offset >= 0 {
+        now + Duration::from_secs(offset as u64)
+    } else {
+        now - Duration::from_secs(offset.unsigned_abs())
+    }
+}
+
+/// Builds a server response. `received` and `transmitted` are durations since the Unix epoch.
+fn response(request: &[u8], received: Duration, transmitted: Duration) -> Option<[u8; PACKET_LEN]> {
+    let mode = request.first()? & 0x7;
+    if request.len() < PACKET_LEN || mode != 3 {
+        return None;
+    }
+
+    let version = (request[0] >> 3) & 0x7;
+
+    let mut response = [0; PACKET_LEN];
+    response[0] = (version << 3) | 4; // LI = 0, mode = server
+    response[1] = 1; // stratum: primary reference
+    response[2] = request[2]; // poll interval
+    response[3] = (-20i8) as u8; // precision: ~1µs
+    response[12..16].copy_from_slice(b"LOCL");
+    response[16..24].copy_from_slice(&ntp_timestamp(received));
+    response[24..32].copy_from_slice(&request[40..48]);
+    response[32..40].copy_from_slice(&ntp_timestamp(received));
+    response[40..48].copy_from_slice(&ntp_timestamp(transmitted));
+
+    Some(response)
+}
+
+fn ntp_timestamp(since_unix_epoch: Duration) -> [u8; 8] {
+    let seconds = (since_unix_epoch.as_secs() + NTP_TO_UNIX_SECONDS) as u32;
+    let fraction = ((since_unix_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+
+    let mut timestamp = [0; 8];
+    timestamp[..4].copy_from_slice(&seconds.to_be_bytes());
+    timestamp[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+    timestamp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn response_echoes_request_timestamp() {
+        let mut request = [0; PACKET_LEN];
+        request[0] = (4 << 3) | 3;
+        request[40..48].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let time = Duration::new(1_700_000_000, 500_000_000);
+        let response = response(&request, time, time).unwrap();
+
+        assert_eq!(response[0], (4 << 3) | 4);
+        assert_eq!(response[1], 1);
+        assert_eq!(&response[24..32], &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let seconds = u32::from_be_bytes(response[40..44].try_into().unwrap()) as u64;
+        let fraction = u32::from_be_bytes(response[44..48].try_into().unwrap());
+        assert_eq!(seconds - NTP_TO_UNIX_SECONDS, 1_700_000_000);
+        assert_eq!(fraction, 1 << 31);
+    }
+
+    #[test]
+    fn non_client_packets_are_ignored() {
+        let mut request = [0; PACKET_LEN];
+        request[0] = (4 << 3) | 4;
+
+        assert!(response(&request, Duration::ZERO, Duration::ZERO).is_none());
+        assert!(response(&request[..10], Duration::ZERO, Duration::ZERO).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+//! Signs firmware images for over-the-air updates.
+//!
+//! The firmware only installs updates signed with the key whose public half it was built with,
+//! see the `firmware-image` crate for the format.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result as AnyResult};
+use firmware_image::{SecretKey, SIGNATURE_LEN, TRAILER_LEN};
+use sha2::{Digest, Sha256};
+
+use crate::mock_backend::{hex, unhex};
+
+/// Where the firmware build looks for the public key.
+const PUBLIC_KEY_FILE: &str = "firmware-key.pub";
+
+pub struct SignOptions {
+    pub input: PathBuf,
+    pub key: PathBuf,
+    pub output: Option<PathBuf>,
+}
+
+pub fn generate_key(out: &Path) -> AnyResult<()> {
+    if out.exists() {
+        bail!("{} already exists, refusing to overwrite it", out.display());
+    }
+
+    let secret = duct::cmd!("openssl", "rand", "-hex", "32")
+        .read()
+        .context("Failed to run openssl")?;
+    let public_key = firmware_image::public_key(&parse_key(&secret)?);
+
+    fs::write(out, format!("{secret}\n"))
+        .with_context(|| format!("Failed to write {}", out.display()))?;
+    fs::write(PUBLIC_KEY_FILE, format!("{}\n", hex(&public_key)))
+        .with_context(|| format!("Failed to write {PUBLIC_KEY_FILE}"))?;
+
+    println!(
+        "🔑  Signing key written to {}, keep it secret",
+        out.display()
+    );
+    println!("Firmware built from now on only accepts updates signed with it.");
+
+    Ok(())
+}
+
+pub fn sign(options: SignOptions) -> AnyResult<()> {
+    let secret = fs::read_to_string(&options.key)
+        .with_context(|| format!("Failed to read {}", options.key.display()))?;
+    let secret = parse_key(&secret)?;
+
+    let image = fs::read(&options.input)
+        .with_context(|| format!("Failed to read {}", options.input.display()))?;
+
+    let output = options
+        .output
+        .unwrap_or_else(|| options.input.with_extension("signed.bin"));
+
+    let trailer = firmware_image::sign(&image, &secret);
+
+    let mut signed = image.clone();
+    signed.extend_from_slice(&trailer);
+    fs::write(&output, &signed).with_context(|| format!("Failed to write {}", output.display()))?;
+
+    let commit = duct::cmd!("git", "rev-parse", "--short", "HEAD")
+        .read()
+        .context("Failed to read the current commit")?;
+    let manifest_path = output.with_extension("json");
+    fs::write(&manifest_path, manifest(&output, &image, &trailer, &commit))
+        .with_context(|| format!("Failed to write {}", manifest_path.display()))?;
+
+    println!("✍️  Signed image written to {}", output.display());
+    println!("Manifest written to {}", manifest_path.display());
+
+    Ok(())
+}
+
+fn parse_key(hex: &str) -> AnyResult<SecretKey> {
+    unhex(hex.trim())
+        .and_then(|key| SecretKey::try_from(key).ok())
+        .context("The signing key must be 32 hex encoded bytes")
+}
+
+fn manifest(output: &Path, image: &[u8], trailer: &[u8; TRAILER_LEN], commit: &str) -> String {
+    let file = output.file_name().unwrap_or_default().to_string_lossy();
+    let signature = &trailer[4..4 + SIGNATURE_LEN];
+
+    format!(
+        concat!(
+            "{{\n",
+            "  \"file\": \"{}\",\n",
+            "  \"commit\": \"{}\",\n",
+            "  \"image_size\": {},\n",
+            "  \"signed_size\": {},\n",
+            "  \"sha256\": \"{}\",\n",
+            "  \"signature\": \"{}\"\n",
+            "}}\n"
+        ),
+        file,
+        commit,
+        image.len(),
+        image.len() + trailer.len(),
+        hex(&Sha256::digest(image)),
+        hex(signature),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use firmware_image::Verifier;
+
+    #[test]
+    fn signed_images_verify_with_the_generated_public_key() {
+        let secret = parse_key(&format!("{}\n", "2a".repeat(32))).unwrap();
+        let image = b"not really a firmware image".to_vec();
+
+        let mut verifier = Verifier::new();
+        verifier.update(&image);
+        verifier.update(&firmware_image::sign(&image, &secret));
+
+        assert_eq!(
+            verifier.verify(&firmware_image::public_key(&secret)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn manifest_describes_the_signed_image() {
+        let secret = [1; 32];
+        let image = vec![0; 100];
+        let trailer = firmware_image::sign(&image, &secret);
+
+        let manifest = manifest(
+            Path::new("target/card_io_fw.signed.bin"),
+            &image,
+            &trailer,
+            "abcdef0",
+        );
+
+        assert!(manifest.contains("\"file\": \"card_io_fw.signed.bin\""));
+        assert!(manifest.contains("\"image_size\": 100,"));
+        assert!(manifest.contains(&format!("\"signed_size\": {},", 100 + TRAILER_LEN)));
+        assert!(manifest.contains(&format!(
+            "\"signature\": \"{}\"",
+            hex(&trailer[4..4 + SIGNATURE_LEN])
+        )));
+    }
+}
@@ -0,0 +1,120 @@
+//! Generates a self-signed CA and a server certificate to test certificate verification against.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context as _, Result as AnyResult};
+
+pub fn generate(host: &str, out: &Path) -> AnyResult<()> {
+    fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+
+    let path = |name: &str| out.join(name).to_string_lossy().into_owned();
+    let (ca_key, ca_cert) = (path("ca.key"), path("ca.pem"));
+    let (server_key, server_csr, server_cert) =
+        (path("server.key"), path("server.csr"), path("server.pem"));
+    let extensions = path("server.ext");
+
+    // The firmware only verifies ECDSA P-256 signatures made directly by the CA, so the server
+    // certificate is issued without an intermediate.
+    let genkey = |out: &str| {
+        openssl(&[
+            "ecparam",
+            "-name",
+            "prime256v1",
+            "-genkey",
+            "-noout",
+            "-out",
+            out,
+        ])
+    };
+
+    genkey(&ca_key)?;
+    openssl(&[
+        "req",
+        "-new",
+        "-x509",
+        "-key",
+        &ca_key,
+        "-out",
+        &ca_cert,
+        "-days",
+        "365",
+        "-subj",
+        "/CN=Card-IO test CA",
+    ])?;
+
+    genkey(&server_key)?;
+    let subject = format!("/CN={host}");
+    openssl(&[
+        "req",
+        "-new",
+        "-key",
+        &server_key,
+        "-out",
+        &server_csr,
+        "-subj",
+        &subject,
+    ])?;
+
+    fs::write(&extensions, subject_alt_name(host))?;
+    openssl(&[
+        "x509",
+        "-req",
+        "-in",
+        &server_csr,
+        "-CA",
+        &ca_cert,
+        "-CAkey",
+        &ca_key,
+        "-CAcreateserial",
+        "-out",
+        &server_cert,
+        "-days",
+        "365",
+        "-extfile",
+        &extensions,
+    ])?;
+
+    println!("🔐  Certificates written to {}", out.display());
+    println!(
+        "Set the contents of {ca_cert} as the server certificate on the config site, then run"
+    );
+    println!(
+        "    openssl s_server -accept 8443 -cert {server_cert} -key {server_key} -tls1_3 -www"
+    );
+    println!("and set the backend URL to https://{host}:8443");
+
+    Ok(())
+}
+
+fn openssl(args: &[&str]) -> AnyResult<()> {
+    duct::cmd("openssl", args)
+        .run()
+        .context("Failed to run openssl")?;
+    Ok(())
+}
+
+fn subject_alt_name(host: &str) -> String {
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        format!("subjectAltName = IP:{host}, DNS:{host}\n")
+    } else {
+        format!("subjectAltName = DNS:{host}\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ip_addresses_are_also_listed_as_dns_names() {
+        // The firmware matches the host of the backend URL against DNS names.
+        assert_eq!(
+            subject_alt_name("192.168.1.2"),
+            "subjectAltName = IP:192.168.1.2, DNS:192.168.1.2\n"
+        );
+        assert_eq!(
+            subject_alt_name("backend.local"),
+            "subjectAltName = DNS:backend.local\n"
+        );
+    }
+}